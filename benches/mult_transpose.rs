@@ -0,0 +1,37 @@
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use fast_cx_circs::CXCircuit16;
+use rand::{rngs::StdRng, SeedableRng};
+
+fn mult_transpose(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let pairs: Vec<_> = (0..100)
+        .map(|_| {
+            (
+                CXCircuit16::random_invertible(&mut rng),
+                CXCircuit16::random_invertible(&mut rng),
+            )
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("mult_transpose");
+    group.bench_function("scalar", |b| {
+        b.iter(|| {
+            for (x, y) in &pairs {
+                black_box(x.mult_transpose_scalar(y));
+            }
+        })
+    });
+    group.bench_function("simd", |b| {
+        b.iter(|| {
+            for (x, y) in &pairs {
+                black_box(x.mult_transpose_simd(y));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, mult_transpose);
+criterion_main!(benches);
@@ -0,0 +1,21 @@
+//! Record the git commit the crate is built from, for benchmark reports.
+
+use std::{fs, process::Command};
+
+fn main() {
+    // Rebuild when a new commit is checked out or made on the current branch
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/packed-refs");
+    if let Some(head_ref) = fs::read_to_string(".git/HEAD")
+        .ok()
+        .and_then(|head| Some(head.strip_prefix("ref: ")?.trim().to_string()))
+    {
+        println!("cargo:rerun-if-changed=.git/{head_ref}");
+    }
+
+    let output = Command::new("git").args(["rev-parse", "HEAD"]).output();
+    if let Some(output) = output.ok().filter(|output| output.status.success()) {
+        let hash = String::from_utf8_lossy(&output.stdout);
+        println!("cargo:rustc-env=GIT_COMMIT_HASH={}", hash.trim());
+    }
+}
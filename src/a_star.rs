@@ -1,10 +1,21 @@
+pub mod bidirectional;
 mod expand_children;
 mod graph;
+mod interner;
+pub mod parallel;
+pub mod pattern_database;
 
 use fxhash::FxHashSet;
 use priority_queue::PriorityQueue;
-use std::{cmp::Reverse, hash::Hash};
+use std::{
+    cmp::{Ordering, Reverse},
+    fs,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
+pub use graph::FocusMode;
 use graph::{ANodeInd, AStarGraph};
 
 use crate::CX;
@@ -19,14 +30,77 @@ where
     /// A* will find the shortest path.
     fn dist(&self, other: &Self) -> usize;
 
+    /// The number of qubits the value acts on
+    fn n_qubits(&self) -> usize;
+
     /// Whether the target was reached on the given qubit
     fn is_complete(&self, qb: u8, target: &Self) -> bool;
 
+    /// An approximate distance to the target on the given qubit.
+    ///
+    /// Must be zero if and only if the qubit is complete.
+    fn qubit_dist(&self, qb: u8, target: &Self) -> usize {
+        !self.is_complete(qb, target) as usize
+    }
+
     /// Apply a CX gate to the current value
     fn cx(&self, ctrl: u8, tgt: u8) -> Self;
 
+    /// Apply a SWAP gate to the current value, as three CXs by default
+    fn swap(&self, q1: u8, q2: u8) -> Self {
+        self.cx(q1, q2).cx(q2, q1).cx(q1, q2)
+    }
+
     /// Merge two values
     fn merge(&self, other: &Self, used_qubits: &FxHashSet<u8>) -> Self;
+
+    /// Merge two values, given the values before and after each gate that
+    /// leads to `other` but not to `self`.
+    ///
+    /// Defaults to [`Self::merge`]. Override it if the effect of the gates is
+    /// not captured by the `used_qubits` of `other`.
+    fn merge_with_gates(
+        &self,
+        other: &Self,
+        used_qubits: &FxHashSet<u8>,
+        _gates: &[(&Self, &Self)],
+    ) -> Self {
+        self.merge(other, used_qubits)
+    }
+
+    /// A solution from `self` to `target`, if one can be computed cheaply.
+    ///
+    /// Its length is used as an initial upper bound to prune the search.
+    fn upper_bound(&self, _target: &Self, _allowed_moves: &FxHashSet<CX>) -> Option<Vec<CX>> {
+        None
+    }
+
+    /// A secondary cost, used to break ties between nodes of equal priority.
+    ///
+    /// Nodes with a lower secondary cost are expanded first.
+    fn secondary_cost(&self, _target: &Self) -> usize {
+        0
+    }
+}
+
+/// Statistics about an A* search, for benchmarking.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+pub struct SearchStats {
+    /// The number of nodes expanded
+    pub expansions: usize,
+    /// The number of nodes in the search graph
+    pub node_count: usize,
+    /// The number of edges in the search graph
+    pub edge_count: usize,
+    /// The number of merge nodes in the search graph
+    pub merge_count: usize,
+    /// The maximum cost of any node in the search graph
+    pub max_depth: usize,
+    /// The average cost of the nodes in the search graph
+    pub avg_depth: f64,
+    /// The estimated number of bytes freed by garbage collecting the graph
+    pub freed_bytes: usize,
 }
 
 type PQ = PriorityQueue<usize, PQCost>;
@@ -35,80 +109,646 @@ type PQ = PriorityQueue<usize, PQCost>;
 /// We want
 ///  i) low estimated total cost
 /// ii) break ties using highest cost already reached
+/// iii) then break ties using low secondary cost
 #[derive(Hash, Eq, PartialEq, Clone, PartialOrd, Ord, Debug)]
-struct PQCost(Reverse<usize>, usize);
+struct PQCost(Reverse<EstimatedCost>, usize, Reverse<usize>);
 impl PQCost {
-    fn new(cost: usize, gates: usize) -> Self {
-        PQCost(Reverse(cost), gates)
+    fn new(cost: f64, gates: usize, secondary_cost: usize) -> Self {
+        PQCost(Reverse(EstimatedCost(cost)), gates, Reverse(secondary_cost))
     }
 
-    fn cost(&self) -> usize {
-        self.0 .0
+    fn cost(&self) -> f64 {
+        self.0 .0 .0
+    }
+}
+
+/// An estimated total cost, which may be fractional for weighted A*.
+///
+/// Totally ordered using [`f64::total_cmp`].
+#[derive(Clone, Copy, Debug)]
+struct EstimatedCost(f64);
+
+impl PartialEq for EstimatedCost {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
     }
 }
 
+impl Eq for EstimatedCost {}
+
+impl PartialOrd for EstimatedCost {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for EstimatedCost {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl Hash for EstimatedCost {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+/// Find a shortest sequence of `allowed_moves` that maps `start` to `target`.
 pub fn a_star<V: AStarValue>(
     start: V,
     target: &V,
     allowed_moves: impl IntoIterator<Item = CX>,
     max_depth: Option<usize>,
+    max_expansions: Option<usize>,
 ) -> Option<Vec<CX>> {
-    let mut graph = AStarGraph::new(start, allowed_moves);
+    a_star_search(
+        start,
+        target,
+        allowed_moves,
+        max_depth,
+        max_expansions,
+        false,
+        1.,
+        None,
+    )
+    .0
+    .into_iter()
+    .next()
+}
 
-    let mut pq = PQ::new();
-    pq.push(graph.root_ind(), PQCost::new(graph.root().dist(target), 0));
+/// Find a sequence of `allowed_moves` that maps `start` to `target`, at most
+/// `epsilon` times longer than the shortest one.
+///
+/// Nodes are prioritised by their cost plus `epsilon` times the heuristic.
+/// With `epsilon = 1.` this is [`a_star`], larger values trade optimality for
+/// speed.
+pub fn weighted_a_star<V: AStarValue>(
+    start: V,
+    target: &V,
+    allowed_moves: impl IntoIterator<Item = CX>,
+    max_depth: Option<usize>,
+    epsilon: f64,
+) -> Option<Vec<CX>> {
+    a_star_search(
+        start,
+        target,
+        allowed_moves,
+        max_depth,
+        None,
+        false,
+        epsilon,
+        None,
+    )
+    .0
+    .into_iter()
+    .next()
+}
+
+/// Find all shortest sequences of `allowed_moves` that map `start` to `target`.
+///
+/// Unlike [`a_star`], the search does not stop at the first optimal solution
+/// but keeps going until all nodes that could lead to an optimal solution have
+/// been explored. The gates on both branches of a merge are interleaved in
+/// every order.
+pub fn a_star_all<V: AStarValue>(
+    start: V,
+    target: &V,
+    allowed_moves: impl IntoIterator<Item = CX>,
+    max_depth: Option<usize>,
+    max_expansions: Option<usize>,
+) -> Vec<Vec<CX>> {
+    a_star_search(
+        start,
+        target,
+        allowed_moves,
+        max_depth,
+        max_expansions,
+        true,
+        1.,
+        None,
+    )
+    .0
+}
 
-    // The solution of the current best solution
-    let mut min_solution: Option<Vec<_>> = None;
+/// Statistics about a time-limited A* search, see [`a_star_timed`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AStarStats {
+    /// The number of nodes expanded
+    pub nodes_expanded: usize,
+    /// The number of nodes in the search graph
+    pub nodes_created: usize,
+    /// The duration of the search
+    pub elapsed: Duration,
+    /// The CX count of the best solution found, if any
+    pub best_cost_found: Option<usize>,
+    /// The estimated number of bytes freed by garbage collecting the graph
+    pub freed_bytes: usize,
+}
 
-    // For progress reporting purposes
-    let mut max_cost: Option<usize> = None;
+/// Find a shortest sequence of `allowed_moves` that maps `start` to `target`,
+/// giving up after `timeout`.
+///
+/// If the search times out, the best solution found so far is returned. It
+/// may not be optimal, and there may be none.
+pub fn a_star_timed<V: AStarValue>(
+    start: V,
+    target: &V,
+    allowed_moves: impl IntoIterator<Item = CX>,
+    max_depth: Option<usize>,
+    timeout: Option<Duration>,
+) -> (Option<Vec<CX>>, AStarStats) {
+    let start_time = Instant::now();
+    let (solutions, search_stats) = a_star_search(
+        start,
+        target,
+        allowed_moves,
+        max_depth,
+        None,
+        false,
+        1.,
+        timeout,
+    );
+    let solution = solutions.into_iter().next();
+    let stats = AStarStats {
+        nodes_expanded: search_stats.expansions,
+        nodes_created: search_stats.node_count,
+        elapsed: start_time.elapsed(),
+        best_cost_found: solution.as_ref().map(|solution| solution.len()),
+        freed_bytes: search_stats.freed_bytes,
+    };
+    (solution, stats)
+}
 
-    loop {
-        let (ind, prio) = pq.pop().expect("Ran out of circuits to explore?!");
-        if max_cost.is_none() || graph.cost(ind) > max_cost.unwrap() {
-            max_cost = Some(graph.cost(ind));
-            println!("Max cost explored: {}", max_cost.unwrap());
-            if max_depth.is_some() && max_cost > max_depth {
-                println!("Max depth reached, aborting");
-                break;
+/// Run the A* search, returning the solutions found and search statistics.
+///
+/// If `all_solutions` is set, behaves as [`a_star_all`], otherwise as
+/// [`a_star`]. The heuristic is weighted by `epsilon`, see
+/// [`weighted_a_star`]. The search is aborted once `timeout` has elapsed,
+/// see [`a_star_timed`]. See [`AStarBuilder`] for more options.
+#[allow(clippy::too_many_arguments)]
+pub fn a_star_search<V: AStarValue>(
+    start: V,
+    target: &V,
+    allowed_moves: impl IntoIterator<Item = CX>,
+    max_depth: Option<usize>,
+    max_expansions: Option<usize>,
+    all_solutions: bool,
+    epsilon: f64,
+    timeout: Option<Duration>,
+) -> (Vec<Vec<CX>>, SearchStats) {
+    AStarBuilder::new(start, target, allowed_moves)
+        .max_depth(max_depth)
+        .max_expansions(max_expansions)
+        .all_solutions(all_solutions)
+        .epsilon(epsilon)
+        .timeout(timeout)
+        .run()
+}
+
+/// The progress of an A* search, see [`AStarBuilder::on_progress`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct AStarProgress {
+    /// The number of nodes expanded so far
+    pub nodes_expanded: usize,
+    /// The CX count of the best solution found so far, if any
+    pub current_best_cost: Option<usize>,
+    /// The largest cost of the nodes expanded so far
+    pub max_cost_explored: usize,
+    /// The time since the search started
+    pub elapsed: Duration,
+}
+
+/// Whether `cost` is larger than `bound`, up to rounding errors in sums of
+/// move costs.
+fn exceeds(cost: f64, bound: f64) -> bool {
+    cost > bound + 1e-9
+}
+
+/// A configurable A* search from `start` to `target`.
+///
+/// Without further configuration, [`AStarBuilder::run`] behaves as
+/// [`a_star`] without limits.
+pub struct AStarBuilder<'a, V> {
+    start: V,
+    target: &'a V,
+    allowed_moves: Vec<CX>,
+    move_costs: Vec<(CX, f64)>,
+    swap_cost: Option<f64>,
+    focus_mode: FocusMode,
+    max_depth: Option<usize>,
+    max_expansions: Option<usize>,
+    all_solutions: bool,
+    epsilon: f64,
+    timeout: Option<Duration>,
+    on_progress: Option<ProgressCallback<'a>>,
+    dump_graph: Option<PathBuf>,
+    heuristic: Option<Heuristic<'a, V>>,
+}
+
+type ProgressCallback<'a> = Box<dyn Fn(&AStarProgress) + 'a>;
+type Heuristic<'a, V> = Box<dyn Fn(&V, &V) -> usize + 'a>;
+
+impl<'a, V: AStarValue> AStarBuilder<'a, V> {
+    pub fn new(start: V, target: &'a V, allowed_moves: impl IntoIterator<Item = CX>) -> Self {
+        Self {
+            start,
+            target,
+            allowed_moves: allowed_moves.into_iter().collect(),
+            move_costs: Vec::new(),
+            swap_cost: None,
+            focus_mode: FocusMode::AllMoves,
+            max_depth: None,
+            max_expansions: None,
+            all_solutions: false,
+            epsilon: 1.,
+            timeout: None,
+            on_progress: None,
+            dump_graph: None,
+            heuristic: None,
+        }
+    }
+
+    /// Set the cost of each move, for moves that do not have unit cost.
+    ///
+    /// The search then minimises the total cost of the moves rather than the
+    /// number of CXs. Costs must be positive.
+    pub fn move_costs(mut self, move_costs: impl IntoIterator<Item = (CX, f64)>) -> Self {
+        self.move_costs.extend(move_costs);
+        self
+    }
+
+    /// Also allow SWAP gates of cost `cost`, on the qubit pairs with allowed
+    /// CXs in both directions.
+    ///
+    /// A SWAP is a single move of the search, so that qubits can be routed
+    /// across the topology in fewer expansions. Solutions still list it as
+    /// three CXs. Use a cost of 3 to keep minimising the number of CXs.
+    pub fn allow_swaps(mut self, cost: f64) -> Self {
+        self.swap_cost = Some(cost);
+        self
+    }
+
+    /// Restrict the moves considered when expanding a node, see
+    /// [`FocusMode`].
+    pub fn focus_mode(mut self, focus_mode: FocusMode) -> Self {
+        self.focus_mode = focus_mode;
+        self
+    }
+
+    /// Abort the search beyond `max_depth` CXs.
+    pub fn max_depth(mut self, max_depth: impl Into<Option<usize>>) -> Self {
+        self.max_depth = max_depth.into();
+        self
+    }
+
+    /// Abort the search after expanding `max_expansions` nodes.
+    pub fn max_expansions(mut self, max_expansions: impl Into<Option<usize>>) -> Self {
+        self.max_expansions = max_expansions.into();
+        self
+    }
+
+    /// Find all shortest solutions, see [`a_star_all`].
+    pub fn all_solutions(mut self, all_solutions: bool) -> Self {
+        self.all_solutions = all_solutions;
+        self
+    }
+
+    /// Weight the heuristic by `epsilon`, see [`weighted_a_star`].
+    ///
+    /// Panics when run if `epsilon` is less than 1.
+    pub fn epsilon(mut self, epsilon: f64) -> Self {
+        self.epsilon = epsilon;
+        self
+    }
+
+    /// Abort the search once `timeout` has elapsed, see [`a_star_timed`].
+    pub fn timeout(mut self, timeout: impl Into<Option<Duration>>) -> Self {
+        self.timeout = timeout.into();
+        self
+    }
+
+    /// Call `on_progress` every time the search reaches a higher cost.
+    pub fn on_progress(mut self, on_progress: impl Fn(&AStarProgress) + 'a) -> Self {
+        self.on_progress = Some(Box::new(on_progress));
+        self
+    }
+
+    /// Write the search graph to `path` in Graphviz DOT format once the
+    /// search completes.
+    ///
+    /// Nodes that were garbage collected after a solution was found are not
+    /// included.
+    pub fn dump_graph(mut self, path: impl Into<PathBuf>) -> Self {
+        self.dump_graph = Some(path.into());
+        self
+    }
+
+    /// Estimate the number of CXs from a value to the target with
+    /// `heuristic(value, target)` instead of [`AStarValue::dist`], e.g. with
+    /// [`pattern_database::max_lower_bound`].
+    ///
+    /// Solutions are only optimal if the estimate is a lower bound.
+    pub fn heuristic(mut self, heuristic: impl Fn(&V, &V) -> usize + 'a) -> Self {
+        self.heuristic = Some(Box::new(heuristic));
+        self
+    }
+
+    /// Run the search, returning the solutions found and search statistics.
+    pub fn run(self) -> (Vec<Vec<CX>>, SearchStats) {
+        let dump_graph = self.dump_graph.clone();
+        let (solutions, stats, graph, best) = self.search();
+        if let Some(path) = dump_graph {
+            let dot = graph.to_dot(&best.map(|ind| graph.ancestors(ind)).unwrap_or_default());
+            if let Err(err) = fs::write(&path, dot) {
+                println!("Could not write search graph to {}: {err}", path.display());
             }
         }
-        if let Some(min_solution) = min_solution.as_ref() {
-            if prio.cost() > min_solution.len() {
+        (solutions, stats)
+    }
+
+    /// Run the search as [`AStarBuilder::run`], also returning the search
+    /// graph in Graphviz DOT format, as written by [`AStarBuilder::dump_graph`].
+    ///
+    /// The nodes on the path to the best solution are highlighted.
+    pub fn run_with_dot(self) -> (Vec<Vec<CX>>, SearchStats, String) {
+        let (solutions, stats, graph, best) = self.search();
+        let dot = graph.to_dot(&best.map(|ind| graph.ancestors(ind)).unwrap_or_default());
+        (solutions, stats, dot)
+    }
+
+    /// Run the search, also returning the search graph and the node of the
+    /// best solution found in it, if any.
+    fn search(self) -> (Vec<Vec<CX>>, SearchStats, AStarGraph<V>, Option<ANodeInd>) {
+        let Self {
+            start,
+            target,
+            allowed_moves,
+            move_costs,
+            swap_cost,
+            focus_mode,
+            max_depth,
+            max_expansions,
+            all_solutions,
+            epsilon,
+            timeout,
+            on_progress,
+            dump_graph: _,
+            heuristic,
+        } = self;
+        assert!(epsilon >= 1., "epsilon must be at least 1");
+        let start_time = Instant::now();
+        let deadline = timeout.map(|timeout| start_time + timeout);
+        let mut graph = AStarGraph::new(start, allowed_moves);
+        graph.set_move_costs(move_costs);
+        if let Some(swap_cost) = swap_cost {
+            graph.allow_swaps(swap_cost);
+        }
+        graph.focus_mode = focus_mode;
+        if let Some(max_expansions) = max_expansions {
+            graph.node_expansion_limit(max_expansions);
+        }
+        if all_solutions {
+            // Record every path to the target, not just the first one
+            graph.keep_duplicates(target.clone());
+        }
+        // The heuristic counts CXs, so scale it to remain a lower bound on the
+        // cost of the remaining moves
+        let heuristic_weight = epsilon * graph.min_move_cost();
+        let solution_cost = |graph: &AStarGraph<V>, solution: &[CX]| -> f64 {
+            solution.iter().map(|cx| graph.move_cost(cx)).sum()
+        };
+        let dist = |value: &V| match &heuristic {
+            Some(heuristic) => heuristic(value, target),
+            None => value.dist(target),
+        };
+
+        let mut pq = PQ::new();
+        let root = graph.root();
+        pq.push(
+            graph.root_ind(),
+            PQCost::new(
+                heuristic_weight * dist(root) as f64,
+                0,
+                root.secondary_cost(target),
+            ),
+        );
+
+        // The solutions of minimal cost found so far, and their cost
+        let mut solutions: Vec<Vec<CX>> = Vec::new();
+        let mut best_cost: Option<f64> = None;
+
+        // Start from a cheap solution, if there is one, to prune the search
+        if graph.root() == target {
+            solutions.push(Vec::new());
+            best_cost = Some(0.);
+        } else if let Some(upper_bound) = graph.root().upper_bound(target, &graph.allowed_moves) {
+            println!("Initial upper bound: {} CXs", upper_bound.len());
+            best_cost = Some(solution_cost(&graph, &upper_bound));
+            solutions.push(upper_bound);
+        }
+
+        // For progress reporting purposes
+        let mut max_cost: Option<usize> = None;
+        let mut freed_bytes = 0;
+        // The node of the best solution, unless it is the initial upper bound
+        let mut best_node = None;
+
+        while let Some((ind, prio)) = pq.pop() {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                println!("Timeout reached, aborting");
+                break;
+            }
+            if max_cost.is_none() || graph.cost(ind) > max_cost.unwrap() {
+                max_cost = Some(graph.cost(ind));
+                println!("Max cost explored: {}", max_cost.unwrap());
+                if let Some(on_progress) = &on_progress {
+                    on_progress(&AStarProgress {
+                        nodes_expanded: graph.expansions,
+                        current_best_cost: solutions.first().map(Vec::len),
+                        max_cost_explored: max_cost.unwrap(),
+                        elapsed: start_time.elapsed(),
+                    });
+                }
+                if max_depth.is_some() && max_cost > max_depth {
+                    println!("Max depth reached, aborting");
+                    break;
+                }
+            }
+            if best_cost.is_some_and(|best| exceeds(prio.cost(), best)) {
                 // No further solution will be cheaper, so we are done
                 println!("Found solution is optimal. Terminating");
                 break;
             }
-        }
-        let value = graph.value(ind).unwrap().clone();
-        graph.expand_children(ind, |qb| value.is_complete(qb, target));
-        for new_child in graph.children(ind) {
-            if graph.value(new_child) == Some(target) {
-                let new_solution = graph.path(new_child);
-                match min_solution {
-                    Some(sol) if new_solution.len() < sol.len() => {
-                        min_solution = Some(new_solution);
-                        println!("New best solution: {:?}", min_solution.as_ref().unwrap());
+            if graph.expansion_limit_reached() {
+                println!("Max expansions reached, aborting");
+                break;
+            }
+            if !graph.arc_consistency_check(ind, target) {
+                // The target can never be reached from this node
+                continue;
+            }
+            if graph.value(ind) == Some(target) {
+                // Further moves only lead to longer solutions
+                continue;
+            }
+            graph.expand_children(ind, target);
+            graph.expansions += 1;
+            // The node of a new best solution, if one is found
+            let mut new_best = None;
+            for new_child in graph.children(ind) {
+                let path_cost = graph.path_cost(new_child);
+                if graph.value(new_child) == Some(target) {
+                    let new_solution = graph.path(new_child);
+                    let is_best = best_cost.is_none_or(|best| exceeds(best, path_cost));
+                    let is_as_good = best_cost.is_some_and(|best| !exceeds(path_cost, best));
+                    if is_best {
+                        println!("New best solution: {:?}", new_solution);
+                        solutions = vec![new_solution];
+                        best_cost = Some(path_cost);
+                        new_best = Some(new_child);
+                    } else if is_as_good && best_node.is_none() {
+                        // The initial upper bound is as good, but record the
+                        // node that reaches it
+                        best_node = Some(new_child);
                     }
-                    None => {
-                        min_solution = Some(new_solution);
-                        println!("New best solution: {:?}", min_solution.as_ref().unwrap());
+                    if all_solutions && (is_best || is_as_good) {
+                        // The branches of merges can be interleaved in any
+                        // order
+                        for solution in graph.get_all_solutions(new_child) {
+                            if !solutions.contains(&solution) {
+                                println!("New solution: {:?}", solution);
+                                solutions.push(solution);
+                            }
+                        }
                     }
-                    _ => {}
                 }
+                let new_value = graph.value(new_child).unwrap();
+                let dist = dist(new_value);
+                if best_cost.is_some_and(|best| {
+                    exceeds(path_cost + graph.min_move_cost() * dist as f64, best)
+                }) {
+                    // Cannot lead to a solution that is as good, prune
+                    continue;
+                }
+                let weighted_estimate = path_cost + heuristic_weight * dist as f64;
+                let secondary_cost = new_value.secondary_cost(target);
+                pq.push(
+                    new_child,
+                    PQCost::new(weighted_estimate, graph.cost(new_child), secondary_cost),
+                );
+            }
+            if let Some(solution_ind) = new_best {
+                best_node = Some(solution_ind);
+                // Only the nodes that may still lead to a solution that is as
+                // good are needed, along with their ancestors
+                let best = best_cost.expect("a solution was found");
+                pq.retain(|_, prio| !exceeds(prio.cost(), best));
+                let keep = pq.iter().map(|(&ind, _)| ind).chain([solution_ind]);
+                freed_bytes += graph.gc(&keep.collect::<Vec<_>>());
+            }
+        }
+        debug_assert_eq!(graph.check_consistency(), Ok(()));
+        let stats = SearchStats {
+            expansions: graph.expansions,
+            node_count: graph.node_count(),
+            edge_count: graph.edge_count(),
+            merge_count: graph.merge_count(),
+            max_depth: graph.max_depth(),
+            avg_depth: graph.avg_depth(),
+            freed_bytes,
+        };
+        (solutions, stats, graph, best_node)
+    }
+}
+
+/// Find a shortest sequence of `allowed_moves` that maps `start` to `target`
+/// using iterative deepening A* (IDA*).
+///
+/// Each iteration is a depth-first search that prunes nodes whose estimated
+/// total cost exceeds a threshold. The threshold starts at `start.dist(target)`
+/// and is raised to the smallest pruned cost after every iteration. Unlike
+/// [`a_star`], memory use is linear in the depth, at the price of revisiting
+/// nodes.
+pub fn ida_star<V: AStarValue>(
+    start: V,
+    target: &V,
+    allowed_moves: impl IntoIterator<Item = CX>,
+    max_depth: Option<usize>,
+) -> Option<Vec<CX>> {
+    let moves: Vec<CX> = allowed_moves.into_iter().collect();
+    let mut threshold = start.dist(target);
+    let mut path = Vec::new();
+    let mut visited = vec![start];
+    loop {
+        if max_depth.is_some_and(|max_depth| threshold > max_depth) {
+            println!("Max depth reached, aborting");
+            return None;
+        }
+        println!("Max cost explored: {threshold}");
+        match ida_search(target, &moves, threshold, &mut path, &mut visited) {
+            Ok(()) => return Some(path),
+            Err(Some(next_threshold)) => threshold = next_threshold,
+            // No node was pruned, so the target is unreachable
+            Err(None) => return None,
+        }
+    }
+}
+
+/// A depth-first search from the last value of `visited`, bounded by
+/// `threshold`.
+///
+/// On success, `path` holds the moves to the target. Otherwise, returns the
+/// smallest estimated cost that exceeded `threshold`, if any.
+fn ida_search<V: AStarValue>(
+    target: &V,
+    moves: &[CX],
+    threshold: usize,
+    path: &mut Vec<CX>,
+    visited: &mut Vec<V>,
+) -> Result<(), Option<usize>> {
+    let node = visited
+        .last()
+        .expect("visited contains the start value")
+        .clone();
+    let cost_estimate = path.len() + node.dist(target);
+    if cost_estimate > threshold {
+        return Err(Some(cost_estimate));
+    }
+    if node == *target {
+        return Ok(());
+    }
+    let mut min_exceeded: Option<usize> = None;
+    for &mv in moves {
+        let child = node.cx(mv.ctrl, mv.tgt);
+        // Do not go round in circles
+        if visited.contains(&child) {
+            continue;
+        }
+        visited.push(child);
+        path.push(mv);
+        match ida_search(target, moves, threshold, path, visited) {
+            Ok(()) => return Ok(()),
+            Err(Some(exceeded)) => {
+                min_exceeded = Some(min_exceeded.map_or(exceeded, |min| min.min(exceeded)));
             }
-            let mut cost_estimate = graph.cost(new_child);
-            cost_estimate += graph.value(new_child).unwrap().dist(target);
-            pq.push(new_child, PQCost::new(cost_estimate, graph.cost(new_child)));
+            Err(None) => {}
         }
+        path.pop();
+        visited.pop();
     }
-    min_solution
+    Err(min_exceeded)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::cx_circuit::{CXCircuit, CXCircuit16};
+    use itertools::{iproduct, Itertools};
+
+    use crate::{
+        cx_circuit::{CXCircuit, CXCircuit16},
+        topology::Topology,
+        CXList,
+    };
 
     use super::*;
 
@@ -135,6 +775,10 @@ mod tests {
             new
         }
 
+        fn n_qubits(&self) -> usize {
+            5
+        }
+
         fn is_complete(&self, qb: u8, target: &Self) -> bool {
             self[qb as usize] == target[qb as usize]
         }
@@ -146,10 +790,211 @@ mod tests {
         circuit.add_cx(0, 9);
         circuit.add_cx(0, 10);
         let moves = vec![CX { ctrl: 0, tgt: 9 }, CX { ctrl: 0, tgt: 10 }];
-        let result = a_star(CXCircuit16::new(), &circuit, moves, Some(2)).unwrap();
+        let result = a_star(CXCircuit16::new(), &circuit, moves, Some(2), None).unwrap();
         assert_eq!(result.len(), 2);
     }
 
+    #[test]
+    fn test_merges_with_shared_history() {
+        // Found through merges of nodes with a common history, which must
+        // only be counted once
+        let moves = Topology::linear(8).to_cx_moves();
+        let cxs = [
+            (5, 4),
+            (5, 6),
+            (3, 2),
+            (4, 3),
+            (4, 5),
+            (6, 7),
+            (2, 1),
+            (3, 4),
+        ];
+        let target = CXCircuit16::from_cxs(cxs);
+        let (solutions, stats) = AStarBuilder::new(CXCircuit16::new(), &target, moves)
+            .max_depth(12)
+            .run();
+        assert_eq!(solutions[0].len(), 8);
+        assert!(stats.merge_count > 0);
+    }
+
+    #[test]
+    fn test_weighted_moves() {
+        let moves = Topology::ring(4).to_cx_moves();
+        let target = CXCircuit16::from_cxs([(0, 1)]);
+        let (solutions, _) = AStarBuilder::new(CXCircuit16::new(), &target, moves.clone()).run();
+        assert_eq!(solutions, [[CX { ctrl: 0, tgt: 1 }]]);
+
+        // Going the other way around the ring takes more CXs, but is cheaper
+        let expensive = [(0, 1), (1, 0)].map(|cx| (CX::from(cx), 10.));
+        let (solutions, _) = AStarBuilder::new(CXCircuit16::new(), &target, moves)
+            .move_costs(expensive)
+            .run();
+        let solution = &solutions[0];
+        assert!(solution.len() > 1 && solution.len() < 10);
+        assert!(!solution
+            .iter()
+            .any(|cx| expensive.iter().any(|(e, _)| e == cx)));
+        let result = solution
+            .iter()
+            .fold(CXCircuit16::new(), |circ, cx| circ.cx(cx.ctrl, cx.tgt));
+        assert_eq!(result, target);
+    }
+
+    #[test]
+    fn test_most_constrained_focus() {
+        let moves = Topology::linear(6).to_cx_moves();
+        // Only qubit 1 differs from the identity
+        let target = CXCircuit16::from_cxs([(0, 1), (2, 1)]);
+        let (solutions, stats) = AStarBuilder::new(CXCircuit16::new(), &target, moves.clone())
+            .focus_mode(FocusMode::MostConstrained)
+            .run();
+        let (_, all_moves_stats) = AStarBuilder::new(CXCircuit16::new(), &target, moves).run();
+        assert_eq!(CXList::from(solutions[0].clone()).to_cx_circuit(), target);
+        assert_eq!(solutions[0].len(), 2);
+        assert!(stats.node_count < all_moves_stats.node_count);
+    }
+
+    #[test]
+    fn test_swap_moves() {
+        let moves = Topology::linear(4).to_cx_moves();
+        let target = CXCircuit16::new().row_swap(0, 1).row_swap(2, 3);
+        // The SWAP of a circuit agrees with its default of three CXs
+        let swap_01 = CXCircuit16::new().cx(0, 1).cx(1, 0).cx(0, 1);
+        assert_eq!(swap_01.row_swap(2, 3), target);
+        assert_eq!(CXCircuit16::new().swap(0, 1).swap(2, 3), target);
+
+        let (cx_solutions, cx_stats) =
+            AStarBuilder::new(CXCircuit16::new(), &target, moves.clone()).run();
+        assert_eq!(cx_solutions[0].len(), 6);
+        for swap_cost in [3., 1.5] {
+            let (solutions, stats) = AStarBuilder::new(CXCircuit16::new(), &target, moves.clone())
+                .allow_swaps(swap_cost)
+                .run();
+            // The SWAPs are written as CXs
+            assert_eq!(solutions[0].len(), 6);
+            assert!(solutions[0].iter().all(|cx| moves.contains(cx)));
+            assert_eq!(CXList::from(solutions[0].clone()).to_cx_circuit(), target);
+            // Paths through SWAPs have fewer moves
+            assert!(stats.max_depth < cx_stats.max_depth);
+            // The solution is a sequence of two SWAPs
+            let (_, _, graph, best) = AStarBuilder::new(CXCircuit16::new(), &target, moves.clone())
+                .allow_swaps(swap_cost)
+                .search();
+            let best = best.expect("the search finds a solution");
+            assert_eq!(graph.cost(best), 2);
+            if swap_cost < 3. {
+                // Cheap SWAPs are tried first
+                assert!(stats.expansions < cx_stats.expansions);
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_with_dot() {
+        // The search of `test_a_star_simple`
+        let mut circuit = CXCircuit16::new();
+        circuit.add_cx(0, 9);
+        circuit.add_cx(0, 10);
+        let moves = vec![CX { ctrl: 0, tgt: 9 }, CX { ctrl: 0, tgt: 10 }];
+        let (solutions, stats, dot) =
+            AStarBuilder::new(CXCircuit16::new(), &circuit, moves).run_with_dot();
+        assert_eq!(solutions[0].len(), 2);
+        assert!(dot.starts_with("digraph astar {"));
+        assert_eq!(dot.matches("(cost ").count(), stats.node_count);
+        assert!(dot.contains("0 [label=\"0 (cost 0)\", style=filled, fillcolor=yellow]"));
+        assert!(dot.contains("[label=\"CX(0, 9)\", color=blue]"));
+        assert!(dot.contains("[label=\"CX(0, 10)\", color=blue]"));
+        // The root, one of the two CXs and the solution are highlighted
+        assert_eq!(dot.matches("fillcolor=yellow").count(), 3);
+    }
+
+    #[test]
+    fn test_ida_star() {
+        let line_moves: Vec<_> = (0..7)
+            .flat_map(|i| {
+                [
+                    CX {
+                        ctrl: i,
+                        tgt: i + 1,
+                    },
+                    CX {
+                        ctrl: i + 1,
+                        tgt: i,
+                    },
+                ]
+            })
+            .collect();
+        let test_cases = [
+            (
+                CXCircuit16::from_cxs([(0, 9), (0, 10)]),
+                vec![CX { ctrl: 0, tgt: 9 }, CX { ctrl: 0, tgt: 10 }],
+            ),
+            (
+                CXCircuit16::from_cxs([(0, 1), (2, 3), (1, 2), (4, 3)]),
+                line_moves.clone(),
+            ),
+            (CXCircuit16::from_cxs([(1, 0), (0, 1), (1, 0)]), line_moves),
+        ];
+        for (target, moves) in test_cases {
+            let a_star_result = a_star(CXCircuit16::new(), &target, moves.clone(), None, None);
+            let ida_result = ida_star(CXCircuit16::new(), &target, moves, None).unwrap();
+            assert_eq!(ida_result.len(), a_star_result.unwrap().len());
+            let circuit = ida_result
+                .iter()
+                .fold(CXCircuit16::new(), |circ, cx| circ.cx(cx.ctrl, cx.tgt));
+            assert_eq!(circuit, target);
+        }
+        // The target is out of reach of the moves
+        let target = CXCircuit16::from_cxs([(2, 1)]);
+        let moves = [CX { ctrl: 0, tgt: 1 }];
+        assert_eq!(ida_star(CXCircuit16::new(), &target, moves, Some(3)), None);
+    }
+
+    #[test]
+    fn test_weighted_a_star() {
+        let moves: Vec<CX> = (0..7)
+            .flat_map(|i| [(i, i + 1), (i + 1, i)])
+            .map(CX::from)
+            .collect();
+        let target = CXCircuit16::from_cxs([(0, 1), (1, 2), (3, 2), (2, 3), (5, 4)]);
+        let optimal = weighted_a_star(CXCircuit16::new(), &target, moves.clone(), None, 1.);
+        let optimal = optimal.unwrap();
+        assert_eq!(optimal.len(), 5);
+        let weighted = weighted_a_star(CXCircuit16::new(), &target, moves, None, 2.).unwrap();
+        assert!(weighted.len() <= 2 * optimal.len());
+        let circuit = weighted
+            .iter()
+            .fold(CXCircuit16::new(), |circ, cx| circ.cx(cx.ctrl, cx.tgt));
+        assert_eq!(circuit, target);
+    }
+
+    #[test]
+    fn test_a_star_merged_solution_order() {
+        // The optimal solution is found through merges whose branches share
+        // the `CX(2, 3)` gate, which must come before `CX(1, 2)`
+        let moves: Vec<_> = (0..7)
+            .flat_map(|i| {
+                [
+                    CX {
+                        ctrl: i,
+                        tgt: i + 1,
+                    },
+                    CX {
+                        ctrl: i + 1,
+                        tgt: i,
+                    },
+                ]
+            })
+            .collect();
+        let target = CXCircuit16::from_cxs([(0, 1), (2, 3), (1, 2), (4, 3)]);
+        let result = a_star(CXCircuit16::new(), &target, moves, None, None).unwrap();
+        assert_eq!(result.len(), 4);
+        let circuit = result
+            .iter()
+            .fold(CXCircuit16::new(), |circ, cx| circ.cx(cx.ctrl, cx.tgt));
+        assert_eq!(circuit, target);
+    }
+
     #[test]
     fn test_a_star_with_merge() {
         let mut circuit = CXCircuit16::new();
@@ -161,7 +1006,143 @@ mod tests {
             CX { ctrl: 2, tgt: 3 },
             CX { ctrl: 1, tgt: 4 },
         ];
-        let result = a_star(CXCircuit16::new(), &circuit, moves, Some(3)).unwrap();
+        let result = a_star(CXCircuit16::new(), &circuit, moves, Some(3), None).unwrap();
         assert_eq!(result.len(), 3);
     }
+
+    #[test]
+    fn test_a_star_all() {
+        let mut circuit = CXCircuit16::new();
+        circuit.add_cx(0, 1);
+        circuit.add_cx(0, 2);
+        let moves = vec![CX { ctrl: 0, tgt: 1 }, CX { ctrl: 0, tgt: 2 }];
+        let solutions = a_star_all(CXCircuit16::new(), &circuit, moves, Some(2), None);
+        assert_eq!(
+            FxHashSet::from_iter(solutions),
+            FxHashSet::from_iter([
+                vec![CX { ctrl: 0, tgt: 1 }, CX { ctrl: 0, tgt: 2 }],
+                vec![CX { ctrl: 0, tgt: 2 }, CX { ctrl: 0, tgt: 1 }],
+            ])
+        );
+    }
+
+    #[test]
+    fn test_a_star_all_interleavings() {
+        // CX(2, 3) can only be added to the others by a merge, which allows
+        // any interleaving
+        let circuit = CXCircuit16::from_cxs([(0, 1), (1, 4), (2, 3)]);
+        let moves = [(0, 1), (1, 4), (2, 3)].map(CX::from);
+        let solutions = a_star_all(CXCircuit16::new(), &circuit, moves, Some(3), None);
+        assert_eq!(
+            FxHashSet::from_iter(solutions),
+            FxHashSet::from_iter([
+                [(0, 1), (1, 4), (2, 3)].map(CX::from).to_vec(),
+                [(0, 1), (2, 3), (1, 4)].map(CX::from).to_vec(),
+                [(2, 3), (0, 1), (1, 4)].map(CX::from).to_vec(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_a_star_all_commuting() {
+        // The last two CXs commute, so both orders are solutions
+        let circuit = CXCircuit16::from_cxs([(0, 1), (1, 2), (1, 3)]);
+        let moves = iproduct!(0..5, 0..5)
+            .filter(|(a, b)| a != b)
+            .map(CX::from)
+            .collect_vec();
+        let solutions = a_star_all(CXCircuit16::new(), &circuit, moves.clone(), Some(3), None);
+        assert_eq!(
+            FxHashSet::from_iter(solutions),
+            FxHashSet::from_iter([
+                [(0, 1), (1, 2), (1, 3)].map(CX::from).to_vec(),
+                [(0, 1), (1, 3), (1, 2)].map(CX::from).to_vec(),
+            ])
+        );
+
+        let circuit = CXCircuit16::from_cxs([(1, 2), (0, 2), (2, 3), (2, 4)]);
+        let solutions = a_star_all(CXCircuit16::new(), &circuit, moves, Some(4), None);
+        let last_two = solutions
+            .iter()
+            .map(|sol| &sol[2..])
+            .collect::<FxHashSet<_>>();
+        assert_eq!(
+            last_two,
+            FxHashSet::from_iter([
+                &[(2, 3), (2, 4)].map(CX::from)[..],
+                &[(2, 4), (2, 3)].map(CX::from)[..],
+            ])
+        );
+    }
+
+    #[test]
+    fn test_a_star_start_is_target() {
+        // Not reachable from the identity by Gaussian elimination on a line,
+        // so there is no upper bound to stop the search
+        let start = CXCircuit16::from_cxs([(0, 2), (2, 0), (0, 2)]);
+        let moves = Topology::linear(16).to_cx_moves();
+        assert!(start
+            .upper_bound(&start, &FxHashSet::from_iter(moves.clone()))
+            .is_none());
+        assert_eq!(a_star(start, &start, moves, Some(20), None), Some(vec![]));
+    }
+
+    #[test]
+    fn test_a_star_timed() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let start = CXCircuit16::random_invertible(&mut rng);
+        let target = CXCircuit16::random_invertible(&mut rng);
+        let moves = Topology::linear(16).to_cx_moves();
+        let timeout = Duration::from_millis(10);
+        let (_, stats) = a_star_timed(start, &target, moves.clone(), None, Some(timeout));
+        assert!(stats.nodes_expanded > 0);
+        assert!(stats.nodes_created > stats.nodes_expanded);
+        // The deadline is checked before every expansion
+        let (_, stats) = a_star_timed(start, &target, moves.clone(), None, Some(Duration::ZERO));
+        assert_eq!(stats.nodes_expanded, 0);
+
+        let target = CXCircuit16::from_cxs([(0, 1), (1, 2)]);
+        let (solution, stats) = a_star_timed(CXCircuit16::new(), &target, moves, None, None);
+        assert_eq!(solution.map(|solution| solution.len()), Some(2));
+        assert_eq!(stats.best_cost_found, Some(2));
+    }
+
+    #[test]
+    fn test_a_star_on_progress() {
+        let moves = Topology::linear(16).to_cx_moves();
+        let target = CXCircuit16::from_cxs([(0, 1), (1, 2), (3, 2)]);
+        let progress = std::cell::RefCell::new(Vec::new());
+        let (solutions, _) = AStarBuilder::new(CXCircuit16::new(), &target, moves)
+            .max_depth(10)
+            .on_progress(|p: &AStarProgress| progress.borrow_mut().push(p.clone()))
+            .run();
+        assert_eq!(solutions[0].len(), 3);
+        let progress = progress.into_inner();
+        assert!(progress.len() >= 2);
+        let costs: Vec<_> = progress.iter().map(|p| p.max_cost_explored).collect();
+        assert!(costs.windows(2).all(|w| w[0] < w[1]));
+        assert!(progress.windows(2).all(|w| w[0].elapsed <= w[1].elapsed));
+    }
+
+    proptest::proptest! {
+        #![proptest_config(proptest::prelude::ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn a_star_reaches_random_target(seed: u64, n_gates in 0..4usize) {
+            use rand::{rngs::StdRng, SeedableRng};
+
+            let mut rng = StdRng::seed_from_u64(seed);
+            let moves = Topology::linear(16).to_cx_moves();
+            let start = CXCircuit16::random_invertible(&mut rng);
+            let target = CXCircuit16::random(n_gates, &moves, &mut rng).mult(&start);
+            let solution = a_star(start, &target, moves, Some(20), None).unwrap();
+            proptest::prop_assert!(solution.len() <= n_gates);
+            let result = solution
+                .iter()
+                .fold(start, |circ, cx| circ.cx(cx.ctrl, cx.tgt));
+            proptest::prop_assert_eq!(result, target);
+        }
+    }
 }
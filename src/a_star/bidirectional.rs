@@ -0,0 +1,189 @@
+//! A bidirectional A* search, growing one search tree from the start and one
+//! from the target until they meet.
+//!
+//! CX gates are self-inverse, so the backward search applies the same moves
+//! to the target. A path from the target to a meeting point, reversed, leads
+//! from the meeting point to the target. Unlike [`super::a_star`], only CX
+//! edges are considered.
+
+use std::cmp::{max, Reverse};
+
+use fxhash::FxHashMap;
+use priority_queue::PriorityQueue;
+
+use super::AStarValue;
+use crate::CX;
+
+/// One direction of the search
+struct Frontier<V> {
+    /// The value this search starts from
+    root: V,
+    /// The value this search is heading towards
+    goal: V,
+    /// The cost of the cheapest known path to every reached value, along with
+    /// the previous value and the CX on that path
+    reached: FxHashMap<V, (usize, Option<(V, CX)>)>,
+    /// The reached values that are yet to be expanded, by estimated total
+    /// cost
+    open: PriorityQueue<V, Reverse<usize>>,
+}
+
+impl<V: AStarValue> Frontier<V> {
+    fn new(root: V, goal: V) -> Self {
+        let mut open = PriorityQueue::new();
+        open.push(root.clone(), Reverse(root.dist(&goal)));
+        Self {
+            reached: FxHashMap::from_iter([(root.clone(), (0, None))]),
+            root,
+            goal,
+            open,
+        }
+    }
+
+    /// The lowest estimated total cost of the values yet to be expanded.
+    fn min_cost(&self) -> Option<usize> {
+        self.open.peek().map(|(_, &Reverse(cost))| cost)
+    }
+
+    fn cost(&self, value: &V) -> Option<usize> {
+        self.reached.get(value).map(|&(cost, _)| cost)
+    }
+
+    /// Expand the most promising value, returning the children whose cost
+    /// was improved.
+    fn expand(&mut self, moves: &[CX]) -> Vec<V> {
+        let Some((value, _)) = self.open.pop() else {
+            return Vec::new();
+        };
+        let cost = self.cost(&value).expect("open values are reached") + 1;
+        let mut improved = Vec::new();
+        for &mv in moves {
+            let child = value.cx(mv.ctrl, mv.tgt);
+            if self.cost(&child).is_some_and(|old_cost| old_cost <= cost) {
+                continue;
+            }
+            self.reached
+                .insert(child.clone(), (cost, Some((value.clone(), mv))));
+            self.open
+                .push(child.clone(), Reverse(cost + child.dist(&self.goal)));
+            improved.push(child);
+        }
+        improved
+    }
+
+    /// The moves from the root to `value`.
+    fn path_to(&self, value: &V) -> Vec<CX> {
+        let mut path = Vec::new();
+        let mut curr = value;
+        while let Some((_, Some((prev, mv)))) = self.reached.get(curr) {
+            path.push(*mv);
+            curr = prev;
+        }
+        path.reverse();
+        path
+    }
+
+    /// The value reached by applying `path` to the root.
+    fn apply(&self, path: &[CX]) -> V {
+        path.iter()
+            .fold(self.root.clone(), |value, mv| value.cx(mv.ctrl, mv.tgt))
+    }
+}
+
+/// Find a shortest sequence of `allowed_moves` that maps `start` to `target`,
+/// searching from both ends at once.
+///
+/// The search with fewer open values is expanded first. It stops once no
+/// path through an unexpanded value can be shorter than the best path found
+/// where the two searches meet.
+pub fn bidir_a_star<V: AStarValue>(
+    start: V,
+    target: &V,
+    allowed_moves: impl IntoIterator<Item = CX>,
+    max_depth: Option<usize>,
+) -> Option<Vec<CX>> {
+    let moves: Vec<CX> = allowed_moves.into_iter().collect();
+    // The length of the shortest path found so far and its meeting point
+    let mut best = (start == *target).then(|| (0, start.clone()));
+    let mut forward = Frontier::new(start.clone(), target.clone());
+    let mut backward = Frontier::new(target.clone(), start);
+
+    while let (Some(forward_cost), Some(backward_cost)) = (forward.min_cost(), backward.min_cost())
+    {
+        let lower_bound = max(forward_cost, backward_cost);
+        if best.as_ref().is_some_and(|(len, _)| *len <= lower_bound) {
+            break;
+        }
+        if max_depth.is_some_and(|max_depth| lower_bound > max_depth) {
+            println!("Max depth reached, aborting");
+            break;
+        }
+        let (this, other) = if forward.open.len() <= backward.open.len() {
+            (&mut forward, &backward)
+        } else {
+            (&mut backward, &forward)
+        };
+        for child in this.expand(&moves) {
+            let Some(other_cost) = other.cost(&child) else {
+                continue;
+            };
+            let len = this.cost(&child).expect("child was reached") + other_cost;
+            if best.as_ref().is_none_or(|(best_len, _)| len < *best_len) {
+                println!("New best solution: {len} CXs");
+                best = Some((len, child));
+            }
+        }
+    }
+
+    let (len, meeting_point) = best?;
+    if max_depth.is_some_and(|max_depth| len > max_depth) {
+        return None;
+    }
+    let mut path = forward.path_to(&meeting_point);
+    let backward_path = backward.path_to(&meeting_point);
+    debug_assert_eq!(
+        forward.apply(&path).dist(&backward.apply(&backward_path)),
+        0,
+        "the two searches must meet"
+    );
+    path.extend(backward_path.into_iter().rev());
+    Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        a_star::a_star,
+        cx_circuit::{CXCircuit, CXCircuit16},
+    };
+
+    #[test]
+    fn test_bidir_a_star() {
+        let line_moves: Vec<CX> = (0..7)
+            .flat_map(|i| [(i, i + 1), (i + 1, i)])
+            .map(CX::from)
+            .collect();
+        let targets = [
+            CXCircuit16::new(),
+            CXCircuit16::from_cxs([(0, 1), (2, 3), (1, 2), (4, 3)]),
+            CXCircuit16::from_cxs([(1, 0), (0, 1), (1, 0)]),
+            CXCircuit16::from_cxs([(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 6)]),
+        ];
+        for target in targets {
+            let expected = a_star(CXCircuit16::new(), &target, line_moves.clone(), None, None);
+            let result = bidir_a_star(CXCircuit16::new(), &target, line_moves.clone(), None);
+            let result = result.unwrap();
+            assert_eq!(result.len(), expected.unwrap().len());
+            let circuit = result
+                .iter()
+                .fold(CXCircuit16::new(), |circ, cx| circ.cx(cx.ctrl, cx.tgt));
+            assert_eq!(circuit, target);
+        }
+        let target = CXCircuit16::from_cxs([(0, 1), (1, 2), (2, 3)]);
+        assert_eq!(
+            bidir_a_star(CXCircuit16::new(), &target, line_moves, Some(2)),
+            None
+        );
+    }
+}
@@ -1,7 +1,10 @@
 use fxhash::{FxHashMap, FxHashSet};
 use itertools::{iproduct, Itertools};
 
-use super::{graph::AEdge, ANodeInd, AStarGraph, AStarValue};
+use super::{
+    graph::{AEdge, FocusMode},
+    ANodeInd, AStarGraph, AStarValue,
+};
 use crate::CX;
 
 impl<V: AStarValue> AStarGraph<V> {
@@ -14,8 +17,8 @@ impl<V: AStarValue> AStarGraph<V> {
     ///
     /// This implements the following logic:
     ///  - If `ind` is the root, you can add a CX anywhere but cannot merge
-    ///  - If `ind` is the target of a CX edge, we can:
-    ///      - Add any CX that overlaps at least one qubit with `ind`
+    ///  - If `ind` is the target of a CX or SWAP edge, we can:
+    ///      - Add any CX or SWAP that overlaps at least one qubit with `ind`
     ///      - Add merges -- we can add merges between `ind` (LHS) and any other
     ///        compatible RHS merge node.
     ///  - If `ind` is the target of a merge edge, we distinguish between
@@ -32,13 +35,85 @@ impl<V: AStarValue> AStarGraph<V> {
     /// However, we allow terminal merges only if the CXs that precede the
     /// terminal merges are complete: there is no point in doing terminal merges
     /// if the qubits still need additional CXs.
-    pub(super) fn expand_children(&mut self, ind: ANodeInd, is_complete: impl Fn(u8) -> bool) {
+    ///
+    /// A CX that commutes with the preceding CX is only added if the reverse
+    /// order has not been added already, as both orders reach the same value.
+    /// Both orders are kept if all solutions are requested or with
+    /// [`FocusMode::MostConstrained`].
+    ///
+    /// SWAPs, if allowed, are added wherever CXs in both directions would be,
+    /// except right after the same SWAP.
+    ///
+    /// With [`FocusMode::MostConstrained`], CXs are further restricted to the
+    /// qubit furthest from `target`.
+    pub(super) fn expand_children(&mut self, ind: ANodeInd, target: &V) {
+        let value = self.value(ind).expect("invalid node").clone();
+        let is_complete = |qb| value.is_complete(qb, target);
+        let focus_qubit = match self.focus_mode {
+            FocusMode::AllMoves => None,
+            FocusMode::MostConstrained => (0..value.n_qubits() as u8)
+                .map(|qb| (value.qubit_dist(qb, target), qb))
+                .max()
+                .filter(|&(dist, _)| dist > 0)
+                .map(|(_, qb)| qb),
+        };
+        let is_focused = |cx: &CX| focus_qubit.is_none_or(|qb| cx.ctrl == qb || cx.tgt == qb);
+        let is_focused_swap = |&[q1, q2]: &[u8; 2]| is_focused(&CX { ctrl: q1, tgt: q2 });
+
         // Find out if and where we can add CXs, and add them
         match self.prev_edge(ind) {
+            Some(&AEdge::Swap {
+                qubits: [q1, q2], ..
+            }) => {
+                let overlaps = |qbs: [u8; 2]| qbs.iter().any(|qb| *qb == q1 || *qb == q2);
+                let allowed_moves = self
+                    .allowed_moves
+                    .iter()
+                    .copied()
+                    .filter(|cx| overlaps([cx.ctrl, cx.tgt]))
+                    .filter(is_focused)
+                    .collect_vec();
+                for cx in allowed_moves {
+                    self.add_cx(ind, cx);
+                }
+                let allowed_swaps = self
+                    .allowed_swaps
+                    .iter()
+                    .copied()
+                    .filter(|&swap| overlaps(swap))
+                    // A SWAP cancels itself
+                    .filter(|&swap| swap != [q1.min(q2), q1.max(q2)])
+                    .filter(is_focused_swap)
+                    .collect_vec();
+                for swap in allowed_swaps {
+                    self.add_swap(ind, swap);
+                }
+            }
             Some(&AEdge::Op {
-                op: CX { ctrl, tgt },
+                op: prev_cx @ CX { ctrl, tgt },
+                src,
                 ..
             }) => {
+                // CXs that commute with `prev_cx` give the same value in either
+                // order: skip those that are also a sibling of `ind`, as the
+                // other order is reached from that sibling. All orders are
+                // needed to find all solutions, and the sibling may not add
+                // `prev_cx` when moves are restricted to a focus qubit
+                let prune_reorderings =
+                    self.keep_duplicates.is_none() && self.focus_mode == FocusMode::AllMoves;
+                let sibling_cxs: FxHashSet<CX> = self
+                    .next_edges(src)
+                    .filter_map(|edge| match edge {
+                        &AEdge::Op { op, .. } => Some(op),
+                        AEdge::Swap { .. } | AEdge::Merge { .. } => None,
+                    })
+                    .collect();
+                let is_reordering = |cx: &CX| {
+                    prune_reorderings
+                        && cx.commutes_with(prev_cx)
+                        && *cx < prev_cx
+                        && sibling_cxs.contains(cx)
+                };
                 let allowed_moves = self
                     .allowed_moves
                     .iter()
@@ -46,35 +121,67 @@ impl<V: AStarValue> AStarGraph<V> {
                     .filter(|cx| {
                         cx.ctrl == ctrl || cx.tgt == ctrl || cx.ctrl == tgt || cx.tgt == tgt
                     })
+                    // A CX cancels itself
+                    .filter(|&cx| cx != prev_cx)
+                    .filter(|cx| !is_reordering(cx))
+                    .filter(is_focused)
                     .collect_vec();
                 for cx in allowed_moves {
                     self.add_cx(ind, cx);
                 }
+                let allowed_swaps = self
+                    .allowed_swaps
+                    .iter()
+                    .copied()
+                    .filter(|swap| swap.contains(&ctrl) || swap.contains(&tgt))
+                    .filter(is_focused_swap)
+                    .collect_vec();
+                for swap in allowed_swaps {
+                    self.add_swap(ind, swap);
+                }
             }
             Some(merge_edge @ AEdge::Merge { .. }) => {
                 if let Some((qbs1, qbs2)) = self.get_cx_qbs(merge_edge) {
                     // We can add CX between qbs1 and qbs2
                     for (ctrl, tgt) in iproduct!(qbs1, qbs2) {
                         let cx = CX { ctrl, tgt };
-                        if self.allowed_moves.contains(&cx) {
+                        if self.allowed_moves.contains(&cx) && is_focused(&cx) {
                             self.add_cx(ind, cx);
                         }
                         let rev_cx = CX {
                             ctrl: tgt,
                             tgt: ctrl,
                         };
-                        if self.allowed_moves.contains(&rev_cx) {
+                        if self.allowed_moves.contains(&rev_cx) && is_focused(&rev_cx) {
                             self.add_cx(ind, rev_cx);
                         }
+                        let swap = [ctrl.min(tgt), ctrl.max(tgt)];
+                        if self.is_allowed_swap(swap) && is_focused_swap(&swap) {
+                            self.add_swap(ind, swap);
+                        }
                     }
                 }
             }
             None => {
-                // We are at the root, any CX is allowed
-                let allowed_moves = self.allowed_moves.clone();
+                // We are at the root, any CX or SWAP is allowed
+                let allowed_moves = self
+                    .allowed_moves
+                    .iter()
+                    .copied()
+                    .filter(is_focused)
+                    .collect_vec();
                 for cx in allowed_moves {
                     self.add_cx(ind, cx);
                 }
+                let allowed_swaps = self
+                    .allowed_swaps
+                    .iter()
+                    .copied()
+                    .filter(is_focused_swap)
+                    .collect_vec();
+                for swap in allowed_swaps {
+                    self.add_swap(ind, swap);
+                }
             }
         }
         // We can add merges either after CXs or in the terminal merge phase
@@ -86,6 +193,7 @@ impl<V: AStarValue> AStarGraph<V> {
                 self.add_merge(ind, node, &qbs);
             }
         }
+        self.mark_expanded(ind);
     }
 
     /// Whether `ind` can be the LHS of a merge.
@@ -94,9 +202,9 @@ impl<V: AStarValue> AStarGraph<V> {
     ///  - `ind` is not the root
     ///  - `ind` has not been expanded yet
     ///  - either of
-    ///     * the predecessor of `ind` is a CX edge, or
-    ///     * all CX edges in the past are complete, i.e. no further gates are
-    ///       required on the qubits the op acts on.
+    ///     * the predecessor of `ind` is a CX or SWAP edge, or
+    ///     * all CX and SWAP edges in the past are complete, i.e. no further
+    ///       gates are required on the qubits the op acts on.
     fn is_mergeable(&self, ind: ANodeInd, is_complete: impl Fn(u8) -> bool) -> bool {
         if self.is_expanded(ind) {
             // Cannot merge nodes that have already been expanded
@@ -107,8 +215,8 @@ impl<V: AStarValue> AStarGraph<V> {
                 // Cannot merge root
                 false
             }
-            Some(AEdge::Op { .. }) => {
-                // Can always merge if preceded by CX
+            Some(AEdge::Op { .. } | AEdge::Swap { .. }) => {
+                // Can always merge if preceded by a gate
                 true
             }
             Some(&AEdge::Merge { src1, src2, .. }) => {
@@ -116,19 +224,10 @@ impl<V: AStarValue> AStarGraph<V> {
                 // Here we should be computing the qubits of CXs in the past
                 // recursively -- instead, we rely on the fact that a previous
                 // merge would have already been checked for mergeability
-                if let Some(&AEdge::Op {
-                    op: CX { ctrl, tgt },
-                    ..
-                }) = self.prev_edge(src1)
-                {
-                    qubits.extend([ctrl, tgt]);
-                }
-                if let Some(&AEdge::Op {
-                    op: CX { ctrl, tgt },
-                    ..
-                }) = self.prev_edge(src2)
-                {
-                    qubits.extend([ctrl, tgt]);
+                for src in [src1, src2] {
+                    if let Some(gate_qubits) = self.prev_edge(src).and_then(AEdge::gate_qubits) {
+                        qubits.extend(gate_qubits);
+                    }
                 }
                 qubits.into_iter().all(|qb| is_complete(qb))
             }
@@ -175,11 +274,8 @@ impl<V: AStarValue> AStarGraph<V> {
             //  invariant: the source of `edge` has a value in `disallowed_qbs`
             for edge in self.next_edges(curr) {
                 match edge {
-                    &AEdge::Op {
-                        op: CX { ctrl, tgt },
-                        src,
-                        dst,
-                    } => {
+                    &AEdge::Op { src, dst, .. } | &AEdge::Swap { src, dst, .. } => {
+                        let [ctrl, tgt] = edge.gate_qubits().expect("not a merge");
                         if nodes_in_past.contains(&dst) {
                             // We have already processed this node in the backward pass, skip
                             queue.push(dst);
@@ -240,20 +336,17 @@ impl<V: AStarValue> AStarGraph<V> {
         mergeable_nodes
     }
 
-    /// Given a Merge edge, returns the qubits of both previous CX edges.
+    /// Given a Merge edge, returns the qubits of both previous CX or SWAP
+    /// edges.
     ///
-    /// If at least one of the two previous edges were not CX edges, return None.
+    /// If at least one of the two previous edges were merges, return None.
     fn get_cx_qbs(&self, edge: &AEdge) -> Option<([u8; 2], [u8; 2])> {
         let &AEdge::Merge { src1, src2, .. } = edge else {
             panic!("Not a merge edge");
         };
-        let Some(AEdge::Op { op: op1, .. }) = self.prev_edge(src1) else {
-            return None;
-        };
-        let Some(AEdge::Op { op: op2, .. }) = self.prev_edge(src2) else {
-            return None;
-        };
-        Some(([op1.ctrl, op1.tgt], [op2.ctrl, op2.tgt]))
+        let qbs1 = self.prev_edge(src1)?.gate_qubits()?;
+        let qbs2 = self.prev_edge(src2)?.gate_qubits()?;
+        Some((qbs1, qbs2))
     }
 }
 
@@ -262,6 +355,7 @@ mod tests {
     use itertools::Itertools;
 
     use super::*;
+    use crate::cx_circuit::{CXCircuit, CXCircuit16};
 
     /// Tests the `find_mergeable_nodes` function on the following search graph
     ///
@@ -286,8 +380,8 @@ mod tests {
         .collect_vec();
 
         // Dummy expand as we can only merge expanded nodes
-        graph.add_cx(children[1], CX { ctrl: 0, tgt: 4 }).unwrap();
-        graph.add_cx(children[2], CX { ctrl: 0, tgt: 4 }).unwrap();
+        graph.mark_expanded(children[1]);
+        graph.mark_expanded(children[2]);
         assert!(graph.is_expanded(children[1]));
         assert!(graph.is_expanded(children[2]));
 
@@ -301,7 +395,7 @@ mod tests {
         let grandchild2 = graph.add_cx(children[0], CX { ctrl: 3, tgt: 4 }).unwrap();
 
         // Dummy expand as we can only merge expanded nodes
-        graph.add_cx(grandchild2, CX { ctrl: 0, tgt: 2 }).unwrap();
+        graph.mark_expanded(grandchild2);
         assert!(graph.is_expanded(grandchild2));
 
         let mergeable_nodes = graph.find_mergeable_nodes(grandchild);
@@ -310,4 +404,51 @@ mod tests {
             FxHashSet::from_iter([grandchild2, children[1]])
         );
     }
+
+    /// Draws the graph of `test_find_mergeable_nodes`, with `4` and `2` merged.
+    #[test]
+    fn test_to_dot() {
+        let mut graph = AStarGraph::new([false; 5], []);
+        let children = [(0, 1), (4, 3), (2, 3)]
+            .map(|cx| graph.add_cx(graph.root_ind(), CX::from(cx)).unwrap());
+        let grandchild = graph.add_cx(children[0], CX { ctrl: 1, tgt: 2 }).unwrap();
+        graph.add_cx(children[0], CX { ctrl: 3, tgt: 4 }).unwrap();
+        let merged = graph
+            .add_merge(grandchild, children[1], &FxHashSet::from_iter([3, 4]))
+            .unwrap();
+
+        let dot = graph.to_dot(&graph.ancestors(merged));
+        assert!(dot.contains("0 -> 1 [label=\"CX(0, 1)\", color=blue];"));
+        assert_eq!(dot.matches("(cost ").count(), graph.node_count());
+        assert_eq!(graph.node_count(), 7);
+        // The merge is a hyperarc from both sources
+        assert!(dot.contains("4 -> merge6 [color=red, arrowhead=none];"));
+        assert!(dot.contains("2 -> merge6 [color=red, arrowhead=none];"));
+        assert!(dot.contains("merge6 -> 6 [color=red];"));
+        // All but node 3 and 5 lead to the merged node
+        assert_eq!(dot.matches("fillcolor=yellow").count(), 5);
+    }
+
+    #[test]
+    fn test_most_constrained_focus() {
+        let moves = (0..15).flat_map(|i| [(i, i + 1), (i + 1, i)]).map(CX::from);
+        // Only qubit 1 differs from the identity
+        let target = CXCircuit16::from_cxs([(0, 1), (2, 1)]);
+
+        let mut graph = AStarGraph::new(CXCircuit16::new(), moves.clone());
+        graph.expand_children(graph.root_ind(), &target);
+        assert_eq!(graph.children(graph.root_ind()).count(), 30);
+
+        let mut graph = AStarGraph::new(CXCircuit16::new(), moves);
+        graph.focus_mode = FocusMode::MostConstrained;
+        graph.expand_children(graph.root_ind(), &target);
+        let children = graph.children(graph.root_ind()).collect_vec();
+        assert_eq!(children.len(), 4);
+        for child in children {
+            let Some(&AEdge::Op { op, .. }) = graph.prev_edge(child) else {
+                panic!("expected a CX edge");
+            };
+            assert!(op.ctrl == 1 || op.tgt == 1);
+        }
+    }
 }
@@ -1,8 +1,8 @@
-use std::cmp::max;
+use std::{cmp::max, collections::VecDeque, fmt::Write};
 
-use fxhash::{FxBuildHasher, FxHashSet};
+use fxhash::{FxHashMap, FxHashSet};
 
-use super::AStarValue;
+use super::{interner::Interner, AStarValue};
 use crate::CX;
 
 pub(super) type ANodeInd = usize;
@@ -14,6 +14,12 @@ pub enum AEdge {
         src: ANodeInd,
         dst: ANodeInd,
     },
+    /// A SWAP gate, exchanging the two qubits
+    Swap {
+        qubits: [u8; 2],
+        src: ANodeInd,
+        dst: ANodeInd,
+    },
     Merge {
         src1: ANodeInd,
         src2: ANodeInd,
@@ -21,6 +27,20 @@ pub enum AEdge {
     },
 }
 
+/// Which CX moves are considered when expanding a node
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum FocusMode {
+    /// All allowed moves
+    #[default]
+    AllMoves,
+    /// Only moves on the qubit that is furthest from the target
+    ///
+    /// Similar to the "minimum remaining values" heuristic in constraint
+    /// satisfaction, this reduces the branching factor but may miss the
+    /// optimal solution.
+    MostConstrained,
+}
+
 /// A node in the A* search graph
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub(super) struct ANode {
@@ -31,35 +51,140 @@ pub(super) struct ANode {
     /// The next edges in the path from this node to other nodes
     next: Vec<AEdge>,
     /// The counts of which CX interactions have happened so far
-    stats: AStarStats,
+    stats: NodeStats,
+    /// The id of the node value in the graph's interner
+    value: usize,
+    /// Whether the children of this node have been added to the graph
+    expanded: bool,
 }
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
-pub(super) struct AStarStats {
+pub(super) struct NodeStats {
     cx_count_per_qb: Vec<u16>,
 }
 
-type AStarValueMap<V> = bimap::BiHashMap<ANodeInd, V, FxBuildHasher, FxBuildHasher>;
-
 #[derive(Debug)]
 pub(super) struct AStarGraph<V> {
-    nodes: Vec<ANode>,
-    values: AStarValueMap<V>,
+    /// The nodes, indexed by [`ANodeInd`], or `None` if garbage collected
+    nodes: Vec<Option<ANode>>,
+    values: Interner<V>,
     pub(super) allowed_moves: FxHashSet<CX>,
+    /// The number of nodes expanded so far
+    pub(super) expansions: usize,
+    /// The maximum number of nodes that may be expanded
+    expansion_limit: Option<usize>,
+    /// The id of a value for which duplicate nodes are added to the graph
+    pub(super) keep_duplicates: Option<usize>,
+    /// The number of merge nodes added to the graph
+    merge_count: usize,
+    /// The cost of each move, for moves that do not have unit cost
+    move_costs: FxHashMap<CX, f64>,
+    /// The lowest path cost with which each value was reached
+    value_costs: FxHashMap<usize, f64>,
+    /// The qubit pairs on which SWAP gates are allowed, smaller qubit first
+    pub(super) allowed_swaps: FxHashSet<[u8; 2]>,
+    /// The cost of a SWAP gate
+    swap_cost: f64,
+    /// Which CX moves are considered when expanding a node
+    pub(super) focus_mode: FocusMode,
 }
 
 impl<V: AStarValue> AStarGraph<V> {
     pub(super) fn new(start: V, allowed_moves: impl IntoIterator<Item = CX>) -> Self {
-        let values = AStarValueMap::from_iter([(0, start)]);
+        let mut values = Interner::new();
+        let (root_value, _) = values.intern(start);
         Self {
-            nodes: vec![ANode::new_root()],
+            nodes: vec![Some(ANode::new_root(root_value))],
             values,
             allowed_moves: FxHashSet::from_iter(allowed_moves),
+            expansions: 0,
+            expansion_limit: None,
+            keep_duplicates: None,
+            merge_count: 0,
+            move_costs: FxHashMap::default(),
+            value_costs: FxHashMap::default(),
+            allowed_swaps: FxHashSet::default(),
+            swap_cost: 3.,
+            focus_mode: FocusMode::AllMoves,
         }
     }
 
+    /// Add a new node every time `value` is reached, even if it is already in
+    /// the graph.
+    ///
+    /// Use this to record every path to the target value.
+    pub(super) fn keep_duplicates(&mut self, value: V) {
+        let (value, _) = self.values.intern(value);
+        self.keep_duplicates = Some(value);
+    }
+
+    /// Stop expanding nodes once `max` nodes have been expanded.
+    pub(super) fn node_expansion_limit(&mut self, max: usize) {
+        self.expansion_limit = Some(max);
+    }
+
+    /// Set the cost of applying each move. Moves not in `costs` have unit cost.
+    pub(super) fn set_move_costs(&mut self, costs: impl IntoIterator<Item = (CX, f64)>) {
+        self.move_costs.extend(costs);
+    }
+
+    /// Allow SWAP gates of cost `cost` on every pair of qubits with allowed
+    /// CXs in both directions.
+    pub(super) fn allow_swaps(&mut self, cost: f64) {
+        self.allowed_swaps = self
+            .allowed_moves
+            .iter()
+            .filter(|cx| cx.ctrl < cx.tgt)
+            .filter(|cx| {
+                self.allowed_moves
+                    .contains(&CX::from((cx.tgt as usize, cx.ctrl as usize)))
+            })
+            .map(|cx| [cx.ctrl, cx.tgt])
+            .collect();
+        self.swap_cost = cost;
+    }
+
+    /// Whether a SWAP gate is allowed on `qubits`, in any order.
+    pub(super) fn is_allowed_swap(&self, [q1, q2]: [u8; 2]) -> bool {
+        self.allowed_swaps.contains(&[q1.min(q2), q1.max(q2)])
+    }
+
+    /// The cost of applying `cx`.
+    pub(super) fn move_cost(&self, cx: &CX) -> f64 {
+        self.move_costs.get(cx).copied().unwrap_or(1.)
+    }
+
+    /// The lowest cost of any allowed move, counting SWAPs as three CXs.
+    ///
+    /// Every CX still required costs at least this much, so scaling an
+    /// admissible heuristic by it keeps it admissible.
+    pub(super) fn min_move_cost(&self) -> f64 {
+        let swap_cost = (!self.allowed_swaps.is_empty()).then_some(self.swap_cost / 3.);
+        self.allowed_moves
+            .iter()
+            .map(|cx| self.move_cost(cx))
+            .chain(swap_cost)
+            .min_by(f64::total_cmp)
+            .unwrap_or(1.)
+    }
+
+    /// The cost of the gate of `edge`, or 0 for merges.
+    fn edge_cost(&self, edge: &AEdge) -> f64 {
+        match edge {
+            AEdge::Op { op, .. } => self.move_cost(op),
+            AEdge::Swap { .. } => self.swap_cost,
+            AEdge::Merge { .. } => 0.,
+        }
+    }
+
+    /// Whether the maximum number of node expansions has been reached.
+    pub(super) fn expansion_limit_reached(&self) -> bool {
+        self.expansion_limit
+            .is_some_and(|limit| self.expansions >= limit)
+    }
+
     pub(super) fn root(&self) -> &V {
-        self.values.get_by_left(&0).unwrap()
+        self.values.get(self.node(self.root_ind()).value)
     }
 
     pub(super) fn root_ind(&self) -> ANodeInd {
@@ -67,91 +192,483 @@ impl<V: AStarValue> AStarGraph<V> {
     }
 
     pub(super) fn children(&self, ind: ANodeInd) -> impl Iterator<Item = ANodeInd> + '_ {
-        self.nodes[ind].next.iter().map(|edge| edge.dst())
+        self.node(ind).next.iter().map(|edge| edge.dst())
     }
 
     pub(super) fn prev_edge(&self, ind: ANodeInd) -> Option<&AEdge> {
-        self.nodes[ind].prev.as_ref()
+        self.node(ind).prev.as_ref()
     }
 
     pub(super) fn next_edges(&self, ind: ANodeInd) -> impl Iterator<Item = &AEdge> {
-        self.nodes[ind].next.iter()
+        self.node(ind).next.iter()
     }
 
     pub(super) fn value(&self, ind: ANodeInd) -> Option<&V> {
-        self.values.get_by_left(&ind)
+        self.nodes
+            .get(ind)
+            .and_then(Option::as_ref)
+            .map(|node| self.values.get(node.value))
     }
 
+    /// A sequence of CX gates that leads from the root to `ind`.
+    ///
+    /// SWAP gates are written as three CXs. The two branches of a merge may
+    /// share part of their history, so the gates are emitted in post-order: a
+    /// node's gate is only added once all of its ancestors have been.
     pub(super) fn path(&self, ind: ANodeInd) -> Vec<CX> {
         let mut path = Vec::new();
-        let mut curr_nodes = vec![ind];
+        // Stack of nodes along with whether their sources have been visited
+        let mut curr_nodes = vec![(ind, false)];
         let mut seen_nodes = FxHashSet::default();
-        while let Some(node) = curr_nodes.pop() {
+        while let Some((node, srcs_visited)) = curr_nodes.pop() {
+            let prev = self.node(node).prev.as_ref();
+            if srcs_visited {
+                path.extend(prev.map(AEdge::cxs).unwrap_or_default());
+                continue;
+            }
             if !seen_nodes.insert(node) {
                 continue;
             }
-            match self.nodes[node].prev.as_ref() {
-                Some(AEdge::Op { op, src, .. }) => {
-                    path.push(*op);
-                    curr_nodes.push(*src);
+            curr_nodes.push((node, true));
+            let srcs = prev.map(|e| e.srcs()).unwrap_or_default();
+            curr_nodes.extend(srcs.into_iter().rev().map(|src| (src, false)));
+        }
+        path
+    }
+
+    /// All sequences of CX gates that lead from the root to `target_ind`.
+    ///
+    /// The two branches of a merge act on disjoint qubits, so their gates can
+    /// be interleaved in any order. Gates on the same branch keep their order.
+    pub(super) fn get_all_solutions(&self, target_ind: ANodeInd) -> Vec<Vec<CX>> {
+        // For every Op node on the paths to `target_ind`, the Op nodes that
+        // must come before it
+        let mut preds = FxHashMap::default();
+        self.collect_predecessors(target_ind, &mut preds);
+
+        let mut solutions = Vec::new();
+        let mut prefix = Vec::new();
+        self.interleavings(&preds, &mut prefix, &mut solutions);
+        solutions
+    }
+
+    /// Add the Op nodes on the paths to `ind` to `preds`, and return them.
+    fn collect_predecessors(
+        &self,
+        ind: ANodeInd,
+        preds: &mut FxHashMap<ANodeInd, FxHashSet<ANodeInd>>,
+    ) -> FxHashSet<ANodeInd> {
+        match self.node(ind).prev {
+            Some(AEdge::Op { src, .. } | AEdge::Swap { src, .. }) => {
+                let mut history = self.collect_predecessors(src, preds);
+                preds.insert(ind, history.clone());
+                history.insert(ind);
+                history
+            }
+            Some(AEdge::Merge { src1, src2, .. }) => {
+                let mut history = self.collect_predecessors(src1, preds);
+                history.extend(self.collect_predecessors(src2, preds));
+                history
+            }
+            None => FxHashSet::default(),
+        }
+    }
+
+    /// Extend `prefix` with the remaining Op nodes in all orders allowed by
+    /// `preds`, adding the complete sequences to `solutions`.
+    fn interleavings(
+        &self,
+        preds: &FxHashMap<ANodeInd, FxHashSet<ANodeInd>>,
+        prefix: &mut Vec<ANodeInd>,
+        solutions: &mut Vec<Vec<CX>>,
+    ) {
+        if prefix.len() == preds.len() {
+            let solution = prefix
+                .iter()
+                .flat_map(|&ind| self.prev_edge(ind).expect("the root is not in preds").cxs())
+                .collect();
+            solutions.push(solution);
+            return;
+        }
+        let mut ready = preds
+            .iter()
+            .filter(|&(ind, ind_preds)| {
+                !prefix.contains(ind) && ind_preds.iter().all(|pred| prefix.contains(pred))
+            })
+            .map(|(&ind, _)| ind)
+            .collect::<Vec<_>>();
+        // Sort for a deterministic order of the solutions
+        ready.sort_unstable();
+        for ind in ready {
+            prefix.push(ind);
+            self.interleavings(preds, prefix, solutions);
+            prefix.pop();
+        }
+    }
+
+    /// The number of CX and SWAP gates on the paths from the root to `ind`.
+    ///
+    /// Gates in the history shared by both sources of a merge are counted
+    /// once.
+    pub(super) fn cost(&self, ind: ANodeInd) -> usize {
+        self.node(ind).cost
+    }
+
+    /// The sum of the move costs on the path from the root to `ind`.
+    ///
+    /// Equal to [`AStarGraph::cost`] if all moves have unit cost and SWAPs
+    /// are not allowed.
+    pub(super) fn path_cost(&self, ind: ANodeInd) -> f64 {
+        if self.move_costs.is_empty() && self.allowed_swaps.is_empty() {
+            return self.cost(ind) as f64;
+        }
+        self.history_cost([ind])
+    }
+
+    /// The sum of the move costs of the ancestors of all of `inds`, each
+    /// counted once.
+    fn history_cost(&self, inds: impl IntoIterator<Item = ANodeInd>) -> f64 {
+        self.ancestor_set(inds)
+            .into_iter()
+            .filter_map(|ind| self.prev_edge(ind))
+            .map(|edge| self.edge_cost(edge))
+            .sum()
+    }
+
+    /// The nodes on the paths from the root to `ind`, including `ind`.
+    ///
+    /// The paths go through both sources of every merge.
+    pub(super) fn ancestors(&self, ind: ANodeInd) -> Vec<ANodeInd> {
+        let mut ancestors = Vec::from_iter(self.ancestor_set([ind]));
+        ancestors.sort_unstable();
+        ancestors
+    }
+
+    /// The values before and after each gate on the paths to `ind` but not
+    /// on those to `other`, in the order in which they were added.
+    fn exclusive_gates(&self, other: ANodeInd, ind: ANodeInd) -> Vec<(&V, &V)> {
+        let shared = self.ancestor_set([other]);
+        let mut exclusive = Vec::from_iter(self.ancestor_set([ind]).difference(&shared).copied());
+        exclusive.sort_unstable();
+        exclusive
+            .into_iter()
+            .filter_map(|dst| match *self.prev_edge(dst)? {
+                AEdge::Op { src, .. } | AEdge::Swap { src, .. } => {
+                    Some((self.value(src)?, self.value(dst)?))
+                }
+                AEdge::Merge { .. } => None,
+            })
+            .collect()
+    }
+
+    fn ancestor_set(&self, inds: impl IntoIterator<Item = ANodeInd>) -> FxHashSet<ANodeInd> {
+        let mut ancestors = FxHashSet::default();
+        let mut dfs_stack = Vec::from_iter(inds);
+        while let Some(ind) = dfs_stack.pop() {
+            if ancestors.insert(ind) {
+                dfs_stack.extend(self.prev_edge(ind).map(|e| e.srcs()).unwrap_or_default());
+            }
+        }
+        ancestors
+    }
+
+    /// The graph in Graphviz DOT format.
+    ///
+    /// Nodes are labelled with their index and cost, and the nodes in
+    /// `highlight` are filled in yellow. CX edges are blue and SWAP edges
+    /// green, both labelled with their gate. A merge is drawn in red, as a
+    /// hyperarc joining both sources at a point before the merged node.
+    pub(super) fn to_dot(&self, highlight: &[ANodeInd]) -> String {
+        // Writing to a String cannot fail
+        let mut dot = String::from("digraph astar {\n");
+        for (ind, node) in self.nodes.iter().enumerate() {
+            let Some(node) = node else {
+                continue;
+            };
+            let style = if highlight.contains(&ind) {
+                ", style=filled, fillcolor=yellow"
+            } else {
+                ""
+            };
+            writeln!(
+                dot,
+                "    {ind} [label=\"{ind} (cost {})\"{style}];",
+                node.cost
+            )
+            .unwrap();
+            match node.prev {
+                Some(AEdge::Op { op, src, dst }) => {
+                    writeln!(dot, "    {src} -> {dst} [label=\"{op:?}\", color=blue];").unwrap()
                 }
-                Some(AEdge::Merge { src1, src2, .. }) => {
-                    curr_nodes.extend([src1, src2]);
+                Some(AEdge::Swap {
+                    qubits: [q1, q2],
+                    src,
+                    dst,
+                }) => writeln!(
+                    dot,
+                    "    {src} -> {dst} [label=\"SWAP({q1}, {q2})\", color=green];"
+                )
+                .unwrap(),
+                Some(AEdge::Merge { src1, src2, dst }) => {
+                    writeln!(dot, "    merge{dst} [shape=point, color=red];").unwrap();
+                    for src in [src1, src2] {
+                        writeln!(dot, "    {src} -> merge{dst} [color=red, arrowhead=none];")
+                            .unwrap();
+                    }
+                    writeln!(dot, "    merge{dst} -> {dst} [color=red];").unwrap();
                 }
                 None => {}
             }
         }
-        path.reverse();
-        path
+        dot.push_str("}\n");
+        dot
     }
 
-    pub(super) fn cost(&self, ind: ANodeInd) -> usize {
-        self.nodes[ind].cost
+    /// The number of nodes in the graph, not counting garbage collected ones.
+    pub(super) fn node_count(&self) -> usize {
+        self.live_nodes().count()
+    }
+
+    /// An estimate of the memory held by the graph's nodes, in bytes.
+    ///
+    /// The values are not included: they stay interned after garbage
+    /// collection, so that they are not reached again.
+    pub(super) fn memory_estimate_bytes(&self) -> usize {
+        let heap_bytes: usize = self
+            .live_nodes()
+            .map(|node| {
+                node.next.capacity() * size_of::<AEdge>()
+                    + node.stats.cx_count_per_qb.capacity() * size_of::<u16>()
+            })
+            .sum();
+        self.nodes.capacity() * size_of::<Option<ANode>>() + heap_bytes
+    }
+
+    /// Drop all nodes that are not in `keep` or one of their ancestors.
+    ///
+    /// Indices of the remaining nodes are unchanged. Returns the number of
+    /// bytes freed, as estimated by [`AStarGraph::memory_estimate_bytes`].
+    pub(super) fn gc(&mut self, keep: &[ANodeInd]) -> usize {
+        let bytes_before = self.memory_estimate_bytes();
+
+        // A backward DFS to find all ancestors of `keep`
+        let mut ancestors = FxHashSet::default();
+        let mut dfs_stack = keep.to_vec();
+        while let Some(ind) = dfs_stack.pop() {
+            if ancestors.insert(ind) {
+                let prev = self.prev_edge(ind);
+                dfs_stack.extend(prev.map(|e| e.srcs()).unwrap_or_default());
+            }
+        }
+
+        for (ind, node) in self.nodes.iter_mut().enumerate() {
+            if !ancestors.contains(&ind) {
+                *node = None;
+            } else if let Some(node) = node {
+                // Forget the edges to dropped children
+                node.next.retain(|edge| ancestors.contains(&edge.dst()));
+                node.next.shrink_to_fit();
+            }
+        }
+
+        bytes_before - self.memory_estimate_bytes()
+    }
+
+    /// Check the invariants of the graph, returning the first violation
+    /// found.
+    ///
+    /// Every live node must have an interned value, and its previous edge
+    /// must end at the node and start from live nodes of lower cost. Every
+    /// next edge must start from the node and lead to a live node whose
+    /// previous edge it is. No edge may start and end at the same node, and
+    /// every live node must be reachable from the root.
+    pub(super) fn check_consistency(&self) -> Result<(), String> {
+        let live = |ind: ANodeInd| self.nodes.get(ind).is_some_and(Option::is_some);
+        for (ind, node) in self.nodes.iter().enumerate() {
+            let Some(node) = node else {
+                continue;
+            };
+            if node.value >= self.values.len() {
+                return Err(format!("node {ind} has unknown value id {}", node.value));
+            }
+            let edges = node.prev.iter().chain(&node.next);
+            if let Some(edge) = edges.clone().find(|edge| edge.srcs().contains(&edge.dst())) {
+                return Err(format!("edge {edge:?} of node {ind} is a self-loop"));
+            }
+            if let Some(prev) = &node.prev {
+                if prev.dst() != ind {
+                    return Err(format!(
+                        "previous edge {prev:?} of node {ind} ends elsewhere"
+                    ));
+                }
+                for src in prev.srcs() {
+                    if !live(src) {
+                        return Err(format!("node {ind} has dropped parent {src}"));
+                    }
+                    if self.cost(src) >= node.cost {
+                        return Err(format!(
+                            "parent {src} of node {ind} has cost {}, not lower than {}",
+                            self.cost(src),
+                            node.cost
+                        ));
+                    }
+                }
+            } else if ind != self.root_ind() {
+                return Err(format!("node {ind} has no previous edge"));
+            }
+            for next in &node.next {
+                if !next.srcs().contains(&ind) {
+                    return Err(format!("next edge {next:?} of node {ind} starts elsewhere"));
+                }
+                if !live(next.dst()) || self.node(next.dst()).prev.as_ref() != Some(next) {
+                    return Err(format!(
+                        "next edge {next:?} of node {ind} does not lead to a child"
+                    ));
+                }
+            }
+        }
+        let reachable = self.reachable_from_root(usize::MAX);
+        if let Some(ind) = (0..self.nodes.len()).find(|&ind| live(ind) && !reachable.contains(&ind))
+        {
+            return Err(format!("node {ind} is not reachable from the root"));
+        }
+        Ok(())
+    }
+
+    fn node(&self, ind: ANodeInd) -> &ANode {
+        self.nodes[ind]
+            .as_ref()
+            .expect("node has been garbage collected")
+    }
+
+    fn node_mut(&mut self, ind: ANodeInd) -> &mut ANode {
+        self.nodes[ind]
+            .as_mut()
+            .expect("node has been garbage collected")
+    }
+
+    fn live_nodes(&self) -> impl Iterator<Item = &ANode> {
+        self.nodes.iter().flatten()
+    }
+
+    /// The number of `Op` and `Merge` edges in the graph.
+    pub(super) fn edge_count(&self) -> usize {
+        // Every node but the root has exactly one incoming edge
+        self.live_nodes().filter(|node| node.prev.is_some()).count()
+    }
+
+    /// The number of merges that produced a new node.
+    ///
+    /// If this stays at 0, merges are not contributing to the search.
+    pub(super) fn merge_count(&self) -> usize {
+        self.merge_count
+    }
+
+    /// The maximum cost of any node in the graph.
+    pub(super) fn max_depth(&self) -> usize {
+        self.live_nodes().map(|n| n.cost).max().unwrap_or(0)
+    }
+
+    /// The average cost of the nodes in the graph.
+    pub(super) fn avg_depth(&self) -> f64 {
+        let total: usize = self.live_nodes().map(|n| n.cost).sum();
+        total as f64 / self.node_count() as f64
+    }
+
+    /// All nodes reachable from the root with cost at most `cost_limit`.
+    ///
+    /// Use [`AStarGraph::value`] to enumerate the values reachable within
+    /// `cost_limit` gates.
+    pub(super) fn reachable_from_root(&self, cost_limit: usize) -> FxHashSet<ANodeInd> {
+        let mut reachable = FxHashSet::default();
+        let mut queue = VecDeque::from([self.root_ind()]);
+        while let Some(ind) = queue.pop_front() {
+            if self.cost(ind) > cost_limit || !reachable.insert(ind) {
+                continue;
+            }
+            queue.extend(self.children(ind));
+        }
+        reachable
     }
 
     pub(super) fn is_expanded(&self, ind: ANodeInd) -> bool {
-        !self.nodes[ind].next.is_empty()
+        self.node(ind).expanded
     }
 
-    pub(super) fn add_cx(&mut self, node: ANodeInd, CX { ctrl, tgt }: CX) -> Option<ANodeInd> {
-        // Construct new edge
+    /// Mark `ind` as expanded.
+    ///
+    /// Note that an expanded node may have no children, if all of them were
+    /// already in the graph.
+    pub(super) fn mark_expanded(&mut self, ind: ANodeInd) {
+        self.node_mut(ind).expanded = true;
+    }
+
+    /// Whether the target may still be reachable from `ind`.
+    ///
+    /// A node is inconsistent if some qubit is not complete but no allowed
+    /// move acts on it, so that it can never be fixed.
+    pub(super) fn arc_consistency_check(&self, ind: ANodeInd, target: &V) -> bool {
+        let value = self.values.get(self.node(ind).value);
+        (0..value.n_qubits() as u8).all(|qb| {
+            value.is_complete(qb, target)
+                || self
+                    .allowed_moves
+                    .iter()
+                    .any(|cx| cx.ctrl == qb || cx.tgt == qb)
+        })
+    }
+
+    pub(super) fn add_cx(&mut self, node: ANodeInd, cx: CX) -> Option<ANodeInd> {
+        let new_value = self.values.get(self.node(node).value).cx(cx.ctrl, cx.tgt);
         let edge = AEdge::Op {
-            op: CX { ctrl, tgt },
+            op: cx,
             src: node,
             dst: self.nodes.len(),
         };
+        self.add_gate(node, edge, new_value)
+    }
+
+    pub(super) fn add_swap(&mut self, node: ANodeInd, [q1, q2]: [u8; 2]) -> Option<ANodeInd> {
+        let new_value = self.values.get(self.node(node).value).swap(q1, q2);
+        let edge = AEdge::Swap {
+            qubits: [q1, q2],
+            src: node,
+            dst: self.nodes.len(),
+        };
+        self.add_gate(node, edge, new_value)
+    }
+
+    /// Add the child of `node` reached by the gate `edge`, of value
+    /// `new_value`, unless a path to the value is already known.
+    fn add_gate(&mut self, node: ANodeInd, edge: AEdge, new_value: V) -> Option<ANodeInd> {
+        let (value, _) = self.values.intern(new_value);
+        let path_cost = |g: &Self| g.path_cost(node) + g.edge_cost(&edge);
+        if !self.is_cheapest(value, path_cost) && self.keep_duplicates != Some(value) {
+            return None;
+        }
 
         // Update cost
         let cost = self.cost(node) + 1;
 
         // Update stats, resizing if too small
         let cx_count_per_qb = {
-            let mut cx_count_per_qb = self.nodes[node].stats.cx_count_per_qb.clone();
-            let max_qb = max(ctrl, tgt) as usize;
+            let mut cx_count_per_qb = self.node(node).stats.cx_count_per_qb.clone();
+            let qubits = edge.gate_qubits().expect("not a merge");
+            let max_qb = max(qubits[0], qubits[1]) as usize;
             if cx_count_per_qb.len() <= max_qb {
                 cx_count_per_qb.resize(max_qb + 1, 0);
             }
-            cx_count_per_qb[ctrl as usize] += 1;
-            cx_count_per_qb[tgt as usize] += 1;
+            for qb in qubits {
+                cx_count_per_qb[qb as usize] += 1;
+            }
             cx_count_per_qb
         };
 
-        // Update node value
-        let new_value = {
-            let node_value = self.values.get_by_left(&node).unwrap();
-            node_value.cx(ctrl, tgt)
-        };
-
-        if !self.values.contains_right(&new_value) {
-            let new_node_ind = self.nodes.len();
-            self.values.insert(new_node_ind, new_value);
-            self.nodes
-                .push(ANode::new_child(edge, cost, cx_count_per_qb));
-            self.nodes[node].next.push(edge);
-            Some(new_node_ind)
-        } else {
-            None
-        }
+        let new_node_ind = self.nodes.len();
+        self.nodes
+            .push(Some(ANode::new_child(edge, cost, cx_count_per_qb, value)));
+        self.node_mut(node).next.push(edge);
+        Some(new_node_ind)
     }
 
     pub(super) fn add_merge(
@@ -160,6 +677,25 @@ impl<V: AStarValue> AStarGraph<V> {
         src2: ANodeInd,
         used_qubits: &FxHashSet<u8>,
     ) -> Option<ANodeInd> {
+        // Check for duplicates first, the rest is wasted work for known values
+        let src1_value = self.values.get(self.node(src1).value);
+        let src2_value = self.values.get(self.node(src2).value);
+        // If both values agree on the used qubits, the merge is `src1` itself,
+        // so we know it is a duplicate without computing it
+        let is_noop = used_qubits
+            .iter()
+            .all(|&qb| src2_value.is_complete(qb, src1_value));
+        if is_noop && self.keep_duplicates != Some(self.node(src1).value) {
+            return None;
+        }
+        let gates = self.exclusive_gates(src1, src2);
+        let new_value = src1_value.merge_with_gates(src2_value, used_qubits, &gates);
+        let (value, _) = self.values.intern(new_value);
+        let path_cost = |g: &Self| g.history_cost([src1, src2]);
+        if !self.is_cheapest(value, path_cost) && self.keep_duplicates != Some(value) {
+            return None;
+        }
+
         // Construct new edge
         let edge = AEdge::Merge {
             src1,
@@ -167,12 +703,19 @@ impl<V: AStarValue> AStarGraph<V> {
             dst: self.nodes.len(),
         };
 
-        // Update cost
-        let cost = self.cost(src1) + self.cost(src2);
+        // Update cost, counting the history shared by both sources once
+        let cost = self
+            .ancestor_set([src1, src2])
+            .into_iter()
+            .filter(|&ind| {
+                self.prev_edge(ind)
+                    .is_some_and(|e| e.gate_qubits().is_some())
+            })
+            .count();
 
         // Update stats, resizing if too small
-        let mut cx_count_per_qb = self.nodes[src1].stats.cx_count_per_qb.clone();
-        let cx_count_per_qb2 = &self.nodes[src2].stats.cx_count_per_qb;
+        let mut cx_count_per_qb = self.node(src1).stats.cx_count_per_qb.clone();
+        let cx_count_per_qb2 = &self.node(src2).stats.cx_count_per_qb;
         if cx_count_per_qb.len() <= cx_count_per_qb2.len() {
             cx_count_per_qb.resize(cx_count_per_qb2.len(), 0);
         }
@@ -180,21 +723,29 @@ impl<V: AStarValue> AStarGraph<V> {
             cx_count_per_qb[qb] += count;
         }
 
-        // Update node value
-        let src1_value = self.values.get_by_left(&src1).unwrap();
-        let src2_value = self.values.get_by_left(&src2).unwrap();
-        let new_value = src1_value.merge(src2_value, used_qubits);
-
-        if !self.values.contains_right(&new_value) {
-            let new_node_ind = self.nodes.len();
-            self.values.insert(new_node_ind, new_value);
-            self.nodes
-                .push(ANode::new_child(edge, cost, cx_count_per_qb));
-            self.nodes[src1].next.push(edge);
-            self.nodes[src2].next.push(edge);
-            Some(new_node_ind)
-        } else {
-            None
+        let new_node_ind = self.nodes.len();
+        self.nodes
+            .push(Some(ANode::new_child(edge, cost, cx_count_per_qb, value)));
+        self.node_mut(src1).next.push(edge);
+        self.node_mut(src2).next.push(edge);
+        self.merge_count += 1;
+        Some(new_node_ind)
+    }
+
+    /// Whether a new node should be added for `value`, reached with a path
+    /// of cost `path_cost`.
+    ///
+    /// Values that are new to the graph are added, as are values reached
+    /// more cheaply than before: with merges or move costs, the first path
+    /// found to a value need not be the cheapest.
+    fn is_cheapest(&mut self, value: usize, path_cost: impl FnOnce(&Self) -> f64) -> bool {
+        let path_cost = path_cost(self);
+        match self.value_costs.get(&value) {
+            Some(&cost) if cost <= path_cost => false,
+            _ => {
+                self.value_costs.insert(value, path_cost);
+                true
+            }
         }
     }
 
@@ -202,8 +753,8 @@ impl<V: AStarValue> AStarGraph<V> {
     ///   i) in the past of `top` but
     ///  ii) not in the past of `ind`
     pub(super) fn disallowed_qubits(&self, ind: ANodeInd, top: ANodeInd) -> FxHashSet<u8> {
-        let mut cx_count_per_qb = self.nodes[top].stats.cx_count_per_qb.clone();
-        for (qb, count) in self.nodes[ind].stats.cx_count_per_qb.iter().enumerate() {
+        let mut cx_count_per_qb = self.node(top).stats.cx_count_per_qb.clone();
+        for (qb, count) in self.node(ind).stats.cx_count_per_qb.iter().enumerate() {
             cx_count_per_qb[qb] -= count;
         }
         cx_count_per_qb
@@ -215,23 +766,27 @@ impl<V: AStarValue> AStarGraph<V> {
 }
 
 impl ANode {
-    fn new_root() -> Self {
+    fn new_root(value: usize) -> Self {
         Self {
             prev: None,
             next: vec![],
-            stats: AStarStats {
+            stats: NodeStats {
                 cx_count_per_qb: Vec::new(),
             },
             cost: 0,
+            value,
+            expanded: false,
         }
     }
 
-    fn new_child(prev: AEdge, cost: usize, cx_count_per_qb: Vec<u16>) -> Self {
+    fn new_child(prev: AEdge, cost: usize, cx_count_per_qb: Vec<u16>, value: usize) -> Self {
         Self {
             prev: Some(prev),
             next: vec![],
-            stats: AStarStats { cx_count_per_qb },
+            stats: NodeStats { cx_count_per_qb },
             cost,
+            value,
+            expanded: false,
         }
     }
 }
@@ -240,6 +795,7 @@ impl AEdge {
     fn dst(&self) -> ANodeInd {
         match self {
             &AEdge::Op { dst, .. } => dst,
+            &AEdge::Swap { dst, .. } => dst,
             &AEdge::Merge { dst, .. } => dst,
         }
     }
@@ -247,9 +803,32 @@ impl AEdge {
     pub(super) fn srcs(&self) -> Vec<ANodeInd> {
         match self {
             &AEdge::Op { src, .. } => vec![src],
+            &AEdge::Swap { src, .. } => vec![src],
             &AEdge::Merge { src1, src2, .. } => vec![src1, src2],
         }
     }
+
+    /// The qubits the gate of the edge acts on, or `None` for merges.
+    pub(super) fn gate_qubits(&self) -> Option<[u8; 2]> {
+        match self {
+            &AEdge::Op { op, .. } => Some([op.ctrl, op.tgt]),
+            &AEdge::Swap { qubits, .. } => Some(qubits),
+            AEdge::Merge { .. } => None,
+        }
+    }
+
+    /// The CXs of the gate of the edge, three for a SWAP.
+    fn cxs(&self) -> Vec<CX> {
+        match self {
+            &AEdge::Op { op, .. } => vec![op],
+            &AEdge::Swap {
+                qubits: [q1, q2], ..
+            } => [(q1, q2), (q2, q1), (q1, q2)]
+                .map(|(ctrl, tgt)| CX { ctrl, tgt })
+                .to_vec(),
+            AEdge::Merge { .. } => Vec::new(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -263,11 +842,223 @@ mod tests {
             .add_cx(graph.root_ind(), CX { ctrl: 0, tgt: 1 })
             .unwrap();
         assert_eq!(graph.cost(child), 1);
-        assert_eq!(graph.nodes[child].stats.cx_count_per_qb, vec![1, 1]);
+        assert_eq!(graph.node(child).stats.cx_count_per_qb, vec![1, 1]);
         let grandchild = graph.add_cx(child, CX { ctrl: 0, tgt: 2 }).unwrap();
         assert_eq!(graph.cost(grandchild), 2);
-        assert_eq!(graph.nodes[grandchild].stats.cx_count_per_qb, vec![2, 1, 1]);
+        assert_eq!(graph.node(grandchild).stats.cx_count_per_qb, vec![2, 1, 1]);
+        assert_eq!(graph.check_consistency(), Ok(()));
+    }
+
+    #[test]
+    fn test_depth_stats() {
+        let mut graph = AStarGraph::new([false; 5], []);
+        assert_eq!(graph.max_depth(), 0);
+        let child = graph
+            .add_cx(graph.root_ind(), CX { ctrl: 0, tgt: 1 })
+            .unwrap();
+        graph.add_cx(child, CX { ctrl: 2, tgt: 3 }).unwrap();
+        assert_eq!(graph.node_count(), 3);
+        assert_eq!(graph.edge_count(), 2);
+        assert_eq!(graph.max_depth(), 2);
+        assert_eq!(graph.avg_depth(), 1.);
+    }
+
+    #[test]
+    fn test_path_cost() {
+        let mut graph = AStarGraph::new([false; 5], []);
+        let child = graph
+            .add_cx(graph.root_ind(), CX { ctrl: 0, tgt: 1 })
+            .unwrap();
+        let grandchild = graph.add_cx(child, CX { ctrl: 2, tgt: 3 }).unwrap();
+        assert_eq!(graph.path_cost(grandchild), graph.cost(grandchild) as f64);
+        graph.set_move_costs([(CX { ctrl: 2, tgt: 3 }, 5.5)]);
+        assert_eq!(graph.path_cost(child), 1.);
+        assert_eq!(graph.path_cost(grandchild), 6.5);
+        assert_eq!(graph.cost(grandchild), 2);
+    }
+
+    #[test]
+    fn test_path_cost_of_merge() {
+        let mut graph = AStarGraph::new([false; 5], []);
+        let child = graph
+            .add_cx(graph.root_ind(), CX { ctrl: 0, tgt: 1 })
+            .unwrap();
+        let grandchild1 = graph.add_cx(child, CX { ctrl: 1, tgt: 2 }).unwrap();
+        let grandchild2 = graph.add_cx(child, CX { ctrl: 3, tgt: 4 }).unwrap();
+        let merged = graph
+            .add_merge(grandchild1, grandchild2, &FxHashSet::from_iter([3, 4]))
+            .unwrap();
+        // `CX(0, 1)` is in the history of both sources, but applied once
+        assert_eq!(graph.cost(merged), 3);
+        assert_eq!(graph.path(merged).len(), 3);
+        assert_eq!(graph.path_cost(merged), 3.);
+    }
+
+    #[test]
+    fn test_reachable_from_root() {
+        let mut graph = AStarGraph::new([false; 5], []);
+        let child = graph
+            .add_cx(graph.root_ind(), CX { ctrl: 0, tgt: 1 })
+            .unwrap();
+        let grandchild = graph.add_cx(child, CX { ctrl: 2, tgt: 3 }).unwrap();
+        assert_eq!(
+            graph.reachable_from_root(1),
+            FxHashSet::from_iter([graph.root_ind(), child])
+        );
+        assert!(graph.reachable_from_root(2).contains(&grandchild));
+        assert_eq!(graph.check_consistency(), Ok(()));
+    }
+
+    #[test]
+    fn test_arc_consistency_check() {
+        let graph = AStarGraph::new([false; 5], [CX { ctrl: 0, tgt: 1 }]);
+        let mut target = [false; 5];
+        target[1] = true;
+        assert!(graph.arc_consistency_check(graph.root_ind(), &target));
+        target[3] = true;
+        assert!(!graph.arc_consistency_check(graph.root_ind(), &target));
+    }
+
+    #[test]
+    fn test_add_noop_merge() {
+        let mut graph = AStarGraph::new([false; 5], []);
+        let child1 = graph
+            .add_cx(graph.root_ind(), CX { ctrl: 0, tgt: 1 })
+            .unwrap();
+        let child2 = graph
+            .add_cx(graph.root_ind(), CX { ctrl: 2, tgt: 3 })
+            .unwrap();
+        // Qubit 4 is unchanged in both values, so merging it is a no-op
+        assert_eq!(
+            graph.add_merge(child1, child2, &FxHashSet::from_iter([4])),
+            None
+        );
+    }
+
+    #[test]
+    fn test_merge_carries_phases() {
+        use crate::stab_state::StabiliserState;
+
+        // A CX along an edge of a graph state flips the phases of its
+        // stabilisers
+        let state = StabiliserState::<4>::graph_state(&[(0, 1), (2, 3)]);
+        let (cx1, cx2) = (CX { ctrl: 0, tgt: 1 }, CX { ctrl: 2, tgt: 3 });
+        let mut graph = AStarGraph::new(state.clone(), []);
+        let child1 = graph.add_cx(graph.root_ind(), cx1).unwrap();
+        let child2 = graph.add_cx(graph.root_ind(), cx2).unwrap();
+        let expected = state.cx(0, 1).cx(2, 3);
+        // Copying the rows of the merged qubits misses the phase flips of `cx2`
+        let (value1, value2) = (graph.value(child1).unwrap(), graph.value(child2).unwrap());
+        assert_ne!(
+            value1.merge(value2, &FxHashSet::from_iter([2, 3])),
+            expected
+        );
+        let merged = graph
+            .add_merge(child1, child2, &FxHashSet::from_iter([2, 3]))
+            .unwrap();
+        assert_eq!(graph.value(merged).unwrap(), &expected);
+    }
+
+    #[test]
+    fn test_get_all_solutions() {
+        let (cx1, cx2, cx3) = (
+            CX { ctrl: 0, tgt: 1 },
+            CX { ctrl: 1, tgt: 4 },
+            CX { ctrl: 2, tgt: 3 },
+        );
+        let mut graph = AStarGraph::new([false; 5], []);
+        let child1 = graph.add_cx(graph.root_ind(), cx1).unwrap();
+        let grandchild1 = graph.add_cx(child1, cx2).unwrap();
+        let child2 = graph.add_cx(graph.root_ind(), cx3).unwrap();
+        let merged = graph
+            .add_merge(grandchild1, child2, &FxHashSet::from_iter([2, 3]))
+            .unwrap();
+
+        // `cx3` can go before, between or after the other two gates
+        assert_eq!(
+            graph.get_all_solutions(merged),
+            vec![
+                vec![cx1, cx2, cx3],
+                vec![cx1, cx3, cx2],
+                vec![cx3, cx1, cx2]
+            ]
+        );
+        assert_eq!(graph.get_all_solutions(grandchild1), vec![vec![cx1, cx2]]);
+        assert_eq!(graph.get_all_solutions(graph.root_ind()), vec![vec![]]);
+        assert_eq!(graph.check_consistency(), Ok(()));
     }
+
+    #[test]
+    fn test_gc() {
+        let (cx1, cx2, cx3) = (
+            CX { ctrl: 0, tgt: 1 },
+            CX { ctrl: 1, tgt: 4 },
+            CX { ctrl: 2, tgt: 3 },
+        );
+        let mut graph = AStarGraph::new([false; 5], []);
+        let child1 = graph.add_cx(graph.root_ind(), cx1).unwrap();
+        let grandchild1 = graph.add_cx(child1, cx2).unwrap();
+        let child2 = graph.add_cx(graph.root_ind(), cx3).unwrap();
+        let merged = graph
+            .add_merge(grandchild1, child2, &FxHashSet::from_iter([2, 3]))
+            .unwrap();
+        // A dead end, not on the way to `merged`
+        let child3 = graph
+            .add_cx(graph.root_ind(), CX { ctrl: 3, tgt: 4 })
+            .unwrap();
+        let grandchild3 = graph.add_cx(child3, cx1).unwrap();
+        assert_eq!(graph.node_count(), 7);
+        let path = graph.path(merged);
+        let memory = graph.memory_estimate_bytes();
+
+        let freed = graph.gc(&[merged]);
+        assert_eq!(graph.node_count(), 5);
+        assert_eq!(graph.value(child3), None);
+        assert_eq!(graph.value(grandchild3), None);
+        assert_eq!(graph.path(merged), path);
+        assert_eq!(
+            graph.children(graph.root_ind()).collect::<Vec<_>>(),
+            vec![child1, child2]
+        );
+        assert!(freed > 0);
+        assert_eq!(graph.memory_estimate_bytes(), memory - freed);
+        assert_eq!(graph.check_consistency(), Ok(()));
+    }
+
+    #[test]
+    fn test_check_consistency() {
+        let mut graph = AStarGraph::new([false; 5], []);
+        let child = graph
+            .add_cx(graph.root_ind(), CX { ctrl: 0, tgt: 1 })
+            .unwrap();
+        let grandchild = graph.add_cx(child, CX { ctrl: 2, tgt: 3 }).unwrap();
+        assert_eq!(graph.check_consistency(), Ok(()));
+
+        let mut broken = AStarGraph::new([false; 5], []);
+        broken.nodes = graph.nodes.clone();
+        broken.values = Interner::new();
+        assert!(broken.check_consistency().is_err());
+
+        let mut broken = AStarGraph::new([false; 5], []);
+        broken.values = graph.values;
+        broken.nodes = graph.nodes.clone();
+        broken.node_mut(grandchild).cost = 1;
+        assert_eq!(
+            broken.check_consistency(),
+            Err(format!(
+                "parent {child} of node {grandchild} has cost 1, not lower than 1"
+            ))
+        );
+        broken.nodes = graph.nodes.clone();
+        broken.node_mut(child).next.clear();
+        broken.nodes[grandchild] = None;
+        assert_eq!(broken.check_consistency(), Ok(()));
+        broken.nodes = graph.nodes;
+        broken.nodes[child] = None;
+        let error = broken.check_consistency().unwrap_err();
+        assert!(error.ends_with("of node 0 does not lead to a child"));
+    }
+
     #[test]
     fn test_disallowed_qubits() {
         let mut graph = AStarGraph::new([false; 5], []);
@@ -280,6 +1071,7 @@ mod tests {
         let grandchild = graph
             .add_merge(child1, child2, &FxHashSet::from_iter([2, 3]))
             .unwrap();
+        assert_eq!(graph.merge_count(), 1);
         assert_eq!(
             graph.disallowed_qubits(child1, grandchild),
             FxHashSet::from_iter([2, 3])
@@ -0,0 +1,72 @@
+use std::hash::{BuildHasher, Hash};
+
+use fxhash::FxBuildHasher;
+use hashbrown::HashTable;
+
+/// A hash-consing store for A* values.
+///
+/// Every unique value is assigned a `usize` id, so that the search graph only
+/// needs to store ids instead of full values. Each value is stored once: the
+/// hash table only holds ids, hashed through the values they refer to.
+#[derive(Debug)]
+pub(super) struct Interner<V> {
+    /// The ids of the values, hashed by value
+    ids: HashTable<usize>,
+    /// The values, indexed by id
+    values: Vec<V>,
+    hasher: FxBuildHasher,
+}
+
+impl<V: Hash + Eq> Interner<V> {
+    pub(super) fn new() -> Self {
+        Self {
+            ids: HashTable::new(),
+            values: Vec::new(),
+            hasher: FxBuildHasher::default(),
+        }
+    }
+
+    /// Intern `value`, returning its id.
+    ///
+    /// The boolean is `true` if `value` had not been interned before.
+    pub(super) fn intern(&mut self, value: V) -> (usize, bool) {
+        let hash = self.hasher.hash_one(&value);
+        if let Some(&id) = self.ids.find(hash, |&id| self.values[id] == value) {
+            return (id, false);
+        }
+        let id = self.values.len();
+        self.values.push(value);
+        let Self {
+            ids,
+            values,
+            hasher,
+        } = self;
+        ids.insert_unique(hash, id, |&id| hasher.hash_one(&values[id]));
+        (id, true)
+    }
+
+    /// The value with the given id.
+    pub(super) fn get(&self, id: usize) -> &V {
+        &self.values[id]
+    }
+
+    /// The number of values interned.
+    pub(super) fn len(&self) -> usize {
+        self.values.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern() {
+        let mut interner = Interner::new();
+        assert_eq!(interner.intern([true, false]), (0, true));
+        assert_eq!(interner.intern([false, false]), (1, true));
+        assert_eq!(interner.intern([true, false]), (0, false));
+        assert_eq!(interner.get(1), &[false, false]);
+        assert_eq!(interner.len(), 2);
+    }
+}
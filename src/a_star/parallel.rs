@@ -0,0 +1,231 @@
+//! A parallel A* search, distributing node expansions over worker threads.
+//!
+//! The coordinator thread owns the priority queue and the search graph. It
+//! sends the most promising nodes to a shared work channel, from which idle
+//! workers take them, compute their children along with the heuristic, and
+//! send them back. Unlike [`super::a_star`], only CX edges are considered.
+
+use std::thread;
+
+use crossbeam::channel::{self, Receiver, Sender};
+use fxhash::FxHashMap;
+
+use super::{AStarValue, PQCost, PQ};
+use crate::CX;
+
+/// The number of nodes sent to the workers per thread and per round.
+///
+/// Larger batches keep the workers busy but expand nodes that a sequential
+/// search would have skipped.
+const BATCH_SIZE_PER_THREAD: usize = 4;
+
+/// A node expansion: the children of a node, with their distance to the target
+/// and their secondary cost.
+type Expansion<V> = (usize, Vec<(CX, V, usize, usize)>);
+
+/// A node of the parallel search graph
+struct PNode<V> {
+    value: V,
+    /// The cost of the cheapest known path from the root
+    cost: usize,
+    /// The parent on the cheapest known path and the CX leading to this node
+    prev: Option<(usize, CX)>,
+}
+
+/// Find a shortest sequence of `moves` that maps `start` to `target`, using
+/// `n_threads` worker threads.
+pub fn parallel_a_star<V: AStarValue + Send + Sync>(
+    start: V,
+    target: &V,
+    moves: Vec<CX>,
+    max_depth: Option<usize>,
+    n_threads: usize,
+) -> Option<Vec<CX>> {
+    assert!(n_threads > 0, "at least one worker thread is required");
+    let (work_tx, work_rx) = channel::unbounded::<(usize, V)>();
+    let (result_tx, result_rx) = channel::unbounded::<Expansion<V>>();
+
+    thread::scope(|scope| {
+        for _ in 0..n_threads {
+            let work_rx = work_rx.clone();
+            let result_tx = result_tx.clone();
+            let moves = &moves;
+            scope.spawn(move || expand_worker(work_rx, result_tx, moves, target));
+        }
+        // Only the workers may send results, so that the channel is closed
+        // if they all stop
+        drop(result_tx);
+
+        let solution = coordinate(
+            start,
+            target,
+            max_depth,
+            n_threads * BATCH_SIZE_PER_THREAD,
+            &work_tx,
+            &result_rx,
+        );
+        // Closing the work channel stops the workers
+        drop(work_tx);
+        solution
+    })
+}
+
+/// Expand the nodes received on `work_rx` until the channel is closed.
+fn expand_worker<V: AStarValue>(
+    work_rx: Receiver<(usize, V)>,
+    result_tx: Sender<Expansion<V>>,
+    moves: &[CX],
+    target: &V,
+) {
+    for (ind, value) in work_rx {
+        let children = moves
+            .iter()
+            .map(|&cx| {
+                let child = value.cx(cx.ctrl, cx.tgt);
+                let dist = child.dist(target);
+                let secondary_cost = child.secondary_cost(target);
+                (cx, child, dist, secondary_cost)
+            })
+            .collect();
+        if result_tx.send((ind, children)).is_err() {
+            break;
+        }
+    }
+}
+
+/// Run the A* main loop, sending nodes to expand to the workers.
+fn coordinate<V: AStarValue>(
+    start: V,
+    target: &V,
+    max_depth: Option<usize>,
+    batch_size: usize,
+    work_tx: &Sender<(usize, V)>,
+    result_rx: &Receiver<Expansion<V>>,
+) -> Option<Vec<CX>> {
+    let mut pq = PQ::new();
+    pq.push(
+        0,
+        PQCost::new(start.dist(target) as f64, 0, start.secondary_cost(target)),
+    );
+    let mut ids = FxHashMap::from_iter([(start.clone(), 0)]);
+    let mut nodes = vec![PNode {
+        value: start,
+        cost: 0,
+        prev: None,
+    }];
+    // The node of the best solution found so far
+    let mut best = (nodes[0].value == *target).then_some(0);
+
+    loop {
+        // Send a batch of the most promising nodes to the workers
+        let mut n_sent = 0;
+        while n_sent < batch_size {
+            let Some((ind, prio)) = pq.pop() else {
+                break;
+            };
+            if best.is_some_and(|best| prio.cost() >= nodes[best].cost as f64) {
+                // No further solution will be cheaper
+                pq.clear();
+                break;
+            }
+            if max_depth.is_some_and(|max_depth| nodes[ind].cost >= max_depth) {
+                continue;
+            }
+            work_tx
+                .send((ind, nodes[ind].value.clone()))
+                .expect("workers stopped early");
+            n_sent += 1;
+        }
+        if n_sent == 0 {
+            break;
+        }
+
+        // Add the children to the graph
+        for _ in 0..n_sent {
+            let (ind, children) = result_rx.recv().expect("workers stopped early");
+            let cost = nodes[ind].cost + 1;
+            for (cx, child, dist, secondary_cost) in children {
+                let child_ind = match ids.get(&child) {
+                    Some(&id) if nodes[id].cost <= cost => continue,
+                    Some(&id) => {
+                        // Found a cheaper path to a known node
+                        nodes[id].cost = cost;
+                        nodes[id].prev = Some((ind, cx));
+                        id
+                    }
+                    None => {
+                        let id = nodes.len();
+                        ids.insert(child.clone(), id);
+                        nodes.push(PNode {
+                            value: child,
+                            cost,
+                            prev: Some((ind, cx)),
+                        });
+                        id
+                    }
+                };
+                if nodes[child_ind].value == *target
+                    && best.is_none_or(|best| cost < nodes[best].cost)
+                {
+                    best = Some(child_ind);
+                }
+                pq.push(
+                    child_ind,
+                    PQCost::new((cost + dist) as f64, cost, secondary_cost),
+                );
+            }
+        }
+    }
+
+    best.map(|best| path(&nodes, best))
+}
+
+/// The CXs on the cheapest known path from the root to `ind`.
+fn path<V>(nodes: &[PNode<V>], mut ind: usize) -> Vec<CX> {
+    let mut path = Vec::new();
+    while let Some((prev, cx)) = nodes[ind].prev {
+        path.push(cx);
+        ind = prev;
+    }
+    path.reverse();
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        a_star::a_star,
+        cx_circuit::{CXCircuit, CXCircuit16},
+    };
+
+    #[test]
+    fn test_parallel_a_star() {
+        let moves: Vec<CX> = (0..4)
+            .flat_map(|i| [(i, i + 1), (i + 1, i)])
+            .map(CX::from)
+            .collect();
+        let target = CXCircuit16::from_cxs([(0, 1), (2, 3), (1, 2), (4, 3)]);
+        let expected = a_star(CXCircuit16::new(), &target, moves.clone(), None, None).unwrap();
+        for n_threads in [1, 4] {
+            let solution =
+                parallel_a_star(CXCircuit16::new(), &target, moves.clone(), None, n_threads)
+                    .unwrap();
+            assert_eq!(solution.len(), expected.len());
+            let result = solution
+                .iter()
+                .fold(CXCircuit16::new(), |circ, cx| circ.cx(cx.ctrl, cx.tgt));
+            assert_eq!(result, target);
+        }
+    }
+
+    #[test]
+    fn test_parallel_a_star_max_depth() {
+        let moves = vec![CX { ctrl: 0, tgt: 1 }, CX { ctrl: 1, tgt: 2 }];
+        let target = CXCircuit16::from_cxs([(0, 1), (1, 2)]);
+        assert_eq!(
+            parallel_a_star(CXCircuit16::new(), &target, moves, Some(1), 2),
+            None
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! Pattern databases: lower bounds on the CX count, precomputed by a search
+//! on a few columns of the circuit.
+//!
+//! A CX gate adds a row of the circuit matrix to another, so it acts on any
+//! subset of the columns independently of the others. The number of CXs
+//! needed to reach a circuit is therefore at least the number needed to reach
+//! its restriction to these columns, which is small enough to be looked up
+//! in a table.
+//!
+//! The databases are only available from the library, as the heuristic of
+//! [`super::AStarBuilder::heuristic`]; the command line search always uses
+//! [`AStarValue::dist`].
+
+use fxhash::FxHashMap;
+
+use super::AStarValue;
+use crate::{
+    cx_circuit::{CXCircuit, CXCircuit16},
+    CX,
+};
+
+/// The depth of the search of [`PatternDatabase::build`].
+pub const DEFAULT_DEPTH: usize = 4;
+
+/// The maximum number of qubits of a pattern database.
+pub const MAX_QUBITS: usize = 4;
+
+/// The number of CXs needed to reach each restriction of a circuit to the
+/// columns of a few qubits, up to a maximum depth.
+#[derive(Clone, Debug)]
+pub struct PatternDatabase {
+    /// The columns of the patterns
+    qubits: Vec<u8>,
+    /// The CX count of every pattern reached within `depth` CXs. Row `i` of
+    /// a pattern is stored in bits `4i..4i + 4`.
+    distances: FxHashMap<u64, usize>,
+    depth: usize,
+}
+
+impl PatternDatabase {
+    /// The pattern database of `qubits` with `moves`, searched up to
+    /// [`DEFAULT_DEPTH`] CXs.
+    pub fn build(moves: &[CX], qubits: &[u8]) -> Self {
+        Self::build_to_depth(moves, qubits, DEFAULT_DEPTH)
+    }
+
+    /// The pattern database of `qubits` with `moves`, searched up to `depth`
+    /// CXs.
+    ///
+    /// Panics if there are more than [`MAX_QUBITS`] qubits.
+    pub fn build_to_depth(moves: &[CX], qubits: &[u8], depth: usize) -> Self {
+        assert!(qubits.len() <= MAX_QUBITS, "too many qubits in pattern");
+        let root = pattern(&CXCircuit16::new(), qubits);
+        let mut distances = FxHashMap::from_iter([(root, 0)]);
+        let mut frontier = vec![root];
+        for dist in 1..=depth {
+            let mut next_frontier = Vec::new();
+            for &pattern in &frontier {
+                for cx in moves {
+                    let ctrl_row = (pattern >> (4 * cx.ctrl)) & 0xf;
+                    if ctrl_row == 0 {
+                        // The CX does not change the pattern
+                        continue;
+                    }
+                    let child = pattern ^ (ctrl_row << (4 * cx.tgt));
+                    distances.entry(child).or_insert_with(|| {
+                        next_frontier.push(child);
+                        dist
+                    });
+                }
+            }
+            frontier = next_frontier;
+        }
+        Self {
+            qubits: qubits.to_vec(),
+            distances,
+            depth,
+        }
+    }
+
+    /// A lower bound on the number of CXs needed to build `remaining`.
+    ///
+    /// To map `circuit` to `target`, this is `target * circuit^-1`. The bound
+    /// is `depth + 1` for the patterns that were not reached.
+    pub fn lower_bound(&self, remaining: &CXCircuit16) -> usize {
+        let pattern = pattern(remaining, &self.qubits);
+        self.distances
+            .get(&pattern)
+            .copied()
+            .unwrap_or(self.depth + 1)
+    }
+}
+
+/// The largest lower bound of [`AStarValue::dist`] and the `databases`, to
+/// be used as the heuristic of [`super::AStarBuilder::heuristic`].
+pub fn max_lower_bound(
+    databases: &[PatternDatabase],
+    circuit: &CXCircuit16,
+    target: &CXCircuit16,
+) -> usize {
+    // The CXs applied to `circuit` form the circuit `target * circuit^-1`
+    let remaining = target.mult(&circuit.inverse());
+    databases
+        .iter()
+        .map(|database| database.lower_bound(&remaining))
+        .fold(circuit.dist(target), usize::max)
+}
+
+/// The columns `qubits` of `circuit`, packed in four bits per row.
+fn pattern(circuit: &CXCircuit16, qubits: &[u8]) -> u64 {
+    (0..16).fold(0, |pattern, i| {
+        let row = circuit.row(i);
+        let bits = qubits
+            .iter()
+            .enumerate()
+            .filter(|&(_, &qb)| row & (1 << qb) != 0)
+            .fold(0, |bits, (j, _)| bits | (1 << j));
+        pattern | (bits << (4 * i))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{a_star::AStarBuilder, Topology};
+
+    #[test]
+    fn test_lower_bound() {
+        let moves = Topology::linear(6).to_cx_moves();
+        let databases =
+            [[0, 1, 2, 3], [2, 3, 4, 5]].map(|qubits| PatternDatabase::build(&moves, &qubits));
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut is_tighter = false;
+        for n_gates in 0..6 {
+            let target = CXCircuit16::random(n_gates, &moves, &mut rng);
+            let start = CXCircuit16::new();
+            let (solutions, _) = AStarBuilder::new(start, &target, moves.clone()).run();
+            let optimal = solutions[0].len();
+
+            let bound = max_lower_bound(&databases, &start, &target);
+            assert!(bound >= start.dist(&target));
+            assert!(bound <= optimal);
+            is_tighter |= bound > start.dist(&target);
+            // The bound also holds half-way along an optimal solution
+            let middle = solutions[0][..optimal / 2]
+                .iter()
+                .fold(start, |circ, cx| circ.cx(cx.ctrl, cx.tgt));
+            assert!(max_lower_bound(&databases, &middle, &target) <= optimal - optimal / 2);
+        }
+        assert!(is_tighter);
+    }
+
+    #[test]
+    fn test_a_star_with_pattern_databases() {
+        let moves = Topology::linear(5).to_cx_moves();
+        let databases = [PatternDatabase::build(&moves, &[0, 1, 2, 3])];
+        let target = CXCircuit16::from_cxs([(0, 1), (1, 2), (2, 3), (3, 4), (1, 0)]);
+        let (solutions, stats) = AStarBuilder::new(CXCircuit16::new(), &target, moves.clone())
+            .heuristic(|circuit, target| max_lower_bound(&databases, circuit, target))
+            .run();
+        let (expected, row_count_stats) =
+            AStarBuilder::new(CXCircuit16::new(), &target, moves).run();
+        assert_eq!(solutions[0].len(), expected[0].len());
+        assert!(stats.expansions <= row_count_stats.expansions);
+    }
+}
@@ -0,0 +1,47 @@
+//! JSON reports with search statistics, to compare performance across changes.
+
+use std::{fs::File, io};
+
+use serde::Serialize;
+
+use crate::a_star::SearchStats;
+
+/// The time spent in each phase of a run, in seconds.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct PhaseTimings {
+    pub parsing: f64,
+    pub search: f64,
+    pub verification: f64,
+    pub writing: f64,
+}
+
+/// A benchmark report for a single run.
+#[derive(Clone, Debug, Serialize)]
+pub struct BenchmarkReport {
+    /// The commit the binary was built from, if known
+    pub git_commit: Option<String>,
+    /// The search algorithm used
+    pub algorithm: String,
+    /// The number of allowed moves
+    pub n_moves: usize,
+    /// The heuristic distance from the source to the target
+    pub initial_dist: usize,
+    /// The CX count of the best solution found, if any
+    pub solution_cx_count: Option<usize>,
+    pub timings: PhaseTimings,
+    /// Search statistics, if supported by the algorithm
+    pub search_stats: Option<SearchStats>,
+}
+
+impl BenchmarkReport {
+    /// Write the report to `file` as JSON.
+    pub fn write(&self, file: &mut File) -> io::Result<()> {
+        serde_json::to_writer_pretty(file, self).map_err(io::Error::other)
+    }
+}
+
+/// The hash of the git commit the binary was built from, if it was built
+/// from a git repository.
+pub fn git_commit_hash() -> Option<String> {
+    option_env!("GIT_COMMIT_HASH").map(String::from)
+}
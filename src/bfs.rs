@@ -1,7 +1,16 @@
+use std::{io, path::Path};
+
 use fxhash::{FxHashMap, FxHashSet};
 
 use crate::{cx_circuit::CXCircuit, CircMoves, Moves};
 use rayon::prelude::*;
+use symmetry::{canonical, invert_permutation};
+
+pub use symmetry::topology_automorphisms;
+
+mod checkpoint;
+pub mod pareto;
+mod symmetry;
 
 // const PRIME: usize = 10000007;
 
@@ -10,18 +19,98 @@ struct BFS<'m, T> {
     moves: &'m Moves<T>,
     /// Map from CX count to circuits
     /// At CX count of 0: just the identity circuit
+    ///
+    /// Circuits are stored in their canonical form under `symmetries`.
     cx_count_circs: Vec<CircMoves<T>>,
+    /// Qubit permutations that leave the moves and the start circuit invariant
+    symmetries: Vec<Vec<usize>>,
+    /// If set, the circuit to reach and the maximum depth to reach it by.
+    ///
+    /// Circuits from which the goal cannot be reached in time are skipped.
+    goal: Option<(T, usize)>,
+    /// The qubits whose columns can be changed by a move, as a bitmask
+    movable_qubits: u64,
 }
 
 impl<'m, T: CXCircuit> BFS<'m, T> {
     fn new(start_circ: T, moves: &'m Moves<T>) -> Self {
         let cx_count_circs = vec![CircMoves::from_iter([(start_circ, usize::MAX)])];
+        // Moves are stored transposed: the column they change is a row here
+        let movable_qubits = moves.iter().fold(0, |mask, mv| {
+            mask | mv.transpose().column_diff_mask(&T::new())
+        });
         Self {
             moves,
             cx_count_circs,
+            symmetries: Vec::new(),
+            goal: None,
+            movable_qubits,
         }
     }
 
+    /// Skip circuits from which `goal` cannot be reached within `max_depth`
+    /// moves of the start circuit.
+    ///
+    /// Only valid if the BFS is used to find paths to `goal`: the layers will
+    /// not contain every circuit at a given depth.
+    fn forward_checking(&mut self, goal: T, max_depth: usize) {
+        self.goal = Some((goal, max_depth));
+    }
+
+    /// Whether the goal may be reachable from `circ`, which is at `depth`.
+    ///
+    /// Every column that differs from the goal must be fixed by a move acting
+    /// on that qubit, and each move fixes at most one column. As `circ` is a
+    /// canonical form, it suffices for any circuit in its orbit to pass.
+    fn can_reach_goal(&self, circ: &T, depth: usize) -> bool {
+        let Some((goal, max_depth)) = self.goal else {
+            return true;
+        };
+        let remaining = max_depth.saturating_sub(depth);
+        let images = self
+            .symmetries
+            .iter()
+            .map(|perm| circ.apply_permutation(perm));
+        images.chain([*circ]).any(|circ| {
+            let diff = circ.column_diff_mask(&goal);
+            diff & !self.movable_qubits == 0 && diff.count_ones() as usize <= remaining
+        })
+    }
+
+    /// A BFS that only explores one circuit per orbit of `symmetries`.
+    ///
+    /// Only the symmetries that leave `start_circ` invariant are used.
+    fn with_symmetries(start_circ: T, moves: &'m Moves<T>, symmetries: Vec<Vec<usize>>) -> Self {
+        let mut bfs = Self::new(start_circ, moves);
+        bfs.symmetries = symmetries
+            .into_iter()
+            .filter(|perm| start_circ.apply_permutation(perm) == start_circ)
+            .collect();
+        bfs
+    }
+
+    /// The canonical form of `circ`, used as key in `cx_count_circs`.
+    fn canonical(&self, circ: &T) -> T {
+        canonical(circ, &self.symmetries).0
+    }
+
+    /// All circuits that are equivalent to `circs` under the symmetries.
+    fn orbits<'a>(&self, circs: impl IntoIterator<Item = &'a T>) -> FxHashSet<T>
+    where
+        T: 'a,
+    {
+        circs
+            .into_iter()
+            .flat_map(|circ| {
+                let images = self
+                    .symmetries
+                    .iter()
+                    .map(|perm| circ.apply_permutation(perm));
+                images.chain([*circ])
+            })
+            .collect()
+    }
+
     /// Apply the valid moves to every circuit reached in the previous step.
     ///
     /// Returns the newly discovered circuits
@@ -34,9 +123,14 @@ impl<'m, T: CXCircuit> BFS<'m, T> {
             }
             frontiers
         };
-        let new_moves = collect_moves(&frontiers[0], self.moves, |circ| {
-            !frontiers.iter().any(|f| f.contains_key(circ))
-        });
+        let new_moves = collect_moves(
+            &frontiers[0],
+            self.moves,
+            |circ| self.canonical(circ),
+            |circ| {
+                !frontiers.iter().any(|f| f.contains_key(circ)) && self.can_reach_goal(circ, depth)
+            },
+        );
         println!("With {} CX gates: {} circuits", depth, new_moves.len());
         let new_circs = new_moves.keys().copied().collect();
         self.cx_count_circs.push(new_moves);
@@ -47,11 +141,16 @@ impl<'m, T: CXCircuit> BFS<'m, T> {
         let mut moves = Vec::new();
         let mut curr = *circ;
         for curr_depth in (1..self.cx_count_circs.len()).rev() {
-            let Some(move_id) = self.cx_count_circs[curr_depth].get(&curr).copied() else {
+            let (canon, perm) = canonical(&curr, &self.symmetries);
+            let Some(move_id) = self.cx_count_circs[curr_depth].get(&canon).copied() else {
                 // It's possible that the circuit is not at the highest depth, in which case
                 // we hope to find it in a future iteration
                 continue;
             };
+            let move_id = match perm {
+                None if self.symmetries.is_empty() => move_id,
+                _ => self.relabel_move(&canon, perm, move_id, curr_depth),
+            };
             moves.push(move_id);
             let mv = self
                 .moves
@@ -67,19 +166,70 @@ impl<'m, T: CXCircuit> BFS<'m, T> {
         moves
     }
 
+    /// Find the move that leads to the circuit with canonical form `canon`.
+    ///
+    /// `canon` is the image of the current circuit under `perm`, and was
+    /// reached by applying move `move_id` to the image of a circuit at
+    /// `depth - 1` under some symmetry. Undo both relabellings to recover
+    /// the move that applies to the current circuit.
+    fn relabel_move(
+        &self,
+        canon: &T,
+        perm: Option<&[usize]>,
+        move_id: usize,
+        depth: usize,
+    ) -> usize {
+        let mv = self.moves[move_id];
+        let identity = Vec::new();
+        let sigma = [&identity]
+            .into_iter()
+            .chain(&self.symmetries)
+            .find(|sigma| {
+                let prev = canon.apply_permutation(&invert_permutation(sigma));
+                self.cx_count_circs[depth - 1].contains_key(&prev.mult_transpose(&mv))
+            })
+            .expect("invalid backtracking: no symmetry leads to the previous depth");
+        let mut real_move = mv.apply_permutation(sigma);
+        if let Some(perm) = perm {
+            real_move = real_move.apply_permutation(&invert_permutation(perm));
+        }
+        self.moves
+            .iter()
+            .position(|&mv| mv == real_move)
+            .expect("symmetries must map moves to moves")
+    }
+
     fn depth(&self) -> usize {
         self.cx_count_circs.len() - 1
     }
+
+    /// Check that `solution` is a shortest sequence of moves to `target`.
+    ///
+    /// Checks that `solution` maps the start circuit to `target`, and that
+    /// `target` was not reached at a lower depth. Requires the BFS to have
+    /// been run up to at least `solution.len()`.
+    fn assert_optimal(&self, solution: &[usize], target: &T) -> bool {
+        // Moves are listed from the target back to the start circuit
+        let start = solution
+            .iter()
+            .fold(*target, |circ, &mv| circ.mult_transpose(&self.moves[mv]));
+        let canonical_target = self.canonical(target);
+        let reached_earlier = self.cx_count_circs[..solution.len()]
+            .iter()
+            .any(|circs| circs.contains_key(&canonical_target));
+        self.cx_count_circs[0].contains_key(&start) && !reached_earlier
+    }
 }
 
 /// Breadth-first search, starting from identity circuit.
-#[cfg(test)]
 pub fn bfs<T: CXCircuit>(target_circ: T, moves: &Moves<T>, max_steps: usize) -> Option<Vec<usize>> {
     let mut bfs = BFS::new(T::new(), moves);
+    bfs.forward_checking(target_circ, max_steps);
     for _ in 1..=max_steps {
         let frontier = bfs.step();
         if frontier.contains(&target_circ) {
             let moves = Vec::from_iter(bfs.backtrack(&target_circ));
+            debug_assert!(bfs.assert_optimal(&moves, &target_circ));
             return Some(moves);
         }
     }
@@ -88,50 +238,156 @@ pub fn bfs<T: CXCircuit>(target_circ: T, moves: &Moves<T>, max_steps: usize) ->
 
 /// Breadth-first search, starting from both ends and meet in the middle.
 ///
+/// The moves of the solution, applied after `source`, give `target`.
+///
 /// Optionally, extrapolate to circuits with up to 3 * `max_steps` gates. This
 /// has no additional memory costs.
+///
+/// The forward search only explores one circuit per orbit of `symmetries`,
+/// see [`topology_automorphisms`].
+///
+/// Frontiers are expanded in parallel on the rayon thread pool. Unset
+/// `parallel` to run the whole search on a single thread instead, e.g. to
+/// measure the speedup.
 pub fn mitm_bfs<T: CXCircuit>(
     source: T,
     target: T,
     moves: &Moves<T>,
     max_steps: usize,
     extrapolate: bool,
+    symmetries: Vec<Vec<usize>>,
+    parallel: bool,
 ) -> Option<Vec<usize>> {
+    if !parallel {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .expect("failed to build single-threaded pool");
+        return pool.install(|| {
+            mitm_bfs(
+                source,
+                target,
+                moves,
+                max_steps,
+                extrapolate,
+                symmetries,
+                true,
+            )
+        });
+    }
+    mitm_search(
+        source,
+        target,
+        moves,
+        max_steps,
+        extrapolate,
+        symmetries,
+        None,
+    )
+    .expect("only checkpoints can fail to be written")
+}
+
+/// As [`mitm_bfs`], saving the layers of both BFS to `checkpoint_dir` as
+/// they are found.
+///
+/// If `checkpoint_dir` holds the checkpoints of an interrupted search with
+/// the same arguments, the search resumes from them. Checkpoints of a
+/// different search are rejected as invalid data.
+pub fn mitm_bfs_checkpointed<T: CXCircuit>(
+    source: T,
+    target: T,
+    moves: &Moves<T>,
+    max_steps: usize,
+    extrapolate: bool,
+    symmetries: Vec<Vec<usize>>,
+    checkpoint_dir: &Path,
+) -> io::Result<Option<Vec<usize>>> {
+    mitm_search(
+        source,
+        target,
+        moves,
+        max_steps,
+        extrapolate,
+        symmetries,
+        Some(checkpoint_dir),
+    )
+}
+
+fn mitm_search<T: CXCircuit>(
+    source: T,
+    target: T,
+    moves: &Moves<T>,
+    max_steps: usize,
+    extrapolate: bool,
+    symmetries: Vec<Vec<usize>>,
+    checkpoint_dir: Option<&Path>,
+) -> io::Result<Option<Vec<usize>>> {
     if max_steps < 1 {
-        return None;
+        return Ok(None);
     }
+    // The BFS applies moves before the circuits it starts from, so search
+    // for the circuit that maps the identity to `target * source^-1` instead
+    let (source, target) = if source.is_identity() {
+        (source, target)
+    } else {
+        (T::new(), target.mult(&source.inverse()))
+    };
 
+    let forward_checkpoint = checkpoint_dir.map(|dir| dir.join("forward.bfs"));
+    let backward_checkpoint = checkpoint_dir.map(|dir| dir.join("backward.bfs"));
     // Start one BFS at the identity circuit
-    let mut forward = BFS::new(source, moves);
+    let mut forward = BFS::resume_or(forward_checkpoint.as_deref(), source, moves, || {
+        BFS::with_symmetries(source, moves, symmetries)
+    })?;
     // Start one BFS at the target circuit
-    let mut backward = BFS::new(target, moves);
+    let mut backward = BFS::resume_or(backward_checkpoint.as_deref(), target, moves, || {
+        BFS::new(target, moves)
+    })?;
+    if !extrapolate {
+        // The extrapolation uses the forward layers as moves, so they must
+        // not be pruned
+        forward.forward_checking(target, 2 * max_steps);
+        backward.forward_checking(source, 2 * max_steps);
+    }
 
     let mut forward_frontier = None;
     let mut backward_frontier = None;
 
     for n_cx in 1..=max_steps {
         println!("forward:");
-        forward_frontier = Some(forward.step());
-        if let Some(circ) = intersect(forward_frontier.as_ref(), backward_frontier.as_ref()) {
+        forward_frontier = Some(forward.step_checkpointed(n_cx, forward_checkpoint.as_deref())?);
+        if let Some(circ) = intersect(
+            &forward,
+            forward_frontier.as_ref(),
+            backward_frontier.as_ref(),
+        ) {
             println!("Found solution using {} CXs", 2 * n_cx - 1,);
-            return Some(backtrack_mitm(&forward, &backward, circ));
+            return Ok(Some(backtrack_mitm(&forward, &backward, circ)));
         }
         println!("backward:");
-        backward_frontier = Some(backward.step());
-        if let Some(circ) = intersect(forward_frontier.as_ref(), backward_frontier.as_ref()) {
+        backward_frontier = Some(backward.step_checkpointed(n_cx, backward_checkpoint.as_deref())?);
+        if let Some(circ) = intersect(
+            &forward,
+            forward_frontier.as_ref(),
+            backward_frontier.as_ref(),
+        ) {
             println!("Found solution using {} CXs", 2 * n_cx);
-            return Some(backtrack_mitm(&forward, &backward, circ));
+            return Ok(Some(backtrack_mitm(&forward, &backward, circ)));
         }
     }
 
     if extrapolate {
         // Now we extrapolate
         // TODO: use hash explicitly?
-        let forward_frontier = forward_frontier.expect("max_steps > 0");
+        let mut forward_frontier = forward_frontier.expect("max_steps > 0");
         let backward_frontier = backward_frontier.expect("max_steps > 0");
+        if !forward.symmetries.is_empty() {
+            forward_frontier = forward.orbits(&forward_frontier);
+        }
         for extra_depth in 1..=forward.depth() {
-            let moves: Vec<_> = forward.cx_count_circs[extra_depth]
-                .keys()
+            let moves: Vec<_> = forward
+                .orbits(forward.cx_count_circs[extra_depth].keys())
+                .into_iter()
                 // Always transpose moves!
                 .map(|mv| mv.transpose())
                 .collect();
@@ -152,19 +408,67 @@ pub fn mitm_bfs<T: CXCircuit>(
                 let circ_forward = circ_backward.mult_transpose(&extra_moves);
                 // Transpose back!
                 let circ_mid = extra_moves.transpose();
-                return Some(backtrack_mitm_extra(
+                return Ok(Some(backtrack_mitm_extra(
                     &forward,
                     &backward,
                     circ_forward,
                     circ_mid,
                     circ_backward,
-                ));
+                )));
             };
         }
     }
 
     println!("No solution found at maximal depth, aborting");
-    None
+    Ok(None)
+}
+
+/// All shortest sequences of `moves` that map `source` to `target`, using a
+/// meet-in-the-middle BFS.
+///
+/// Unlike [`mitm_bfs`], the search does not stop at the first circuit in
+/// both frontiers: every such meeting point yields a solution. Solutions
+/// that share their meeting point are only found once. Returns an empty
+/// vector if `target` needs more than 2 * `max_steps` moves.
+pub fn mitm_bfs_all<T: CXCircuit>(
+    source: T,
+    target: T,
+    moves: &Moves<T>,
+    max_steps: usize,
+) -> Vec<Vec<usize>> {
+    if source == target {
+        return vec![Vec::new()];
+    }
+    // Search from the identity, as in `mitm_search`
+    let target = target.mult(&source.inverse());
+    let mut forward = BFS::new(T::new(), moves);
+    let mut backward = BFS::new(target, moves);
+    let mut backward_frontier = FxHashSet::from_iter([target]);
+    for n_cx in 1..=max_steps {
+        let forward_frontier = forward.step();
+        let meeting_points = intersect_all(&forward_frontier, &backward_frontier);
+        if !meeting_points.is_empty() {
+            println!("Found solutions using {} CXs", 2 * n_cx - 1);
+            return backtrack_all(&forward, &backward, meeting_points);
+        }
+        backward_frontier = backward.step();
+        let meeting_points = intersect_all(&forward_frontier, &backward_frontier);
+        if !meeting_points.is_empty() {
+            println!("Found solutions using {} CXs", 2 * n_cx);
+            return backtrack_all(&forward, &backward, meeting_points);
+        }
+    }
+    Vec::new()
+}
+
+/// Whether `target` can be reached from `source` with at most
+/// 2 * `max_steps` moves.
+///
+/// A cheap check to run with a small `max_steps` before an expensive search:
+/// if it succeeds, the problem is trivial, otherwise fall back to the main
+/// algorithm.
+pub fn can_reach<T: CXCircuit>(source: T, target: T, moves: &Moves<T>, max_steps: usize) -> bool {
+    source == target || mitm_bfs(source, target, moves, max_steps, false, vec![], true).is_some()
 }
 
 fn apply_moves<'a, T, Circs, Moves>(
@@ -188,6 +492,7 @@ where
 fn collect_moves<T: CXCircuit, V: Send>(
     circs: &FxHashMap<T, V>,
     moves: &Moves<T>,
+    canonical_f: impl Fn(&T) -> T + Sync,
     retain_f: impl Fn(&T) -> bool + Sync,
 ) -> CircMoves<T> {
     // A rough estimate of the capacity required
@@ -196,6 +501,7 @@ fn collect_moves<T: CXCircuit, V: Send>(
 
     let circs: Vec<_> = circs.keys().copied().collect();
     let circuits = apply_moves(circs.into_par_iter(), moves.par_iter().copied())
+        .map(|(i, mv)| (i, canonical_f(&mv)))
         .filter(|(_, mv)| retain_f(&mv))
         .map(|(i, mv)| (mv, i))
         .collect();
@@ -203,17 +509,35 @@ fn collect_moves<T: CXCircuit, V: Send>(
     circuits
 }
 
+/// Find a circuit of the backward frontier that is in the forward frontier.
+///
+/// The forward frontier only contains canonical forms, so the backward
+/// circuits are canonicalised before lookup.
 fn intersect<T: CXCircuit>(
-    frontier1: Option<&FxHashSet<T>>,
-    frontier2: Option<&FxHashSet<T>>,
+    forward: &BFS<T>,
+    forward_frontier: Option<&FxHashSet<T>>,
+    backward_frontier: Option<&FxHashSet<T>>,
 ) -> Option<T> {
-    let Some(frontier1) = frontier1 else {
-        return None;
-    };
-    let Some(frontier2) = frontier2 else {
-        return None;
-    };
-    frontier1.intersection(frontier2).next().copied()
+    let forward_frontier = forward_frontier?;
+    let backward_frontier = backward_frontier?;
+    if forward.symmetries.is_empty() {
+        return forward_frontier
+            .intersection(backward_frontier)
+            .next()
+            .copied();
+    }
+    backward_frontier
+        .iter()
+        .find(|circ| forward_frontier.contains(&forward.canonical(circ)))
+        .copied()
+}
+
+/// All circuits in both `frontier1` and `frontier2`.
+///
+/// Each circuit in the intersection is the meeting point of a distinct
+/// shortest path, so that several solutions can be backtracked at once.
+fn intersect_all<T: CXCircuit>(frontier1: &FxHashSet<T>, frontier2: &FxHashSet<T>) -> Vec<T> {
+    frontier1.intersection(frontier2).copied().collect()
 }
 
 fn backtrack_mitm<T: CXCircuit>(forward: &BFS<T>, backward: &BFS<T>, circ: T) -> Vec<usize> {
@@ -223,6 +547,20 @@ fn backtrack_mitm<T: CXCircuit>(forward: &BFS<T>, backward: &BFS<T>, circ: T) ->
     moves
 }
 
+/// The solutions through each of `meeting_points`, in a deterministic order.
+fn backtrack_all<T: CXCircuit>(
+    forward: &BFS<T>,
+    backward: &BFS<T>,
+    meeting_points: Vec<T>,
+) -> Vec<Vec<usize>> {
+    let mut solutions = meeting_points
+        .into_iter()
+        .map(|circ| backtrack_mitm(forward, backward, circ))
+        .collect::<Vec<_>>();
+    solutions.sort_unstable();
+    solutions
+}
+
 fn backtrack_mitm_extra<T: CXCircuit>(
     forward: &BFS<T>,
     backward: &BFS<T>,
@@ -236,3 +574,184 @@ fn backtrack_mitm_extra<T: CXCircuit>(
     moves.extend(forward.backtrack(&circ_forward));
     moves
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cx_circuit::CXCircuit16, CX};
+
+    pub(super) fn ring_moves(n: usize) -> (Vec<CX>, Moves<CXCircuit16>) {
+        let cxs: Vec<CX> = (0..n)
+            .flat_map(|i| [(i, (i + 1) % n), ((i + 1) % n, i)])
+            .map(CX::from)
+            .collect();
+        let moves = cxs
+            .iter()
+            .map(|cx| CXCircuit16::from_cxs([(cx.ctrl as usize, cx.tgt as usize)]).transpose())
+            .collect();
+        (cxs, moves)
+    }
+
+    #[test]
+    fn test_intersect_all() {
+        let circs = [
+            CXCircuit16::new(),
+            CXCircuit16::from_cxs([(0, 1)]),
+            CXCircuit16::from_cxs([(1, 2)]),
+            CXCircuit16::from_cxs([(2, 3)]),
+        ];
+        let frontier1 = FxHashSet::from_iter(circs[..3].iter().copied());
+        let frontier2 = FxHashSet::from_iter(circs[1..].iter().copied());
+        let mut intersection = intersect_all(&frontier1, &frontier2);
+        intersection.sort();
+        let mut expected = circs[1..3].to_vec();
+        expected.sort();
+        assert_eq!(intersection, expected);
+        assert!(intersect_all(&frontier1, &FxHashSet::default()).is_empty());
+    }
+    #[test]
+    fn test_symmetric_frontier_is_smaller() {
+        let (cxs, moves) = ring_moves(4);
+        let mut bfs = BFS::new(CXCircuit16::new(), &moves);
+        let mut sym_bfs =
+            BFS::with_symmetries(CXCircuit16::new(), &moves, topology_automorphisms(&cxs));
+        for _ in 0..3 {
+            let frontier = bfs.step();
+            let sym_frontier = sym_bfs.step();
+            assert!(sym_frontier.len() < frontier.len());
+            assert_eq!(sym_bfs.orbits(&sym_frontier), frontier);
+        }
+    }
+
+    #[test]
+    fn test_assert_optimal() {
+        let (cxs, moves) = ring_moves(4);
+        let target = CXCircuit16::from_cxs([(0, 1), (2, 3)]);
+        let mut bfs = BFS::new(CXCircuit16::new(), &moves);
+        bfs.step();
+        bfs.step();
+        let solution = bfs.backtrack(&target);
+        assert!(bfs.assert_optimal(&solution, &target));
+
+        // A correct but longer solution: CX(0, 1) three times
+        let cx01 = cxs.iter().position(|cx| *cx == CX::from((0, 1))).unwrap();
+        let target = CXCircuit16::from_cxs([(0, 1)]);
+        bfs.step();
+        assert!(!bfs.assert_optimal(&[cx01; 3], &target));
+        assert!(bfs.assert_optimal(&[cx01], &target));
+    }
+
+    #[test]
+    fn test_forward_checking() {
+        let (_, moves) = ring_moves(4);
+        let target = CXCircuit16::from_cxs([(0, 1), (2, 3)]);
+        let mut bfs = BFS::new(CXCircuit16::new(), &moves);
+        let mut checked_bfs = BFS::new(CXCircuit16::new(), &moves);
+        checked_bfs.forward_checking(target, 2);
+        assert!(checked_bfs.step().len() < bfs.step().len());
+        assert!(checked_bfs.step().contains(&target));
+    }
+
+    #[test]
+    fn test_symmetric_mitm() {
+        let (cxs, moves) = ring_moves(4);
+        let cx_list = [(0, 1), (1, 2), (3, 0), (2, 3), (1, 0)];
+        let target = CXCircuit16::from_cxs(cx_list);
+        let solution = mitm_bfs(
+            CXCircuit16::new(),
+            target,
+            &moves,
+            3,
+            false,
+            topology_automorphisms(&cxs),
+            true,
+        )
+        .unwrap();
+        let result = CXCircuit16::from_cxs(
+            solution
+                .iter()
+                .map(|&mv| (cxs[mv].ctrl as usize, cxs[mv].tgt as usize)),
+        );
+        assert_eq!(result, target);
+    }
+
+    #[test]
+    fn test_mitm_bfs_all() {
+        let (cxs, moves) = ring_moves(4);
+        let cx = |pair| cxs.iter().position(|&cx| cx == CX::from(pair)).unwrap();
+        // The two CXs commute, so they can be applied in either order
+        let target = CXCircuit16::from_cxs([(0, 1), (2, 3)]);
+        let solutions = mitm_bfs_all(CXCircuit16::new(), target, &moves, 2);
+        let mut expected = vec![vec![cx((0, 1)), cx((2, 3))], vec![cx((2, 3)), cx((0, 1))]];
+        expected.sort();
+        assert_eq!(solutions, expected);
+        assert!(mitm_bfs_all(CXCircuit16::new(), target, &moves, 0).is_empty());
+
+        // Starting from one of the CXs, only the other one is left
+        let source = CXCircuit16::from_cxs([(0, 1)]);
+        assert_eq!(
+            mitm_bfs_all(source, target, &moves, 1),
+            vec![vec![cx((2, 3))]]
+        );
+        assert_eq!(
+            mitm_bfs_all(target, target, &moves, 1),
+            [Vec::<usize>::new()]
+        );
+
+        // Every solution maps `source` to `target`
+        let source = CXCircuit16::from_cxs([(1, 2), (3, 0)]);
+        let target = CXCircuit16::from_cxs([(1, 2), (3, 0), (0, 1), (2, 3), (1, 0)]);
+        let solutions = mitm_bfs_all(source, target, &moves, 2);
+        assert!(!solutions.is_empty());
+        for solution in solutions {
+            assert_eq!(solution.len(), 3);
+            let result = solution.iter().fold(source, |mut circ, &mv| {
+                circ.add_cx(cxs[mv].ctrl as usize, cxs[mv].tgt as usize);
+                circ
+            });
+            assert_eq!(result, target);
+        }
+    }
+
+    #[test]
+    fn test_mitm_from_source() {
+        let (cxs, moves) = ring_moves(4);
+        let source = CXCircuit16::from_cxs([(1, 2), (3, 0)]);
+        let target = CXCircuit16::from_cxs([(1, 2), (3, 0), (0, 1), (2, 3), (1, 0)]);
+        let solution = mitm_bfs(source, target, &moves, 2, false, vec![], true).unwrap();
+        assert_eq!(solution.len(), 3);
+        let result = solution.iter().fold(source, |mut circ, &mv| {
+            circ.add_cx(cxs[mv].ctrl as usize, cxs[mv].tgt as usize);
+            circ
+        });
+        assert_eq!(result, target);
+    }
+
+    #[test]
+    fn test_can_reach() {
+        let (_, moves) = ring_moves(4);
+        let source = CXCircuit16::new();
+        assert!(can_reach(source, source, &moves, 1));
+        let target = CXCircuit16::from_cxs([(0, 1), (1, 2), (2, 3)]);
+        assert!(!can_reach(source, target, &moves, 1));
+        assert!(can_reach(source, target, &moves, 2));
+    }
+
+    #[test]
+    fn test_sequential_mitm() {
+        let (_, moves) = ring_moves(4);
+        let target = CXCircuit16::from_cxs([(0, 1), (1, 2), (3, 0), (2, 3), (1, 0)]);
+        let search = |parallel| {
+            mitm_bfs(
+                CXCircuit16::new(),
+                target,
+                &moves,
+                3,
+                false,
+                vec![],
+                parallel,
+            )
+        };
+        assert_eq!(search(false).unwrap().len(), search(true).unwrap().len());
+    }
+}
@@ -0,0 +1,223 @@
+//! Saving and loading the layers of a BFS, to resume long searches.
+//!
+//! The checkpoint is a packed little-endian binary file:
+//!  - the magic bytes `CXBFS v1`,
+//!  - the number of qubits, as a `u32`,
+//!  - the number of symmetries, as a `u32`, followed by each permutation as
+//!    its length and images, all `u32`s,
+//!  - the number of layers, as a `u32`, followed by each layer as its number
+//!    of circuits, as a `u64`, and the `(circuit, move id)` pairs of the
+//!    layer. A circuit is stored as its rows, each in as many bytes as
+//!    needed for the qubits (e.g. the raw `[u16; 16]` matrix of a
+//!    [`crate::CXCircuit16`]), and the move id as a `u64`.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use fxhash::FxHashSet;
+
+use super::BFS;
+use crate::{cx_circuit::CXCircuit, CircMoves, Moves};
+
+const MAGIC: &[u8; 8] = b"CXBFS v1";
+
+impl<'m, T: CXCircuit> BFS<'m, T> {
+    /// Resume the BFS saved at `path`, or start a `new` one if there is no
+    /// checkpoint yet.
+    ///
+    /// Fails if the saved BFS does not start from `start_circ`.
+    pub(super) fn resume_or(
+        path: Option<&Path>,
+        start_circ: T,
+        moves: &'m Moves<T>,
+        new: impl FnOnce() -> Self,
+    ) -> io::Result<Self> {
+        let Some(path) = path.filter(|path| path.exists()) else {
+            return Ok(new());
+        };
+        let bfs = Self::load_checkpoint(path, moves)?;
+        if !bfs.cx_count_circs[0].contains_key(&start_circ) {
+            return Err(invalid_data(format!(
+                "checkpoint {} is for a different search",
+                path.display()
+            )));
+        }
+        println!(
+            "Resuming from {} at {} CX gates",
+            path.display(),
+            bfs.depth()
+        );
+        Ok(bfs)
+    }
+
+    /// The circuits at `depth`, found by a [`BFS::step`] unless they were
+    /// loaded from a checkpoint.
+    ///
+    /// New layers are saved to `checkpoint`, if set.
+    pub(super) fn step_checkpointed(
+        &mut self,
+        depth: usize,
+        checkpoint: Option<&Path>,
+    ) -> io::Result<FxHashSet<T>> {
+        if depth <= self.depth() {
+            return Ok(self.cx_count_circs[depth].keys().copied().collect());
+        }
+        let frontier = self.step();
+        if let Some(path) = checkpoint {
+            self.save_checkpoint(path)?;
+        }
+        Ok(frontier)
+    }
+
+    /// Write all layers found so far to `path`.
+    ///
+    /// The file is only replaced once the checkpoint is complete, so that an
+    /// interrupted write does not lose the previous checkpoint.
+    pub(super) fn save_checkpoint(&self, path: &Path) -> io::Result<()> {
+        let partial_path = path.with_extension("part");
+        self.write_checkpoint(&partial_path)?;
+        fs::rename(partial_path, path)
+    }
+
+    fn write_checkpoint(&self, path: &Path) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        write_u32(&mut file, T::N_QUBITS)?;
+        write_u32(&mut file, self.symmetries.len())?;
+        for perm in &self.symmetries {
+            write_u32(&mut file, perm.len())?;
+            for &image in perm {
+                write_u32(&mut file, image)?;
+            }
+        }
+        write_u32(&mut file, self.cx_count_circs.len())?;
+        for layer in &self.cx_count_circs {
+            file.write_all(&(layer.len() as u64).to_le_bytes())?;
+            for (circ, &move_id) in layer {
+                for i in 0..T::N_QUBITS {
+                    file.write_all(&circ.row_bits(i).to_le_bytes()[..row_bytes::<T>()])?;
+                }
+                file.write_all(&(move_id as u64).to_le_bytes())?;
+            }
+        }
+        file.flush()
+    }
+
+    /// Resume a BFS from a checkpoint written by [`BFS::save_checkpoint`].
+    ///
+    /// `moves` must be the moves of the saved BFS. Goals set by
+    /// [`BFS::forward_checking`] are not saved.
+    pub(super) fn load_checkpoint(path: &Path, moves: &'m Moves<T>) -> io::Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut magic = [0; MAGIC.len()];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a BFS checkpoint"));
+        }
+        let n_qubits = read_u32(&mut file)?;
+        if n_qubits != T::N_QUBITS {
+            return Err(invalid_data(format!(
+                "checkpoint is for {n_qubits} qubits, expected {}",
+                T::N_QUBITS
+            )));
+        }
+        let symmetries = (0..read_u32(&mut file)?)
+            .map(|_| {
+                (0..read_u32(&mut file)?)
+                    .map(|_| read_u32(&mut file))
+                    .collect()
+            })
+            .collect::<io::Result<Vec<Vec<_>>>>()?;
+
+        let n_layers = read_u32(&mut file)?;
+        let mut cx_count_circs = Vec::with_capacity(n_layers);
+        for depth in 0..n_layers {
+            let mut len = [0; 8];
+            file.read_exact(&mut len)?;
+            let mut layer = CircMoves::default();
+            for _ in 0..u64::from_le_bytes(len) {
+                let (circ, move_id) = read_circ_move::<T>(&mut file)?;
+                if depth > 0 && move_id >= moves.len() {
+                    return Err(invalid_data(format!("unknown move {move_id}")));
+                }
+                layer.insert(circ, move_id);
+            }
+            cx_count_circs.push(layer);
+        }
+
+        let start_circ = match cx_count_circs.first().map(|layer| layer.len()) {
+            Some(1) => *cx_count_circs[0].keys().next().unwrap(),
+            _ => return Err(invalid_data("the first layer must be a single circuit")),
+        };
+        let mut bfs = Self::new(start_circ, moves);
+        bfs.cx_count_circs = cx_count_circs;
+        bfs.symmetries = symmetries;
+        Ok(bfs)
+    }
+}
+
+/// The number of bytes used to store each row of a `T`.
+fn row_bytes<T: CXCircuit>() -> usize {
+    T::N_QUBITS.div_ceil(8)
+}
+
+fn read_circ_move<T: CXCircuit>(file: &mut impl Read) -> io::Result<(T, usize)> {
+    let mut rows = Vec::with_capacity(T::N_QUBITS);
+    for _ in 0..T::N_QUBITS {
+        let mut row = [0; 8];
+        file.read_exact(&mut row[..row_bytes::<T>()])?;
+        let row = u64::from_le_bytes(row);
+        if row == 0 || (T::N_QUBITS < 64 && row >> T::N_QUBITS != 0) {
+            return Err(invalid_data(format!("invalid circuit row {row:#b}")));
+        }
+        rows.push(row);
+    }
+    let mut move_id = [0; 8];
+    file.read_exact(&mut move_id)?;
+    // The move id of the start circuit is `usize::MAX`
+    let move_id = usize::try_from(u64::from_le_bytes(move_id)).unwrap_or(usize::MAX);
+    Ok((T::from_row_bits(&rows), move_id))
+}
+
+fn write_u32(file: &mut impl Write, value: usize) -> io::Result<()> {
+    let value = u32::try_from(value).map_err(|_| invalid_data("value too large"))?;
+    file.write_all(&value.to_le_bytes())
+}
+
+fn read_u32(file: &mut impl Read) -> io::Result<usize> {
+    let mut bytes = [0; 4];
+    file.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes) as usize)
+}
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bfs::tests::ring_moves, cx_circuit::CXCircuit16};
+
+    #[test]
+    fn test_resume_from_checkpoint() {
+        let (_, moves) = ring_moves(4);
+        let path = std::env::temp_dir().join(format!("bfs_checkpoint_{}", std::process::id()));
+
+        let mut bfs = BFS::new(CXCircuit16::new(), &moves);
+        bfs.step();
+        bfs.step();
+        bfs.save_checkpoint(&path).unwrap();
+        let mut resumed = BFS::load_checkpoint(&path, &moves).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(resumed.cx_count_circs, bfs.cx_count_circs);
+
+        // The moves recorded for circuits reachable in several ways may
+        // differ between runs, so only compare the circuits
+        assert_eq!(resumed.step(), bfs.step());
+        assert_eq!(resumed.depth(), 3);
+    }
+}
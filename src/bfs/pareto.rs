@@ -0,0 +1,107 @@
+//! BFS trading off CX count against topology cost.
+
+use fxhash::FxHashMap;
+use rayon::prelude::*;
+
+use super::apply_moves;
+use crate::{cx_circuit::CXCircuit, Moves};
+
+/// Map from circuits to the last move applied and the cumulative cost.
+type CostCircMoves<T> = FxHashMap<T, (usize, usize)>;
+
+/// Breadth-first search for the Pareto front of CX count vs topology cost.
+///
+/// `costs[i]` is the cost of applying `moves[i]`, e.g. reflecting the error
+/// rate of the corresponding qubit interaction. Returns triples of
+/// `(cx_count, topology_cost, moves)`, sorted by increasing CX count and
+/// strictly decreasing topology cost.
+///
+/// Unlike [`super::mitm_bfs`], circuits may be revisited at a higher CX count
+/// if they are reached with a lower cost, so the search space is larger.
+pub fn pareto_bfs<T: CXCircuit>(
+    source: T,
+    target: T,
+    moves: &Moves<T>,
+    costs: &[usize],
+    max_steps: usize,
+) -> Vec<(usize, usize, Vec<usize>)> {
+    assert_eq!(moves.len(), costs.len(), "every move must have a cost");
+
+    let mut layers = vec![CostCircMoves::from_iter([(source, (usize::MAX, 0))])];
+    // The lowest cost at which each circuit was reached, at any CX count
+    let mut best_costs = FxHashMap::from_iter([(source, 0)]);
+    let mut front = Vec::new();
+    if source == target {
+        front.push((0, 0, Vec::new()));
+    }
+
+    for depth in 1..=max_steps {
+        let prev_layer = layers.last().expect("at least one layer");
+        let circs: Vec<_> = prev_layer.keys().copied().collect();
+        let new_circs: Vec<_> = apply_moves(circs.into_par_iter(), moves.par_iter().copied())
+            .map(|(mv_id, circ)| (circ, mv_id))
+            .collect();
+
+        let mut layer = CostCircMoves::default();
+        for (circ, mv_id) in new_circs {
+            // Recover the cost of the circuit the move was applied to
+            let prev = circ.mult_transpose(&moves[mv_id]);
+            let cost = prev_layer[&prev].1 + costs[mv_id];
+            // Only keep circuits that are cheaper than at any lower CX count
+            if best_costs.get(&circ).is_some_and(|&best| best <= cost) {
+                continue;
+            }
+            if layer.get(&circ).is_none_or(|&(_, best)| cost < best) {
+                layer.insert(circ, (mv_id, cost));
+            }
+        }
+        for (&circ, &(_, cost)) in &layer {
+            best_costs.insert(circ, cost);
+        }
+        println!("With {} CX gates: {} circuits", depth, layer.len());
+        layers.push(layer);
+
+        if let Some(&(_, cost)) = layers[depth].get(&target) {
+            front.push((depth, cost, backtrack(&layers, moves, target)));
+        }
+    }
+    front
+}
+
+/// Recover the moves leading to `circ` in the last layer.
+fn backtrack<T: CXCircuit>(layers: &[CostCircMoves<T>], moves: &Moves<T>, circ: T) -> Vec<usize> {
+    let mut path = Vec::new();
+    let mut curr = circ;
+    for layer in layers[1..].iter().rev() {
+        let (mv_id, _) = layer[&curr];
+        path.push(mv_id);
+        curr = curr.mult_transpose(&moves[mv_id]);
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cx_circuit::CXCircuit16;
+
+    #[test]
+    fn test_pareto_bfs() {
+        // CX(0, 2) directly is expensive, going via qubit 1 is cheap
+        let cxs = [(0, 2), (0, 1), (1, 2), (1, 0), (2, 1)];
+        let moves = cxs
+            .iter()
+            .map(|&cx| CXCircuit16::from_cxs([cx]).transpose())
+            .collect();
+        let costs = [10, 1, 1, 1, 1];
+        let target = CXCircuit16::from_cxs([(0, 2)]);
+
+        let front = pareto_bfs(CXCircuit16::new(), target, &moves, &costs, 4);
+        assert_eq!(front.len(), 2);
+        assert_eq!(front[0], (1, 10, vec![0]));
+        let (cx_count, cost, path) = &front[1];
+        assert_eq!((*cx_count, *cost), (4, 4));
+        let result = CXCircuit16::from_cxs(path.iter().map(|&mv| cxs[mv]));
+        assert_eq!(result, target);
+    }
+}
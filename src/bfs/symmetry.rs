@@ -0,0 +1,162 @@
+//! Symmetries of the qubit topology, used to reduce the BFS search space.
+
+use fxhash::FxHashSet;
+
+use crate::{cx_circuit::CXCircuit, CX};
+
+/// The maximum number of automorphisms to consider.
+///
+/// Canonicalising a circuit costs one permutation per automorphism, so very
+/// large groups (e.g. from isolated qubits) are not worth it.
+const MAX_AUTOMORPHISMS: usize = 64;
+
+/// Compute the automorphism group of the move set.
+///
+/// An automorphism is a relabelling of the qubits that maps the set of allowed
+/// CX gates onto itself. Permutation `perm` maps qubit `i` to `perm[i]`; the
+/// identity is always included.
+///
+/// If the group has more than `MAX_AUTOMORPHISMS` elements, only the
+/// identity is returned.
+pub fn topology_automorphisms(moves: &[CX]) -> Vec<Vec<usize>> {
+    let n_qubits = moves
+        .iter()
+        .map(|cx| cx.ctrl.max(cx.tgt) as usize + 1)
+        .max()
+        .unwrap_or(0);
+    let edges: FxHashSet<(usize, usize)> = moves
+        .iter()
+        .map(|cx| (cx.ctrl as usize, cx.tgt as usize))
+        .collect();
+    let degrees: Vec<(usize, usize)> = (0..n_qubits)
+        .map(|qb| {
+            let out_deg = edges.iter().filter(|&&(ctrl, _)| ctrl == qb).count();
+            let in_deg = edges.iter().filter(|&&(_, tgt)| tgt == qb).count();
+            (out_deg, in_deg)
+        })
+        .collect();
+
+    let mut automorphisms = Vec::new();
+    let mut perm = Vec::with_capacity(n_qubits);
+    let mut used = vec![false; n_qubits];
+    let complete = extend_automorphism(&edges, &degrees, &mut perm, &mut used, &mut automorphisms);
+    if !complete {
+        return vec![(0..n_qubits).collect()];
+    }
+    automorphisms
+}
+
+/// Recursively extend the partial permutation `perm` to automorphisms.
+///
+/// Returns `false` if the search was aborted because too many automorphisms
+/// were found.
+fn extend_automorphism(
+    edges: &FxHashSet<(usize, usize)>,
+    degrees: &[(usize, usize)],
+    perm: &mut Vec<usize>,
+    used: &mut [bool],
+    automorphisms: &mut Vec<Vec<usize>>,
+) -> bool {
+    let qb = perm.len();
+    if qb == degrees.len() {
+        if automorphisms.len() >= MAX_AUTOMORPHISMS {
+            return false;
+        }
+        automorphisms.push(perm.clone());
+        return true;
+    }
+    for image in 0..degrees.len() {
+        if used[image] || degrees[image] != degrees[qb] {
+            continue;
+        }
+        // Check the edges to all previously assigned qubits are preserved
+        let consistent = perm.iter().enumerate().all(|(prev, &prev_image)| {
+            edges.contains(&(qb, prev)) == edges.contains(&(image, prev_image))
+                && edges.contains(&(prev, qb)) == edges.contains(&(prev_image, image))
+        });
+        if !consistent {
+            continue;
+        }
+        perm.push(image);
+        used[image] = true;
+        let complete = extend_automorphism(edges, degrees, perm, used, automorphisms);
+        perm.pop();
+        used[image] = false;
+        if !complete {
+            return false;
+        }
+    }
+    true
+}
+
+/// The smallest image of `circ` under `symmetries`, along with the
+/// permutation that maps `circ` to it.
+///
+/// The permutation is `None` if the identity is (one of) the smallest.
+pub(super) fn canonical<'s, T: CXCircuit>(
+    circ: &T,
+    symmetries: &'s [Vec<usize>],
+) -> (T, Option<&'s [usize]>) {
+    symmetries
+        .iter()
+        .map(|perm| (circ.apply_permutation(perm), Some(perm.as_slice())))
+        .fold(
+            (*circ, None),
+            |min, image| if image.0 < min.0 { image } else { min },
+        )
+}
+
+/// The inverse of a permutation.
+pub(super) fn invert_permutation(perm: &[usize]) -> Vec<usize> {
+    let mut inverse = vec![0; perm.len()];
+    for (i, &image) in perm.iter().enumerate() {
+        inverse[image] = i;
+    }
+    inverse
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cx_circuit::CXCircuit16;
+
+    fn both_directions(edges: &[(usize, usize)]) -> Vec<CX> {
+        edges
+            .iter()
+            .flat_map(|&(a, b)| [(a, b), (b, a)])
+            .map(CX::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_line_automorphisms() {
+        let moves = both_directions(&[(0, 1), (1, 2)]);
+        let automorphisms = topology_automorphisms(&moves);
+        assert_eq!(automorphisms, vec![vec![0, 1, 2], vec![2, 1, 0]]);
+    }
+
+    #[test]
+    fn test_ring_automorphisms() {
+        let moves = both_directions(&[(0, 1), (1, 2), (2, 3), (3, 0)]);
+        assert_eq!(topology_automorphisms(&moves).len(), 8);
+    }
+
+    #[test]
+    fn test_directed_automorphisms() {
+        // Reversing the line does not preserve the CX directions
+        let moves = [(0, 1), (1, 2)].map(CX::from);
+        assert_eq!(topology_automorphisms(&moves), vec![vec![0, 1, 2]]);
+    }
+
+    #[test]
+    fn test_canonical() {
+        let symmetries = vec![vec![0, 1, 2], vec![2, 1, 0]];
+        let circ = CXCircuit16::from_cxs([(0, 1)]);
+        let mirrored = CXCircuit16::from_cxs([(2, 1)]);
+        assert_eq!(
+            canonical(&circ, &symmetries).0,
+            canonical(&mirrored, &symmetries).0
+        );
+        assert_eq!(invert_permutation(&[1, 2, 0]), vec![2, 0, 1]);
+    }
+}
@@ -1,12 +1,24 @@
 use std::fmt::Debug;
 
 /// A CX gate on two qubits.
-#[derive(Clone, Copy, Hash, Eq, PartialEq)]
+#[derive(Clone, Copy, Hash, Eq, Ord, PartialEq, PartialOrd)]
 pub struct CX {
     pub ctrl: u8,
     pub tgt: u8,
 }
 
+impl CX {
+    /// Whether applying `self` and `other` in either order gives the same
+    /// circuit.
+    ///
+    /// This is the case unless the control of one gate is the target of the
+    /// other, i.e. if they act on disjoint qubits, share their control or
+    /// share their target.
+    pub fn commutes_with(&self, other: CX) -> bool {
+        self.ctrl != other.tgt && self.tgt != other.ctrl
+    }
+}
+
 impl From<(usize, usize)> for CX {
     fn from((ctrl, tgt): (usize, usize)) -> Self {
         Self {
@@ -21,3 +33,37 @@ impl Debug for CX {
         write!(f, "CX({}, {})", self.ctrl, self.tgt)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CXCircuit, CXCircuit16};
+
+    #[test]
+    fn commutes_with() {
+        let cx = |ctrl, tgt| CX::from((ctrl, tgt));
+        // Disjoint qubits
+        assert!(cx(0, 1).commutes_with(cx(2, 3)));
+        // Shared control
+        assert!(cx(0, 1).commutes_with(cx(0, 2)));
+        // Shared target
+        assert!(cx(0, 2).commutes_with(cx(1, 2)));
+        // The same gate
+        assert!(cx(0, 1).commutes_with(cx(0, 1)));
+        // The control of one is the target of the other
+        assert!(!cx(0, 1).commutes_with(cx(1, 2)));
+        assert!(!cx(1, 2).commutes_with(cx(0, 1)));
+        assert!(!cx(0, 1).commutes_with(cx(1, 0)));
+
+        // Check against the circuits for all pairs of gates on 3 qubits
+        let gates = [(0, 1), (1, 0), (0, 2), (2, 0), (1, 2), (2, 1)];
+        for (a, b) in gates.into_iter().flat_map(|a| gates.map(|b| (a, b))) {
+            let commutes = CXCircuit16::from_cxs([a, b]) == CXCircuit16::from_cxs([b, a]);
+            assert_eq!(
+                CX::from(a).commutes_with(CX::from(b)),
+                commutes,
+                "{a:?} {b:?}"
+            );
+        }
+    }
+}
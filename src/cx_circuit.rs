@@ -1,15 +1,40 @@
 //! Circuits with only CX gates.
 
+pub mod generic;
+#[cfg(feature = "simd")]
+mod simd;
+
+use std::cmp::max;
+use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::num::NonZeroU16;
+use std::ops::Deref;
+use std::str::FromStr;
+
+use fxhash::{FxHashMap, FxHashSet};
 
-use crate::a_star::AStarValue;
+use crate::{
+    a_star::AStarValue, stab_state::StabiliserState, topology::Topology,
+    upper_bound::synthesize_by_elimination, CX,
+};
 
 /// A trait for a CX circuit with a fixed number of qubits.
-pub trait CXCircuit: Copy + Eq + Sized + Hash + Send + Sync {
+pub trait CXCircuit: Copy + Eq + Ord + Sized + Hash + Send + Sync {
+    /// The number of qubits of the circuit
+    const N_QUBITS: usize;
+
     /// A new CX circuit.
     fn new() -> Self;
 
+    /// Row `i` of the matrix, as a bitmask.
+    fn row_bits(&self, i: usize) -> u64;
+
+    /// The circuit with the rows given by `rows`, as returned by
+    /// [`CXCircuit::row_bits`].
+    ///
+    /// Panics unless there are `N_QUBITS` non-zero rows.
+    fn from_row_bits(rows: &[u64]) -> Self;
+
     /// Apply a CX gate to the circuit.
     fn add_cx(&mut self, ctrl: usize, tgt: usize);
 
@@ -19,9 +44,120 @@ pub trait CXCircuit: Copy + Eq + Sized + Hash + Send + Sync {
         self.mult_transpose(&other_t)
     }
 
+    /// Compose two CX circuits together, checking the result is invertible.
+    ///
+    /// Returns `None` if a row of the result is zero, which can only happen if
+    /// one of the inputs was not a valid CX circuit.
+    fn mult_checked(&self, other: &Self) -> Option<Self>;
+
     fn mult_transpose(&self, other: &Self) -> Self;
     fn transpose(&self) -> Self;
 
+    /// Whether the circuit is the identity.
+    fn is_identity(&self) -> bool {
+        *self == Self::new()
+    }
+
+    /// The inverse circuit, computed by Gauss-Jordan elimination.
+    ///
+    /// Panics if the matrix is singular.
+    fn inverse(&self) -> Self {
+        let n = Self::N_QUBITS;
+        // Apply the same row operations to the identity as those reducing
+        // `self` to the identity. The two are kept apart rather than packed
+        // into one u64, so that up to 64 qubits fit
+        let mut rows: Vec<u64> = (0..n).map(|i| self.row_bits(i)).collect();
+        let mut inverse: Vec<u64> = (0..n).map(|i| 1 << i).collect();
+        for col in 0..n {
+            let pivot = (col..n)
+                .find(|&row| rows[row] & (1 << col) != 0)
+                .expect("matrix is singular");
+            rows.swap(col, pivot);
+            inverse.swap(col, pivot);
+            for row in 0..n {
+                if row != col && rows[row] & (1 << col) != 0 {
+                    rows[row] ^= rows[col];
+                    inverse[row] ^= inverse[col];
+                }
+            }
+        }
+        Self::from_row_bits(&inverse)
+    }
+
+    /// Whether composing the two circuits in either order gives the same
+    /// circuit.
+    fn commutes_with(&self, other: &Self) -> bool {
+        self.mult(other) == other.mult(self)
+    }
+
+    /// Relabel the qubits of the circuit, mapping qubit `i` to `perm[i]`.
+    ///
+    /// Qubits beyond the end of `perm` are left unchanged. Panics if `perm`
+    /// is not a permutation of `0..perm.len()`, or is longer than the number
+    /// of qubits.
+    fn apply_permutation(&self, perm: &[usize]) -> Self;
+
+    /// The qubits whose columns differ between `self` and `other`, as a
+    /// bitmask.
+    ///
+    /// Right-multiplying by a CX gate changes a single column, so at least
+    /// this many moves are required to map one circuit to the other.
+    fn column_diff_mask(&self, other: &Self) -> u64;
+
+    /// A circuit of `n_gates` CX gates drawn uniformly from `allowed_moves`.
+    ///
+    /// Panics if `allowed_moves` is empty and `n_gates` is non-zero.
+    #[cfg(any(test, feature = "rand"))]
+    fn random(n_gates: usize, allowed_moves: &[CX], rng: &mut impl rand::Rng) -> Self {
+        use rand::RngExt;
+
+        Self::from_cxs((0..n_gates).map(|_| {
+            let cx = allowed_moves[rng.random_range(0..allowed_moves.len())];
+            (cx.ctrl as usize, cx.tgt as usize)
+        }))
+    }
+
+    /// The circuit of the boolean matrix `matrix`, given as a list of rows.
+    ///
+    /// Entry `j` of row `i` is the coefficient of qubit `j` in row `i`, as
+    /// in [`CXCircuit::to_dense_matrix`]. Fails unless the matrix is square,
+    /// of size `N_QUBITS` and invertible.
+    ///
+    /// Invertibility is checked by computing the full GF(2) rank, so
+    /// [`MatrixError::SingularMatrix`] is returned for any singular matrix,
+    /// not just those with a zero row.
+    fn from_dense_matrix<R: Deref<Target = [bool]>>(matrix: &[R]) -> Result<Self, MatrixError> {
+        if let Some(row) = matrix.iter().position(|row| row.len() != matrix.len()) {
+            return Err(MatrixError::NonSquare { row });
+        }
+        if matrix.len() != Self::N_QUBITS {
+            return Err(MatrixError::WrongSize(matrix.len()));
+        }
+        let rows: Vec<u64> = matrix
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .enumerate()
+                    .filter(|&(_, &entry)| entry)
+                    .fold(0, |bits, (j, _)| bits | (1 << j))
+            })
+            .collect();
+        if echelon_form(&mut rows.clone(), Self::N_QUBITS).len() != Self::N_QUBITS {
+            return Err(MatrixError::SingularMatrix);
+        }
+        Ok(Self::from_row_bits(&rows))
+    }
+
+    /// The boolean matrix of the circuit, as a list of rows.
+    fn to_dense_matrix(&self) -> Vec<Vec<bool>> {
+        (0..Self::N_QUBITS)
+            .map(|i| {
+                let row = self.row_bits(i);
+                (0..Self::N_QUBITS).map(|j| row & (1 << j) != 0).collect()
+            })
+            .collect()
+    }
+
     /// Construct a CX circuit from a list of CX gates.
     fn from_cxs(cxs: impl IntoIterator<Item = (usize, usize)>) -> Self {
         let mut cx = Self::new();
@@ -32,40 +168,199 @@ pub trait CXCircuit: Copy + Eq + Sized + Hash + Send + Sync {
     }
 }
 
+/// Panics unless `perm` is a permutation of `0..perm.len()`, on at most
+/// `n_qubits` qubits.
+fn assert_permutation(perm: &[usize], n_qubits: usize) {
+    assert!(
+        perm.len() <= n_qubits,
+        "permutation {perm:?} acts on more than {n_qubits} qubits"
+    );
+    let seen = perm
+        .iter()
+        .filter(|&&i| i < perm.len())
+        .fold(0u64, |seen, &i| seen | 1 << i);
+    assert_eq!(
+        seen.count_ones() as usize,
+        perm.len(),
+        "not a permutation: {perm:?}"
+    );
+}
+
+/// Reduce the first `n_cols` columns of `rows` to reduced row echelon form
+/// over GF(2), in place, and return the pivot columns.
+///
+/// Bits beyond `n_cols` are carried along by the row operations, so rows
+/// augmented with the identity record the operations performed.
+fn echelon_form(rows: &mut [u64], n_cols: usize) -> Vec<usize> {
+    let mut pivots = Vec::new();
+    for col in 0..n_cols {
+        let rank = pivots.len();
+        let Some(pivot) = (rank..rows.len()).find(|&row| rows[row] & (1 << col) != 0) else {
+            continue;
+        };
+        rows.swap(rank, pivot);
+        for row in 0..rows.len() {
+            if row != rank && rows[row] & (1 << col) != 0 {
+                rows[row] ^= rows[rank];
+            }
+        }
+        pivots.push(col);
+    }
+    pivots
+}
+
 /// A 16-qubit CX circuit.
 ///
-/// Represented by a boolean matrix.
+/// Represented by a boolean matrix. With the `serde` feature, it is serialised
+/// as the array of its 16 rows, and rows are checked to be non-zero when
+/// deserialising.
 #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct CXCircuit16 {
     matrix: [NonZeroU16; 16],
 }
 
+/// Error returned when a circuit would act on more qubits than supported.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DimensionError {
+    /// The number of qubits that was requested
+    pub n_qubits: usize,
+}
+
+impl fmt::Display for DimensionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "circuit on {} qubits exceeds 16 qubits", self.n_qubits)
+    }
+}
+
+impl std::error::Error for DimensionError {}
+
+/// Error returned when parsing a circuit from its binary matrix fails.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ParseCircuitError {
+    /// The matrix does not have 16 rows
+    RowCount(usize),
+    /// A row does not have 16 entries
+    RowLength { row: usize },
+    /// A row contains a character other than `0` or `1`
+    InvalidEntry { row: usize },
+    /// A row is all zeros, so the matrix is not invertible
+    ZeroRow { row: usize },
+}
+
+impl fmt::Display for ParseCircuitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RowCount(n_rows) => write!(f, "expected 16 rows, found {n_rows}"),
+            Self::RowLength { row } => write!(f, "row {row} must contain exactly 16 entries"),
+            Self::InvalidEntry { row } => write!(f, "entries of row {row} must be 0 or 1"),
+            Self::ZeroRow { row } => write!(f, "row {row} is zero, the matrix must be invertible"),
+        }
+    }
+}
+
+impl std::error::Error for ParseCircuitError {}
+
+/// Error returned by [`CXCircuit::from_dense_matrix`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MatrixError {
+    /// A row does not have as many entries as the matrix has rows
+    NonSquare { row: usize },
+    /// The matrix does not have one row per qubit of the circuit
+    WrongSize(usize),
+    /// The matrix is not invertible, e.g. because a row is all zeros
+    SingularMatrix,
+}
+
+impl fmt::Display for MatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NonSquare { row } => write!(f, "row {row} must have one entry per row"),
+            Self::WrongSize(n_rows) => write!(f, "a matrix of {n_rows} rows has the wrong size"),
+            Self::SingularMatrix => write!(f, "the matrix must be invertible"),
+        }
+    }
+}
+
+impl std::error::Error for MatrixError {}
+
+/// The binary matrix, with one line of 16 `0`s and `1`s per row.
+///
+/// Entry `j` of a line is the coefficient of qubit `j` in that row.
+impl fmt::Display for CXCircuit16 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, row) in self.matrix.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            for j in 0..16 {
+                let bit = if row.get() & (1 << j) != 0 { '1' } else { '0' };
+                write!(f, "{bit}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse the binary matrix in the format of [`Display`](fmt::Display).
+///
+/// Surrounding whitespace and blank lines are ignored.
+impl FromStr for CXCircuit16 {
+    type Err = ParseCircuitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lines: Vec<&str> = s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        if lines.len() != 16 {
+            return Err(ParseCircuitError::RowCount(lines.len()));
+        }
+        let mut matrix = [0; 16];
+        for (row, line) in lines.into_iter().enumerate() {
+            if line.chars().count() != 16 {
+                return Err(ParseCircuitError::RowLength { row });
+            }
+            for (j, entry) in line.chars().enumerate() {
+                match entry {
+                    '0' => {}
+                    '1' => matrix[row] |= 1 << j,
+                    _ => return Err(ParseCircuitError::InvalidEntry { row }),
+                }
+            }
+            if matrix[row] == 0 {
+                return Err(ParseCircuitError::ZeroRow { row });
+            }
+        }
+        Ok(Self::from_mat(matrix))
+    }
+}
+
 impl Hash for CXCircuit16 {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        // Pointer cast [NonZeroU16; 16] to [u64; 4]
-        let matrix = unsafe { &*self.matrix.as_ptr().cast::<[u64; 4]>() };
-        for &elem in matrix {
-            state.write_u64(elem);
-        }
+        state.write_u64(self.deterministic_hash());
     }
 }
 
 impl AStarValue for CXCircuit16 {
     fn dist(&self, other: &Self) -> usize {
-        self.matrix
-            .iter()
-            .zip(other.matrix.iter())
-            .map(|(&a, &b)| (a != b) as usize)
-            .sum()
+        self.cx_lower_bound(other)
     }
 
     fn cx(&self, ctrl: u8, tgt: u8) -> Self {
-        let mut cx = self.clone();
-        cx.add_cx(ctrl as usize, tgt as usize);
-        cx
+        self.xor_rows(ctrl as usize, tgt as usize)
     }
 
-    fn merge(&self, other: &Self, used_qubits: &fxhash::FxHashSet<u8>) -> Self {
+    fn swap(&self, q1: u8, q2: u8) -> Self {
+        self.row_swap(q1 as usize, q2 as usize)
+    }
+
+    fn merge(&self, other: &Self, used_qubits: &FxHashSet<u8>) -> Self {
         let mut merge = self.clone();
         for &qb in used_qubits {
             merge.matrix[qb as usize] = other.matrix[qb as usize];
@@ -73,9 +368,27 @@ impl AStarValue for CXCircuit16 {
         merge
     }
 
+    fn n_qubits(&self) -> usize {
+        16
+    }
+
     fn is_complete(&self, qb: u8, target: &Self) -> bool {
         self.matrix[qb as usize] == target.matrix[qb as usize]
     }
+
+    fn qubit_dist(&self, qb: u8, target: &Self) -> usize {
+        (self.row(qb as usize) ^ target.row(qb as usize)).count_ones() as usize
+    }
+
+    fn upper_bound(&self, target: &Self, allowed_moves: &FxHashSet<CX>) -> Option<Vec<CX>> {
+        synthesize_by_elimination(self, target, allowed_moves)
+    }
+
+    fn secondary_cost(&self, target: &Self) -> usize {
+        // Prefer circuits that are as complex as the target
+        self.count_nonzero_offdiagonal()
+            .abs_diff(target.count_nonzero_offdiagonal())
+    }
 }
 
 fn eye<const N: usize>() -> [NonZeroU16; N] {
@@ -86,11 +399,45 @@ fn eye<const N: usize>() -> [NonZeroU16; N] {
     matrix
 }
 
+/// Group a sequence of CX gates into layers of gates on disjoint qubits.
+///
+/// Every gate is placed in the earliest layer after all previous gates on its
+/// qubits, so the order of gates on each qubit is preserved.
+pub fn into_layers(cxs: &[CX]) -> Vec<Vec<CX>> {
+    let mut layers: Vec<Vec<CX>> = Vec::new();
+    // The first layer in which each qubit is free
+    let mut free_from = FxHashMap::default();
+    for &cx in cxs {
+        let layer = max(
+            free_from.get(&cx.ctrl).copied().unwrap_or(0),
+            free_from.get(&cx.tgt).copied().unwrap_or(0),
+        );
+        if layer == layers.len() {
+            layers.push(Vec::new());
+        }
+        layers[layer].push(cx);
+        free_from.insert(cx.ctrl, layer + 1);
+        free_from.insert(cx.tgt, layer + 1);
+    }
+    layers
+}
+
 impl CXCircuit for CXCircuit16 {
+    const N_QUBITS: usize = 16;
+
     fn new() -> Self {
         Self { matrix: eye() }
     }
 
+    fn row_bits(&self, i: usize) -> u64 {
+        self.matrix[i].get() as u64
+    }
+
+    fn from_row_bits(rows: &[u64]) -> Self {
+        assert_eq!(rows.len(), 16, "expected 16 rows");
+        Self::from_mat(std::array::from_fn(|i| rows[i] as u16))
+    }
+
     fn add_cx(&mut self, ctrl: usize, tgt: usize) {
         let ctrl_value = self.matrix[ctrl].get();
         let tgt_value = self.matrix[tgt].get();
@@ -98,18 +445,56 @@ impl CXCircuit for CXCircuit16 {
         self.matrix[tgt] = NonZeroU16::new(new_tgt_value).unwrap();
     }
 
+    fn mult_checked(&self, other: &Self) -> Option<Self> {
+        // Row i of the product is the sum of the rows of `other` selected by
+        // row i of `self`
+        let result = self.matrix.map(|row| {
+            (0..16)
+                .filter(|&k| row.get() & (1 << k) != 0)
+                .fold(0, |acc, k| acc ^ other.matrix[k].get())
+        });
+        result
+            .iter()
+            .all(|&row| row != 0)
+            .then(|| Self::from_mat(result))
+    }
+
     fn mult_transpose(&self, other: &Self) -> Self {
-        let mut result = [0; 16];
-        for i in 0..16 {
-            for j in 0..16 {
-                let elem_wise_mult = self.matrix[i].get() & other.matrix[j].get();
-                let bit = (elem_wise_mult.count_ones() % 2) as u16;
-                if bit == 1 {
-                    result[i] += bit << j;
-                }
-            }
-        }
-        Self::from_mat(result)
+        #[cfg(feature = "simd")]
+        return self.mult_transpose_simd(other);
+        #[cfg(not(feature = "simd"))]
+        self.mult_transpose_scalar(other)
+    }
+
+    fn is_identity(&self) -> bool {
+        self.matrix
+            .iter()
+            .enumerate()
+            .all(|(i, row)| row.get() == 1 << i)
+    }
+
+    fn inverse(&self) -> Self {
+        // Reduce `self` augmented with the identity, which then holds the
+        // inverse
+        let mut rows: [u64; 16] =
+            std::array::from_fn(|i| self.matrix[i].get() as u64 | 1 << (16 + i));
+        let pivots = echelon_form(&mut rows, 16);
+        assert_eq!(pivots.len(), 16, "matrix is singular");
+        Self::from_mat(rows.map(|row| (row >> 16) as u16))
+    }
+
+    fn commutes_with(&self, other: &Self) -> bool {
+        // Row i of a product is the sum of the rows of the right factor
+        // selected by row i of the left factor
+        let product_row = |left: u16, right: &Self| {
+            (0..16)
+                .filter(|&k| left & (1 << k) != 0)
+                .fold(0, |acc, k| acc ^ right.matrix[k].get())
+        };
+        self.matrix
+            .iter()
+            .zip(other.matrix.iter())
+            .all(|(a, b)| product_row(a.get(), other) == product_row(b.get(), self))
     }
 
     fn transpose(&self) -> Self {
@@ -125,6 +510,25 @@ impl CXCircuit for CXCircuit16 {
         }
         transposed
     }
+
+    fn apply_permutation(&self, perm: &[usize]) -> Self {
+        assert_permutation(perm, 16);
+        let image = |i: usize| perm.get(i).copied().unwrap_or(i);
+        let mut result = [0; 16];
+        for (i, row) in self.matrix.iter().enumerate() {
+            result[image(i)] = (0..16)
+                .filter(|&j| row.get() & (1 << j) != 0)
+                .fold(0, |acc, j| acc | (1 << image(j)));
+        }
+        Self::from_mat(result)
+    }
+
+    fn column_diff_mask(&self, other: &Self) -> u64 {
+        self.matrix
+            .iter()
+            .zip(other.matrix.iter())
+            .fold(0, |mask, (a, b)| mask | (a.get() ^ b.get()) as u64)
+    }
 }
 
 impl CXCircuit16 {
@@ -132,11 +536,489 @@ impl CXCircuit16 {
         let matrix = matrix.map(|x| NonZeroU16::new(x).unwrap());
         Self { matrix }
     }
+
+    /// [`CXCircuit::mult_transpose`], one matrix entry at a time.
+    pub fn mult_transpose_scalar(&self, other: &Self) -> Self {
+        let mut result = [0; 16];
+        for (res_row, row) in result.iter_mut().zip(self.matrix.iter()) {
+            for (j, other_row) in other.matrix.iter().enumerate() {
+                let elem_wise_mult = row.get() & other_row.get();
+                let bit = (elem_wise_mult.count_ones() % 2) as u16;
+                if bit == 1 {
+                    *res_row += bit << j;
+                }
+            }
+        }
+        Self::from_mat(result)
+    }
+
+    /// [`CXCircuit::mult_transpose`], one row at a time using AVX2
+    /// instructions.
+    ///
+    /// Falls back to [`CXCircuit16::mult_transpose_scalar`] on CPUs without
+    /// AVX2. With the `simd` feature, `mult_transpose` uses this method.
+    #[cfg(feature = "simd")]
+    pub fn mult_transpose_simd(&self, other: &Self) -> Self {
+        let rows = |circ: &Self| circ.matrix.map(NonZeroU16::get);
+        match simd::mult_transpose(&rows(self), &rows(other)) {
+            Some(result) => Self::from_mat(result),
+            None => self.mult_transpose_scalar(other),
+        }
+    }
+
+    /// A uniformly random invertible circuit, i.e. a uniform element of
+    /// GL(16, 2).
+    ///
+    /// Random matrices are drawn until one is invertible, which takes fewer
+    /// than 4 attempts on average.
+    #[cfg(any(test, feature = "rand"))]
+    pub fn random_invertible(rng: &mut impl rand::Rng) -> Self {
+        use rand::RngExt;
+
+        loop {
+            let matrix: [u16; 16] = std::array::from_fn(|_| rng.random());
+            if matrix.contains(&0) {
+                continue;
+            }
+            let circuit = Self::from_mat(matrix);
+            if circuit.rank() == 16 {
+                return circuit;
+            }
+        }
+    }
+
+    /// The circuit with row `i` XOR-ed into row `j`.
+    ///
+    /// This is the matrix of `self` followed by a CX with control `i` and
+    /// target `j`, i.e. an immutable [`CXCircuit::add_cx`].
+    ///
+    /// Panics if `i == j`.
+    pub fn xor_rows(&self, i: usize, j: usize) -> Self {
+        assert_ne!(i, j, "cannot XOR a row into itself");
+        let mut circuit = *self;
+        circuit.add_cx(i, j);
+        circuit
+    }
+
+    /// A hash of the matrix that is stable across Rust and crate versions.
+    ///
+    /// Computed with FNV-1a on the rows in little-endian byte order, so it can
+    /// be used for persistent hash tables, checksums and cache keys.
+    pub fn deterministic_hash(&self) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+        const FNV_PRIME: u64 = 0x100000001b3;
+        self.matrix
+            .iter()
+            .flat_map(|row| row.get().to_le_bytes())
+            .fold(FNV_OFFSET_BASIS, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+            })
+    }
+
+    /// Row `i` of the matrix, as a bitmask.
+    pub fn row(&self, i: usize) -> u16 {
+        self.matrix[i].get()
+    }
+
+    /// The column-major representation of the matrix.
+    ///
+    /// Entry `j` is a bitmask of the rows that have a 1 in column `j`.
+    pub fn as_column_slice(&self) -> [u16; 16] {
+        self.transpose().matrix.map(|col| col.get())
+    }
+
+    /// The Kronecker product of two circuits acting on disjoint qubits.
+    ///
+    /// Returns the block-diagonal matrix `[[A, 0], [0, B]]`, where `self` (A)
+    /// acts on qubits `0..n1` and `other` (B) acts on qubits `n1..n1 + n2`.
+    /// The remaining qubits are left untouched.
+    ///
+    /// Panics if `n1 + n2 > 16`, or if `self` acts on qubits beyond `n1` or
+    /// `other` on qubits beyond `n2`.
+    pub fn kronecker_product(&self, other: &Self, n1: usize, n2: usize) -> Self {
+        assert!(
+            n1 + n2 <= 16,
+            "Kronecker product must act on at most 16 qubits"
+        );
+        // The identity on all qubits from `n` onwards
+        let is_block_identity = |circ: &Self, n: usize| {
+            (0..16).all(|i| {
+                if i < n {
+                    u32::from(circ.row(i)) >> n == 0
+                } else {
+                    circ.row(i) == 1 << i
+                }
+            })
+        };
+        assert!(
+            is_block_identity(self, n1),
+            "circuit acts beyond {n1} qubits"
+        );
+        assert!(
+            is_block_identity(other, n2),
+            "circuit acts beyond {n2} qubits"
+        );
+        let mut result = Self::new();
+        result.matrix[..n1].copy_from_slice(&self.matrix[..n1]);
+        for (row, other_row) in result.matrix[n1..n1 + n2].iter_mut().zip(other.matrix) {
+            *row = NonZeroU16::new(other_row.get() << n1).unwrap();
+        }
+        result
+    }
+
+    /// The tensor product of a circuit `a` on `n` qubits and `b` on `m` qubits.
+    ///
+    /// Unlike [`CXCircuit16::kronecker_product`], returns an error instead of
+    /// panicking if the result does not fit on 16 qubits. Still panics if `a`
+    /// or `b` act beyond their qubits.
+    pub fn tensor_product(a: &Self, n: usize, b: &Self, m: usize) -> Result<Self, DimensionError> {
+        if n + m > 16 {
+            return Err(DimensionError { n_qubits: n + m });
+        }
+        Ok(a.kronecker_product(b, n, m))
+    }
+
+    /// Swap rows `i` and `j` of the matrix.
+    ///
+    /// This relabels the output qubits `i` and `j` of the circuit.
+    pub fn row_swap(&self, i: usize, j: usize) -> Self {
+        let mut swapped = *self;
+        swapped.matrix.swap(i, j);
+        swapped
+    }
+
+    /// The rows of the matrix that differ from the identity.
+    ///
+    /// Returns pairs of row indices and row values. This is much more compact
+    /// than the full matrix for circuits that are close to the identity.
+    pub fn sparse_repr(&self) -> Vec<(usize, u16)> {
+        self.matrix
+            .iter()
+            .enumerate()
+            .filter(|&(i, row)| row.get() != 1 << i)
+            .map(|(i, row)| (i, row.get()))
+            .collect()
+    }
+
+    /// Construct a circuit from its sparse representation.
+    ///
+    /// Rows that are not specified are set to the identity.
+    pub fn from_sparse_repr(rows: impl IntoIterator<Item = (usize, u16)>) -> Self {
+        let mut circuit = Self::new();
+        for (i, row) in rows {
+            circuit.matrix[i] = NonZeroU16::new(row).expect("rows must be non-zero");
+        }
+        circuit
+    }
+
+    /// Construct a circuit from its row echelon form, up to a row permutation.
+    ///
+    /// `pivots` are the `(row, col)` positions of the leading 1 of each row;
+    /// rows without a pivot default to `(row, row)`. `free_entries` are the
+    /// `(row, col, value)` entries to the right of the pivots, all other
+    /// entries are 0. As the pivot columns are distinct, the matrix is always
+    /// invertible.
+    ///
+    /// Panics if two rows share a pivot column, or if a free entry is not to
+    /// the right of the pivot of its row.
+    pub fn from_row_echelon(
+        pivots: &[(usize, usize)],
+        free_entries: &[(usize, usize, bool)],
+    ) -> Self {
+        let mut pivot_cols: [usize; 16] = std::array::from_fn(|i| i);
+        for &(row, col) in pivots {
+            pivot_cols[row] = col;
+        }
+        let mut seen_cols = 0u16;
+        for &col in &pivot_cols {
+            assert!(
+                seen_cols & (1 << col) == 0,
+                "pivot columns must be distinct"
+            );
+            seen_cols |= 1 << col;
+        }
+
+        let mut matrix = pivot_cols.map(|col| 1u16 << col);
+        for &(row, col, value) in free_entries {
+            assert!(
+                col > pivot_cols[row],
+                "free entries must be to the right of the pivot"
+            );
+            if value {
+                matrix[row] |= 1 << col;
+            } else {
+                matrix[row] &= !(1 << col);
+            }
+        }
+        Self::from_mat(matrix)
+    }
+
+    /// Whether the sparse representation has at most `max_rows` rows.
+    ///
+    /// Use this to decide whether to store the sparse or the dense
+    /// representation of the circuit.
+    pub fn is_sparse(&self, max_rows: usize) -> bool {
+        self.hamming_distance_to_identity() <= max_rows
+    }
+
+    /// The number of rows that differ from the identity.
+    ///
+    /// A lower bound on `self.dist(&CXCircuit16::new())`, which also counts
+    /// differing columns. A quick metric of the circuit complexity.
+    pub fn hamming_distance_to_identity(&self) -> usize {
+        self.matrix
+            .iter()
+            .enumerate()
+            .filter(|&(i, row)| row.get() != 1 << i)
+            .count()
+    }
+
+    /// The number of off-diagonal 1-entries of the matrix.
+    ///
+    /// A measure of the entanglement complexity of the circuit, finer than
+    /// [`CXCircuit16::hamming_distance_to_identity`].
+    pub fn count_nonzero_offdiagonal(&self) -> usize {
+        self.matrix
+            .iter()
+            .enumerate()
+            .map(|(i, &row)| (row.get() & !(1 << i)).count_ones() as usize)
+            .sum()
+    }
+
+    /// The LU decomposition of the matrix over GF(2).
+    ///
+    /// Returns `(L, U)` such that `L.mult(&U) == self`, where `L` is
+    /// lower-triangular and `U` is upper-triangular, both with 1s on the
+    /// diagonal. Each factor can be synthesised independently using at most
+    /// 16 * 15 / 2 CX gates.
+    ///
+    /// Returns `None` if the decomposition requires pivoting, i.e. if a
+    /// leading principal minor of the matrix is zero.
+    pub fn lu_decomposition(&self) -> Option<(Self, Self)> {
+        let mut lower = eye::<16>().map(|row| row.get());
+        let mut upper = self.matrix.map(|row| row.get());
+        for k in 0..16 {
+            if upper[k] & (1 << k) == 0 {
+                return None;
+            }
+            for i in k + 1..16 {
+                if upper[i] & (1 << k) != 0 {
+                    upper[i] ^= upper[k];
+                    lower[i] |= 1 << k;
+                }
+            }
+        }
+        Some((Self::from_mat(lower), Self::from_mat(upper)))
+    }
+
+    /// The reduced row echelon form of the matrix, and its pivot columns.
+    ///
+    /// Row `i` of the echelon form has its leading 1 in column `pivots[i]`,
+    /// all rows after `pivots.len()` are zero. Rows may be zero, so they are
+    /// returned as plain `u16`s.
+    pub fn echelon_form(&self) -> ([u16; 16], Vec<usize>) {
+        let mut rows = self.matrix.map(|row| row.get() as u64);
+        let pivots = echelon_form(&mut rows, 16);
+        (rows.map(|row| row as u16), pivots)
+    }
+
+    /// The rank of the matrix over GF(2).
+    ///
+    /// Circuits built from CX gates always have full rank 16.
+    pub fn rank(&self) -> usize {
+        self.echelon_form().1.len()
+    }
+
+    /// The reduced row echelon form of the matrix, along with the number of
+    /// row additions used to reach it.
+    ///
+    /// Rows are never swapped, so every row addition is a CX gate and the
+    /// count is an upper bound on the CX count of `self` with all-to-all
+    /// connectivity. The reduced form of an invertible matrix is the
+    /// identity.
+    ///
+    /// Panics if the matrix is singular.
+    pub fn row_echelon_form(&self) -> (Self, usize) {
+        let mut circ = *self;
+        let mut n_ops = 0;
+        for col in 0..16 {
+            if circ.matrix[col].get() & (1 << col) == 0 {
+                let pivot = (col + 1..16)
+                    .find(|&row| circ.matrix[row].get() & (1 << col) != 0)
+                    .expect("matrix is singular");
+                circ.add_cx(pivot, col);
+                n_ops += 1;
+            }
+            for row in (0..16).filter(|&row| row != col) {
+                if circ.matrix[row].get() & (1 << col) != 0 {
+                    circ.add_cx(col, row);
+                    n_ops += 1;
+                }
+            }
+        }
+        (circ, n_ops)
+    }
+
+    /// A lower bound on the number of CX gates needed to map `self` to
+    /// `target`, used as the A* heuristic of [`AStarValue::dist`].
+    ///
+    /// The CXs applied to `self` form the remaining circuit
+    /// `target * self^-1`. A CX changes a single row of `self` and a single
+    /// column of the remaining circuit, so at least as many CXs are needed as
+    /// rows of `self` that differ from `target`, and as columns of the
+    /// remaining circuit that differ from the identity.
+    pub fn cx_lower_bound(&self, target: &Self) -> usize {
+        let diff_rows = self
+            .matrix
+            .iter()
+            .zip(target.matrix.iter())
+            .filter(|(a, b)| a != b)
+            .count();
+        if diff_rows == 0 {
+            return 0;
+        }
+        let remaining = target.mult(&self.inverse());
+        let diff_cols = remaining.column_diff_mask(&Self::new()).count_ones() as usize;
+        diff_rows.max(diff_cols)
+    }
+
+    /// The GF(2) trace of the matrix, i.e. the XOR of its diagonal elements.
+    ///
+    /// A cheap circuit invariant: circuits with different traces are
+    /// different. The trace of the identity is 0.
+    pub fn trace(&self) -> u16 {
+        self.matrix
+            .iter()
+            .enumerate()
+            .fold(0, |trace, (i, row)| trace ^ ((row.get() >> i) & 1))
+    }
+
+    /// A sequence of CX gates that maps the identity to `self`, using
+    /// all-to-all connectivity.
+    ///
+    /// Returns `None` if the matrix is singular.
+    pub fn decompose(&self) -> Option<Vec<CX>> {
+        let all_to_all = (0..16)
+            .flat_map(|ctrl| (0..16).map(move |tgt| (ctrl, tgt)))
+            .filter(|(ctrl, tgt)| ctrl != tgt)
+            .map(CX::from)
+            .collect();
+        synthesize_by_elimination(&Self::new(), self, &all_to_all)
+    }
+
+    /// The gates of [`CXCircuit16::decompose`], grouped into layers of CXs
+    /// on disjoint qubits.
+    ///
+    /// Returns `None` if the matrix is singular.
+    pub fn to_layers(self) -> Option<Vec<Vec<CX>>> {
+        self.decompose().map(|cxs| into_layers(&cxs))
+    }
+
+    /// The depth of a circuit implementing `self`, i.e. its number of layers.
+    ///
+    /// The circuit is obtained by Gaussian elimination, using only the
+    /// interactions of `topology` if one is given. This is an upper bound on
+    /// the optimal depth. Returns `None` if the matrix is singular or cannot
+    /// be eliminated within `topology`.
+    pub fn depth(&self, topology: Option<&Topology>) -> Option<usize> {
+        let cxs = match topology {
+            Some(topology) => {
+                let allowed_moves = FxHashSet::from_iter(topology.to_cx_moves());
+                synthesize_by_elimination(&Self::new(), self, &allowed_moves)?
+            }
+            None => self.decompose()?,
+        };
+        Some(into_layers(&cxs).len())
+    }
+
+    /// Apply the circuit to a stabiliser state.
+    ///
+    /// The X stabilisers of the result are the product of the circuit matrix
+    /// with the X stabilisers of `state`, over GF(2).
+    pub fn apply_to_stabiliser_state(&self, state: &StabiliserState<16>) -> StabiliserState<16> {
+        // The Z parts and phases depend on the matrix as a whole, so apply
+        // the gates one by one
+        let cxs = self.decompose().expect("circuits are invertible");
+        cxs.iter()
+            .fold(state.clone(), |state, cx| state.cx(cx.ctrl, cx.tgt))
+    }
+
+    /// Whether the matrix is a permutation matrix, i.e. the circuit only
+    /// relabels qubits.
+    pub fn is_permutation_matrix(&self) -> bool {
+        self.to_permutation().is_some()
+    }
+
+    /// The permutation of the matrix, if it is a permutation matrix.
+    ///
+    /// Row `i` of the matrix has its 1 in column `perm[i]`.
+    pub fn to_permutation(&self) -> Option<Vec<usize>> {
+        let mut seen = 0;
+        let mut perm = Vec::with_capacity(16);
+        for row in self.matrix {
+            let row = row.get();
+            if row.count_ones() != 1 || seen & row != 0 {
+                return None;
+            }
+            seen |= row;
+            perm.push(row.trailing_zeros() as usize);
+        }
+        Some(perm)
+    }
+
+    /// Compose the circuit with a permutation matrix, in O(N).
+    ///
+    /// Row `i` of the result is row `perm[i]` of `self`. This is equal to
+    /// `p.mult(self)`, where `p` is the permutation matrix with
+    /// `p.to_permutation() == Some(perm)`.
+    ///
+    /// Panics if `perm` is not a permutation of `0..16`.
+    pub fn compose_with_permutation(&self, perm: &[usize; 16]) -> Self {
+        let seen = perm.iter().fold(0u32, |seen, &i| seen | 1 << i.min(16));
+        assert_eq!(seen, 0xffff, "not a permutation: {perm:?}");
+        Self {
+            matrix: perm.map(|i| self.matrix[i]),
+        }
+    }
+
+    /// Whether the matrix is equal to its transpose.
+    pub fn is_symmetric(&self) -> bool {
+        *self == self.transpose()
+    }
+
+    /// The submatrix restricted to the rows and columns in `qubits`.
+    ///
+    /// Qubit `qubits[i]` is relabelled to `i`, i.e. row `i` of the result is
+    /// row `qubits[i]` of `self`, restricted to the columns in `qubits`. Rows
+    /// may be zero, so they are returned as plain `u16`s.
+    pub fn submatrix(&self, qubits: &[usize]) -> Vec<u16> {
+        qubits
+            .iter()
+            .map(|&row| {
+                let row = self.matrix[row].get();
+                qubits
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &col)| row & (1 << col) != 0)
+                    .fold(0, |acc, (j, _)| acc | (1 << j))
+            })
+            .collect()
+    }
+
+    /// Whether the circuit restricted to `qubits` matches `pattern`.
+    ///
+    /// Qubit `i` of `pattern` is matched with qubit `qubits[i]` of `self`.
+    pub fn pattern_match(&self, pattern: &Self, qubits: &[usize]) -> bool {
+        let pattern_qubits = (0..qubits.len()).collect::<Vec<_>>();
+        self.submatrix(qubits) == pattern.submatrix(&pattern_qubits)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Topology;
+    use generic::CXCircuit8;
 
     fn sum_pow_two(vals: impl IntoIterator<Item = u16>) -> NonZeroU16 {
         let mut sum = 0;
@@ -146,6 +1028,140 @@ mod tests {
         NonZeroU16::new(sum).unwrap()
     }
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip_16() {
+        use rand::{rngs::StdRng, RngExt, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..20 {
+            let cxs = (0..30).map(|_| {
+                let ctrl = rng.random_range(0..16);
+                (ctrl, (ctrl + rng.random_range(1..16)) % 16)
+            });
+            let circ = CXCircuit16::from_cxs(cxs.collect::<Vec<_>>());
+            let json = serde_json::to_string(&circ).unwrap();
+            assert_eq!(serde_json::from_str::<CXCircuit16>(&json).unwrap(), circ);
+        }
+        let json = serde_json::to_string(&CXCircuit16::new()).unwrap();
+        assert_eq!(
+            json,
+            format!("{:?}", eye::<16>().map(NonZeroU16::get)).replace(' ', "")
+        );
+
+        let zero_row = format!("[0{}]", ",1".repeat(15));
+        assert!(serde_json::from_str::<CXCircuit16>(&zero_row).is_err());
+        assert!(serde_json::from_str::<CXCircuit16>("[1,2,4]").is_err());
+    }
+
+    #[test]
+    fn random_16() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let moves = [(0, 1), (1, 0), (1, 2)].map(CX::from);
+        let circ = CXCircuit16::random(10, &moves, &mut rng);
+        // Only the first three qubits are acted on
+        assert!((3..16).all(|i| circ.row(i) == 1 << i));
+        assert_eq!(CXCircuit16::random(0, &moves, &mut rng), CXCircuit16::new());
+        for _ in 0..10 {
+            assert_eq!(CXCircuit16::random_invertible(&mut rng).rank(), 16);
+        }
+    }
+
+    #[test]
+    fn display_from_str_16() {
+        let identity = CXCircuit16::new().to_string();
+        assert_eq!(identity.lines().next(), Some("1000000000000000"));
+        assert_eq!(identity.lines().count(), 16);
+        let circuits = [
+            CXCircuit16::new(),
+            CXCircuit16::from_cxs([(0, 1)]),
+            CXCircuit16::from_cxs([(0, 15), (15, 3), (7, 8), (8, 0)]),
+        ];
+        for circ in circuits {
+            let s = circ.to_string();
+            assert_eq!(s.parse::<CXCircuit16>().unwrap(), circ);
+            assert_eq!(s.parse::<CXCircuit16>().unwrap().to_string(), s);
+        }
+        // Row 1 has qubit 0 added to it
+        let s = CXCircuit16::from_cxs([(0, 1)]).to_string();
+        assert_eq!(s.lines().nth(1), Some("1100000000000000"));
+        assert_eq!(
+            format!("\n{s}\n\n").parse(),
+            Ok(CXCircuit16::from_cxs([(0, 1)]))
+        );
+
+        let zero_row = s.replacen("1000000000000000", "0000000000000000", 1);
+        assert_eq!(
+            zero_row.parse::<CXCircuit16>(),
+            Err(ParseCircuitError::ZeroRow { row: 0 })
+        );
+        let invalid = s.replacen("1000000000000000", "2000000000000000", 1);
+        assert_eq!(
+            invalid.parse::<CXCircuit16>(),
+            Err(ParseCircuitError::InvalidEntry { row: 0 })
+        );
+        let short = s.replacen("1000000000000000", "100000000000000", 1);
+        assert_eq!(
+            short.parse::<CXCircuit16>(),
+            Err(ParseCircuitError::RowLength { row: 0 })
+        );
+        let missing_row = s.lines().skip(1).collect::<Vec<_>>().join("\n");
+        assert_eq!(
+            missing_row.parse::<CXCircuit16>(),
+            Err(ParseCircuitError::RowCount(15))
+        );
+    }
+
+    #[test]
+    fn dense_matrix() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let circuits = [
+            CXCircuit16::new(),
+            CXCircuit16::from_cxs([(0, 1)]),
+            CXCircuit16::random_invertible(&mut rng),
+        ];
+        for circ in circuits {
+            let matrix = circ.to_dense_matrix();
+            assert_eq!(CXCircuit16::from_dense_matrix(&matrix), Ok(circ));
+        }
+        let matrix = CXCircuit16::from_cxs([(0, 1)]).to_dense_matrix();
+        assert!(matrix[0][0] && matrix[1][0] && matrix[1][1]);
+        assert_eq!(matrix.iter().flatten().filter(|&&entry| entry).count(), 17);
+        let matrix8 = CXCircuit8::from_cxs([(7, 0)]).to_dense_matrix();
+        assert_eq!(
+            CXCircuit8::from_dense_matrix(&matrix8),
+            Ok(CXCircuit8::from_cxs([(7, 0)]))
+        );
+
+        assert_eq!(
+            CXCircuit16::from_dense_matrix(&matrix8),
+            Err(MatrixError::WrongSize(8))
+        );
+        let mut non_square = matrix.clone();
+        non_square[3].pop();
+        assert_eq!(
+            CXCircuit16::from_dense_matrix(&non_square),
+            Err(MatrixError::NonSquare { row: 3 })
+        );
+        let mut zero_row = matrix.clone();
+        zero_row[2][2] = false;
+        assert_eq!(
+            CXCircuit16::from_dense_matrix(&zero_row),
+            Err(MatrixError::SingularMatrix)
+        );
+        // Rows 0 and 1 are now equal
+        let mut equal_rows = matrix;
+        equal_rows[1][1] = false;
+        assert_eq!(
+            CXCircuit16::from_dense_matrix(&equal_rows),
+            Err(MatrixError::SingularMatrix)
+        );
+    }
+
     #[test]
     fn test_cx_16() {
         let mut cx = CXCircuit16::new();
@@ -210,4 +1226,456 @@ mod tests {
         let t = cx.transpose();
         assert_eq!(t, CXCircuit16::from_mat(mat_t));
     }
+
+    #[test]
+    fn is_identity_16() {
+        assert!(CXCircuit16::new().is_identity());
+        for ctrl in 0..16 {
+            for tgt in (0..16).filter(|&tgt| tgt != ctrl) {
+                let circ = CXCircuit16::from_cxs([(ctrl, tgt)]);
+                assert!(!circ.is_identity());
+                assert!(CXCircuit16::from_cxs([(ctrl, tgt), (ctrl, tgt)]).is_identity());
+            }
+        }
+        assert!(CXCircuit8::new().is_identity());
+        assert!(!CXCircuit8::from_cxs([(0, 7)]).is_identity());
+    }
+
+    #[test]
+    fn inverse_16() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        assert_eq!(CXCircuit16::new().inverse(), CXCircuit16::new());
+        let circ = CXCircuit16::from_cxs([(0, 1), (1, 2), (2, 0)]);
+        // CX gates are self-inverse
+        assert_eq!(
+            circ.inverse(),
+            CXCircuit16::from_cxs([(2, 0), (1, 2), (0, 1)])
+        );
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..20 {
+            let circ = CXCircuit16::random_invertible(&mut rng);
+            assert!(circ.mult(&circ.inverse()).is_identity());
+            assert!(circ.inverse().mult(&circ).is_identity());
+        }
+        // The default implementation agrees
+        let circ = CXCircuit8::from_cxs([(0, 1), (1, 2), (2, 0), (7, 3), (3, 5)]);
+        assert!(circ.mult(&circ.inverse()).is_identity());
+        for _ in 0..5 {
+            let circ = CXCircuit16::random_invertible(&mut rng);
+            let rows: Vec<u64> = (0..16).map(|i| circ.row_bits(i)).collect();
+            let generic_circ = generic::CXCircuitN::<NonZeroU16, 16>::from_row_bits(&rows);
+            let inverse: Vec<u64> = (0..16)
+                .map(|i| generic_circ.inverse().row_bits(i))
+                .collect();
+            assert_eq!(CXCircuit16::from_row_bits(&inverse), circ.inverse());
+        }
+    }
+
+    #[test]
+    fn commutes_with_16() {
+        let a = CXCircuit16::from_cxs([(0, 1), (2, 3)]);
+        let disjoint = CXCircuit16::from_cxs([(4, 5), (7, 6)]);
+        assert!(a.commutes_with(&disjoint));
+        assert!(disjoint.commutes_with(&a));
+        // CXs sharing a control, or a target, commute
+        assert!(CXCircuit16::from_cxs([(0, 1)]).commutes_with(&CXCircuit16::from_cxs([(0, 2)])));
+        assert!(CXCircuit16::from_cxs([(0, 2)]).commutes_with(&CXCircuit16::from_cxs([(1, 2)])));
+        // The target of one is the control of the other
+        let overlapping = CXCircuit16::from_cxs([(1, 2)]);
+        assert!(!a.commutes_with(&overlapping));
+        assert!(!overlapping.commutes_with(&a));
+        for circ in [a, disjoint, overlapping, CXCircuit16::new()] {
+            assert!(circ.commutes_with(&circ));
+            assert!(circ.commutes_with(&CXCircuit16::new()));
+            // Agrees with the default implementation
+            for other in [a, disjoint, overlapping] {
+                assert_eq!(
+                    circ.commutes_with(&other),
+                    circ.mult(&other) == other.mult(&circ)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn apply_permutation_16() {
+        let cx = CXCircuit16::from_cxs([(0, 1), (3, 2)]);
+        let perm = [2, 0, 1, 3];
+        assert_eq!(
+            cx.apply_permutation(&perm),
+            CXCircuit16::from_cxs([(2, 0), (3, 1)])
+        );
+
+        let identity = Vec::from_iter(0..16);
+        assert_eq!(cx.apply_permutation(&identity), cx);
+        // Reversing the qubit order
+        let reverse = Vec::from_iter((0..16).rev());
+        assert_eq!(
+            cx.apply_permutation(&reverse),
+            CXCircuit16::from_cxs([(15, 14), (12, 13)])
+        );
+        for perm in [&perm[..], &reverse] {
+            assert_eq!(
+                CXCircuit16::new().apply_permutation(perm),
+                CXCircuit16::new()
+            );
+        }
+    }
+
+    #[test]
+    fn equivalent_up_to_symmetry() {
+        // Two CXs on adjacent edges of a ring, and the same CXs rotated
+        let circ = CXCircuit16::from_cxs([(0, 1), (1, 2)]);
+        let rotated = CXCircuit16::from_cxs([(2, 3), (3, 0)]);
+        let automorphisms = Topology::ring(4).automorphism_group();
+        assert!(automorphisms
+            .iter()
+            .any(|perm| circ.apply_permutation(perm) == rotated));
+        // Swapping qubits 1 and 2 gives CXs that are not on the ring
+        let swapped = CXCircuit16::from_cxs([(0, 2), (2, 1)]);
+        assert_eq!(circ.apply_permutation(&[0, 2, 1]), swapped);
+        assert!(automorphisms
+            .iter()
+            .all(|perm| circ.apply_permutation(perm) != swapped));
+    }
+
+    #[test]
+    fn column_diff_mask_16() {
+        let cx = CXCircuit16::from_cxs([(0, 1), (3, 2)]);
+        assert_eq!(cx.column_diff_mask(&cx), 0);
+        assert_eq!(cx.column_diff_mask(&CXCircuit16::new()), 0b1001);
+    }
+
+    #[test]
+    fn lu_decomposition_16() {
+        let cx = CXCircuit16::from_cxs([(0, 3), (2, 1), (5, 4), (1, 2), (3, 5)]);
+        let (lower, upper) = cx.lu_decomposition().unwrap();
+        assert_eq!(lower.mult(&upper), cx);
+        for i in 0..16 {
+            assert_eq!(lower.row(i) >> i, 1);
+            assert_eq!(upper.row(i).trailing_zeros() as usize, i);
+        }
+
+        // A qubit swap has no LU decomposition without pivoting
+        let swap = CXCircuit16::from_cxs([(0, 1), (1, 0), (0, 1)]);
+        assert_eq!(swap.lu_decomposition(), None);
+    }
+
+    #[test]
+    fn from_row_echelon_16() {
+        let cx = CXCircuit16::from_row_echelon(&[], &[(0, 3, true), (2, 4, true), (2, 4, false)]);
+        assert_eq!(cx, CXCircuit16::from_cxs([(3, 0)]));
+
+        let swapped = CXCircuit16::from_row_echelon(&[(0, 1), (1, 0)], &[(1, 5, true)]);
+        assert_eq!(swapped.row(0), 0b10);
+        assert_eq!(swapped.row(1), 0b100001);
+    }
+
+    #[test]
+    #[should_panic]
+    fn from_row_echelon_repeated_pivot() {
+        CXCircuit16::from_row_echelon(&[(0, 1)], &[]);
+    }
+
+    #[test]
+    fn mult_checked_16() {
+        let a = CXCircuit16::from_cxs([(0, 1)]);
+        let b = CXCircuit16::from_cxs([(2, 1), (1, 0)]);
+        assert_eq!(a.mult_checked(&b), Some(a.mult(&b)));
+
+        // A singular matrix, with two equal rows
+        let mut rows = eye::<16>().map(|row| row.get());
+        rows[1] = rows[0];
+        // Row 1 of `a` is the sum of the two equal rows
+        let singular = CXCircuit16::from_mat(rows);
+        assert_eq!(a.mult_checked(&singular), None);
+    }
+
+    #[test]
+    fn depth_16() {
+        assert_eq!(CXCircuit16::new().depth(None), Some(0));
+        assert_eq!(CXCircuit16::new().to_layers(), Some(vec![]));
+
+        let disjoint = CXCircuit16::from_cxs([(0, 1), (2, 3), (5, 4)]);
+        assert_eq!(disjoint.depth(None), Some(1));
+        let layers = disjoint.to_layers().unwrap();
+        assert_eq!(layers.len(), 1);
+        assert_eq!(
+            CXCircuit16::from_cxs(
+                layers
+                    .concat()
+                    .into_iter()
+                    .map(|cx| (cx.ctrl as usize, cx.tgt as usize))
+            ),
+            disjoint
+        );
+
+        let chain = [(0, 1), (1, 2), (2, 3)].map(CX::from);
+        assert_eq!(into_layers(&chain), chain.map(|cx| vec![cx]).to_vec());
+        let chain = CXCircuit16::from_cxs([(2, 3), (1, 2), (0, 1)]);
+        let line = Topology::linear(16);
+        assert_eq!(chain.depth(Some(&line)), Some(3));
+        assert_eq!(CXCircuit16::from_cxs([(0, 2)]).depth(Some(&line)), None);
+    }
+
+    #[test]
+    fn apply_to_stabiliser_state_16() {
+        let circ = CXCircuit16::from_cxs([(0, 1), (2, 0), (1, 3)]);
+        let cxs = circ.decompose().unwrap();
+        assert_eq!(
+            CXCircuit16::from_cxs(cxs.iter().map(|cx| (cx.ctrl as usize, cx.tgt as usize))),
+            circ
+        );
+
+        let other = CXCircuit16::from_cxs([(4, 5), (1, 4)]);
+        let state = StabiliserState::from_cx_circuit(&other);
+        let expected = StabiliserState::from_cx_circuit(&circ.mult(&other));
+        assert_eq!(circ.apply_to_stabiliser_state(&state), expected);
+        assert_eq!(expected.to_cx_circuit(), Some(circ.mult(&other)));
+    }
+
+    #[test]
+    fn echelon_form_16() {
+        let circ = CXCircuit16::from_cxs([(0, 1), (2, 0), (1, 3)]);
+        let (rows, pivots) = circ.echelon_form();
+        assert_eq!(rows, std::array::from_fn(|i| 1 << i));
+        assert_eq!(pivots, (0..16).collect::<Vec<_>>());
+        assert_eq!(circ.rank(), 16);
+
+        // Rows 0 and 1 are equal, so column 1 has no pivot
+        let singular = CXCircuit16::from_sparse_repr([(1, 0b1)]);
+        let (rows, pivots) = singular.echelon_form();
+        assert_eq!(pivots, [0].into_iter().chain(2..16).collect::<Vec<_>>());
+        assert_eq!(rows[0], 0b1);
+        assert_eq!(rows[1], 0b100);
+        assert_eq!(rows[15], 0);
+        assert_eq!(singular.rank(), 15);
+    }
+
+    #[test]
+    fn row_echelon_form_16() {
+        assert_eq!(
+            CXCircuit16::new().row_echelon_form(),
+            (CXCircuit16::new(), 0)
+        );
+        let circ = CXCircuit16::from_cxs([(0, 1), (2, 0), (1, 3)]);
+        let (reduced, n_ops) = circ.row_echelon_form();
+        assert_eq!(reduced, CXCircuit16::new());
+        assert!(n_ops >= 3);
+        // Column 0 has no pivot on the diagonal, so a row is added to row 0
+        let (_, n_ops) = CXCircuit16::from_cxs([(0, 1), (1, 0)]).row_echelon_form();
+        assert_eq!(n_ops, 2);
+    }
+
+    #[test]
+    fn cx_lower_bound_16() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let id = CXCircuit16::new();
+        assert_eq!(id.cx_lower_bound(&id), 0);
+        // Rows 1 and 2 both differ from the identity, in column 0
+        let fan_out = CXCircuit16::from_cxs([(0, 1), (0, 2)]);
+        assert_eq!(id.cx_lower_bound(&fan_out), 2);
+        // Only row 0 differs, but in columns 1 and 2
+        let fan_in = CXCircuit16::from_cxs([(1, 0), (2, 0)]);
+        assert_eq!(fan_in.hamming_distance_to_identity(), 1);
+        assert_eq!(id.cx_lower_bound(&fan_in), 2);
+        assert_eq!(fan_in.cx_lower_bound(&id), 2);
+        assert_eq!(id.dist(&fan_in), 2);
+
+        // The bound never exceeds the optimal CX count
+        let moves = Topology::all_to_all(4).to_cx_moves();
+        let mut rng = StdRng::seed_from_u64(42);
+        for n_gates in 0..6 {
+            let start = CXCircuit16::random(2, &moves, &mut rng);
+            let target = CXCircuit16::random(n_gates, &moves, &mut rng).mult(&start);
+            let solution = crate::a_star(start, &target, moves.clone(), None, None).unwrap();
+            assert!(start.cx_lower_bound(&target) <= solution.len());
+        }
+    }
+
+    #[test]
+    fn permutation_matrix_16() {
+        let identity = CXCircuit16::new();
+        assert!(identity.is_permutation_matrix());
+        assert_eq!(identity.to_permutation(), Some((0..16).collect()));
+
+        let swap = CXCircuit16::from_cxs([(0, 1), (1, 0), (0, 1)]);
+        let mut perm: Vec<usize> = (0..16).collect();
+        perm.swap(0, 1);
+        assert_eq!(swap.to_permutation(), Some(perm));
+
+        assert!(!CXCircuit16::from_cxs([(0, 1)]).is_permutation_matrix());
+        // Single 1s per row, but a repeated column
+        assert!(!CXCircuit16::from_sparse_repr([(1, 0b1)]).is_permutation_matrix());
+    }
+
+    #[test]
+    fn compose_with_permutation_16() {
+        let circ = CXCircuit16::from_cxs([(0, 1), (2, 0), (1, 3)]);
+        let perm = std::array::from_fn(|i| (i + 3) % 16);
+        let perm_circ = CXCircuit16::from_sparse_repr(perm.iter().map(|&j| 1 << j).enumerate());
+        assert_eq!(perm_circ.to_permutation(), Some(perm.to_vec()));
+        assert_eq!(circ.compose_with_permutation(&perm), perm_circ.mult(&circ));
+    }
+
+    #[test]
+    #[should_panic(expected = "not a permutation")]
+    fn apply_non_permutation_16() {
+        CXCircuit16::new().apply_permutation(&[1, 1, 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "not a permutation")]
+    fn apply_permutation_out_of_range_16() {
+        // Qubit 3 is beyond the end of the permutation
+        CXCircuit16::new().apply_permutation(&[0, 3, 1]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn compose_with_non_permutation_16() {
+        let mut perm = std::array::from_fn(|i| i);
+        perm[3] = 2;
+        CXCircuit16::new().compose_with_permutation(&perm);
+    }
+
+    #[test]
+    fn xor_rows_16() {
+        let circ = CXCircuit16::from_cxs([(0, 1), (2, 0)]);
+        let mut expected = circ;
+        expected.add_cx(1, 3);
+        assert_eq!(circ.xor_rows(1, 3), expected);
+        assert_eq!(circ.xor_rows(1, 3).row(3), circ.row(1) ^ circ.row(3));
+    }
+
+    #[test]
+    #[should_panic]
+    fn xor_rows_same_row_16() {
+        CXCircuit16::new().xor_rows(2, 2);
+    }
+
+    #[test]
+    fn trace_16() {
+        assert_eq!(CXCircuit16::new().trace(), 0);
+        assert_eq!(CXCircuit16::from_cxs([(0, 1)]).trace(), 0);
+        // A SWAP has two zeros on the diagonal
+        let swap = CXCircuit16::from_cxs([(0, 1), (1, 0), (0, 1)]);
+        assert_eq!(swap.trace(), 0);
+        // Rows 0 and 1 become e1 and e0 + e1: a single zero on the diagonal
+        let cx = CXCircuit16::from_cxs([(0, 1), (1, 0)]);
+        assert_eq!(cx.trace(), 1);
+    }
+
+    #[test]
+    fn is_symmetric_16() {
+        assert!(CXCircuit16::new().is_symmetric());
+        assert!(!CXCircuit16::from_cxs([(0, 1)]).is_symmetric());
+        // A SWAP is a symmetric permutation matrix
+        assert!(CXCircuit16::from_cxs([(0, 1), (1, 0), (0, 1)]).is_symmetric());
+    }
+
+    #[test]
+    fn deterministic_hash_16() {
+        assert_eq!(CXCircuit16::new().deterministic_hash(), 0xc888ff5202ebcc7b);
+        assert_ne!(
+            CXCircuit16::from_cxs([(0, 1)]).deterministic_hash(),
+            CXCircuit16::from_cxs([(1, 0)]).deterministic_hash()
+        );
+    }
+
+    #[test]
+    fn as_column_slice_16() {
+        let cx = CXCircuit16::from_cxs([(0, 1), (0, 2)]);
+        let cols = cx.as_column_slice();
+        assert_eq!(cols[0], 0b111);
+        assert_eq!(cols[1], 0b10);
+        assert_eq!(cols[3], 0b1000);
+    }
+
+    #[test]
+    fn kronecker_product_16() {
+        let a = CXCircuit16::from_cxs([(0, 1), (2, 0)]);
+        let b = CXCircuit16::from_cxs([(1, 0), (0, 3)]);
+        let kron = a.kronecker_product(&b, 3, 4);
+        assert_eq!(
+            kron,
+            CXCircuit16::from_cxs([(0, 1), (2, 0), (4, 3), (3, 6)])
+        );
+        assert_eq!(a.kronecker_product(&CXCircuit16::new(), 16, 0), a);
+    }
+
+    #[test]
+    #[should_panic]
+    fn kronecker_product_overlap_16() {
+        // `a` acts on qubit 3, which `b` is placed on
+        let a = CXCircuit16::from_cxs([(0, 3)]);
+        let b = CXCircuit16::from_cxs([(1, 0)]);
+        a.kronecker_product(&b, 3, 2);
+    }
+
+    #[test]
+    fn tensor_product_16() {
+        let a = CXCircuit16::from_cxs([(0, 1)]);
+        let b = CXCircuit16::from_cxs([(1, 0)]);
+        assert_eq!(
+            CXCircuit16::tensor_product(&a, 2, &b, 2),
+            Ok(CXCircuit16::from_cxs([(0, 1), (3, 2)]))
+        );
+        assert_eq!(
+            CXCircuit16::tensor_product(&a, 10, &b, 7),
+            Err(DimensionError { n_qubits: 17 })
+        );
+    }
+
+    #[test]
+    fn row_swap_16() {
+        let cx = CXCircuit16::from_cxs([(0, 1), (3, 2)]);
+        let swapped = cx.row_swap(1, 2);
+        assert_eq!(swapped.matrix[1], cx.matrix[2]);
+        assert_eq!(swapped.matrix[2], cx.matrix[1]);
+        assert_eq!(swapped.row_swap(2, 1), cx);
+    }
+
+    #[test]
+    fn sparse_repr_16() {
+        let cx = CXCircuit16::from_cxs([(0, 1), (3, 2)]);
+        assert_eq!(cx.sparse_repr(), vec![(1, 0b11), (2, 0b1100)]);
+        assert_eq!(CXCircuit16::from_sparse_repr(cx.sparse_repr()), cx);
+        assert!(cx.is_sparse(2));
+        assert!(!cx.is_sparse(1));
+        assert!(CXCircuit16::new().sparse_repr().is_empty());
+    }
+
+    #[test]
+    fn hamming_distance_to_identity_16() {
+        let cx = CXCircuit16::from_cxs([(0, 1), (3, 2), (4, 2)]);
+        assert_eq!(cx.hamming_distance_to_identity(), 2);
+        assert!(cx.hamming_distance_to_identity() <= cx.dist(&CXCircuit16::new()));
+        assert_eq!(CXCircuit16::new().hamming_distance_to_identity(), 0);
+    }
+
+    #[test]
+    fn count_nonzero_offdiagonal_16() {
+        assert_eq!(CXCircuit16::new().count_nonzero_offdiagonal(), 0);
+        let cx = CXCircuit16::from_cxs([(0, 1), (3, 2), (4, 2)]);
+        assert_eq!(cx.count_nonzero_offdiagonal(), 3);
+        // The swap of qubits 0 and 1 has zeros on the diagonal
+        let swap = CXCircuit16::from_cxs([(0, 1), (1, 0), (0, 1)]);
+        assert_eq!(swap.count_nonzero_offdiagonal(), 2);
+        assert_eq!(swap.hamming_distance_to_identity(), 2);
+    }
+    #[test]
+    fn pattern_match_16() {
+        let cx = CXCircuit16::from_cxs([(5, 2), (2, 7), (0, 1)]);
+        assert_eq!(cx.submatrix(&[5, 2]), vec![0b01, 0b11]);
+
+        let pattern = CXCircuit16::from_cxs([(0, 1)]);
+        assert!(cx.pattern_match(&pattern, &[5, 2]));
+        assert!(cx.pattern_match(&pattern, &[0, 1]));
+        assert!(!cx.pattern_match(&pattern, &[2, 5]));
+        assert!(cx.pattern_match(&pattern, &[2, 7]));
+        assert!(!cx.pattern_match(&pattern, &[1, 0]));
+    }
 }
@@ -0,0 +1,298 @@
+//! CX circuits on any number of qubits up to 64.
+
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::num::{NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8};
+
+use fxhash::FxHashSet;
+
+use super::{assert_permutation, CXCircuit};
+use crate::a_star::AStarValue;
+
+/// A non-zero unsigned integer type used to store a row of a circuit matrix.
+///
+/// Bit `j` of row `i` is set if entry `(i, j)` of the matrix is 1. Rows of
+/// invertible matrices are never zero, so `Option<CXCircuitN<R, N>>` is no
+/// larger than the circuit itself.
+pub trait Row: Copy + Debug + Eq + Ord + Hash + Send + Sync {
+    /// The maximum number of qubits a row can represent
+    const BITS: usize;
+
+    /// Panics if `bits` is zero.
+    fn from_u64(bits: u64) -> Self;
+    fn to_u64(self) -> u64;
+}
+
+macro_rules! impl_row {
+    ($($t:ty => $int:ty),*) => {
+        $(
+            impl Row for $t {
+                const BITS: usize = <$t>::BITS as usize;
+
+                fn from_u64(bits: u64) -> Self {
+                    <$t>::new(bits as $int).expect("rows must be non-zero")
+                }
+
+                fn to_u64(self) -> u64 {
+                    self.get() as u64
+                }
+            }
+        )*
+    };
+}
+
+impl_row!(NonZeroU8 => u8, NonZeroU16 => u16, NonZeroU32 => u32, NonZeroU64 => u64);
+
+/// An N-qubit CX circuit, with rows of type `R`.
+///
+/// Represented by a boolean matrix. `N` must be at most `R::BITS`; use the
+/// smallest row type that fits, e.g. through the [`CXCircuit8`],
+/// [`CXCircuit32`] and [`CXCircuit64`] aliases.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct CXCircuitN<R, const N: usize> {
+    matrix: [R; N],
+}
+
+/// An 8-qubit CX circuit.
+pub type CXCircuit8 = CXCircuitN<NonZeroU8, 8>;
+/// A 32-qubit CX circuit.
+pub type CXCircuit32 = CXCircuitN<NonZeroU32, 32>;
+/// A 64-qubit CX circuit.
+pub type CXCircuit64 = CXCircuitN<NonZeroU64, 64>;
+
+impl<R: Row, const N: usize> CXCircuitN<R, N> {
+    fn from_mat(matrix: [u64; N]) -> Self {
+        Self {
+            matrix: matrix.map(R::from_u64),
+        }
+    }
+
+    /// Row `i` of the matrix.
+    pub fn row(&self, i: usize) -> u64 {
+        self.matrix[i].to_u64()
+    }
+}
+
+impl<R: Row, const N: usize> CXCircuit for CXCircuitN<R, N> {
+    const N_QUBITS: usize = N;
+
+    fn new() -> Self {
+        const { assert!(N <= R::BITS, "too many qubits for the row type") };
+        Self::from_mat(std::array::from_fn(|i| 1 << i))
+    }
+
+    fn row_bits(&self, i: usize) -> u64 {
+        self.row(i)
+    }
+
+    fn from_row_bits(rows: &[u64]) -> Self {
+        assert_eq!(rows.len(), N, "expected {N} rows");
+        let matrix = std::array::from_fn(|i| rows[i]);
+        assert!(matrix.iter().all(|&row| row != 0), "rows must be non-zero");
+        Self::from_mat(matrix)
+    }
+
+    fn add_cx(&mut self, ctrl: usize, tgt: usize) {
+        let new_tgt_value = self.row(tgt) ^ self.row(ctrl);
+        assert_ne!(new_tgt_value, 0, "rows must be non-zero");
+        self.matrix[tgt] = R::from_u64(new_tgt_value);
+    }
+
+    fn mult_checked(&self, other: &Self) -> Option<Self> {
+        // Row i of the product is the sum of the rows of `other` selected by
+        // row i of `self`
+        let result: [u64; N] = std::array::from_fn(|i| {
+            (0..N)
+                .filter(|&k| self.row(i) & (1 << k) != 0)
+                .fold(0, |acc, k| acc ^ other.row(k))
+        });
+        result
+            .iter()
+            .all(|&row| row != 0)
+            .then(|| Self::from_mat(result))
+    }
+
+    fn mult_transpose(&self, other: &Self) -> Self {
+        Self::from_mat(std::array::from_fn(|i| {
+            (0..N)
+                .filter(|&j| (self.row(i) & other.row(j)).count_ones() % 2 == 1)
+                .fold(0, |row, j| row | (1 << j))
+        }))
+    }
+
+    fn transpose(&self) -> Self {
+        Self::from_mat(std::array::from_fn(|i| {
+            (0..N)
+                .filter(|&j| self.row(j) & (1 << i) != 0)
+                .fold(0, |row, j| row | (1 << j))
+        }))
+    }
+
+    fn apply_permutation(&self, perm: &[usize]) -> Self {
+        assert_permutation(perm, N);
+        let image = |i: usize| perm.get(i).copied().unwrap_or(i);
+        let mut result = [0; N];
+        for i in 0..N {
+            result[image(i)] = (0..N)
+                .filter(|&j| self.row(i) & (1 << j) != 0)
+                .fold(0, |acc, j| acc | (1 << image(j)));
+        }
+        Self::from_mat(result)
+    }
+
+    fn column_diff_mask(&self, other: &Self) -> u64 {
+        (0..N).fold(0, |mask, i| mask | (self.row(i) ^ other.row(i)))
+    }
+}
+
+impl<R: Row, const N: usize> AStarValue for CXCircuitN<R, N> {
+    fn dist(&self, other: &Self) -> usize {
+        self.matrix
+            .iter()
+            .zip(other.matrix.iter())
+            .filter(|(a, b)| a != b)
+            .count()
+    }
+
+    fn n_qubits(&self) -> usize {
+        N
+    }
+
+    fn is_complete(&self, qb: u8, target: &Self) -> bool {
+        self.matrix[qb as usize] == target.matrix[qb as usize]
+    }
+
+    fn qubit_dist(&self, qb: u8, target: &Self) -> usize {
+        (self.row(qb as usize) ^ target.row(qb as usize)).count_ones() as usize
+    }
+
+    fn cx(&self, ctrl: u8, tgt: u8) -> Self {
+        let mut cx = *self;
+        cx.add_cx(ctrl as usize, tgt as usize);
+        cx
+    }
+
+    fn merge(&self, other: &Self, used_qubits: &FxHashSet<u8>) -> Self {
+        let mut merge = *self;
+        for &qb in used_qubits {
+            merge.matrix[qb as usize] = other.matrix[qb as usize];
+        }
+        merge
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{a_star::a_star, bfs::mitm_bfs, cx_circuit::CXCircuit16, CX};
+
+    /// The same circuit on 16 qubits, padded with identity rows.
+    fn to_circuit16(circ: &CXCircuit8) -> CXCircuit16 {
+        CXCircuit16::from_sparse_repr((0..8).map(|i| (i, circ.row(i) as u16)))
+    }
+
+    #[test]
+    fn matches_circuit16() {
+        let cxs = [(0, 1), (2, 0), (7, 3), (1, 7)];
+        let other_cxs = [(3, 4), (4, 0), (6, 5)];
+        let (a, b) = (CXCircuit8::from_cxs(cxs), CXCircuit8::from_cxs(other_cxs));
+        let (a16, b16) = (CXCircuit16::from_cxs(cxs), CXCircuit16::from_cxs(other_cxs));
+        assert_eq!(to_circuit16(&a), a16);
+        assert_eq!(to_circuit16(&a.transpose()), a16.transpose());
+        assert_eq!(to_circuit16(&a.mult(&b)), a16.mult(&b16));
+        assert_eq!(
+            to_circuit16(&a.mult_transpose(&b)),
+            a16.mult_transpose(&b16)
+        );
+        assert_eq!(a.column_diff_mask(&b), a16.column_diff_mask(&b16));
+        let perm = [3, 0, 1, 2];
+        assert_eq!(
+            to_circuit16(&a.apply_permutation(&perm)),
+            a16.apply_permutation(&perm)
+        );
+    }
+
+    #[test]
+    fn circuit32() {
+        let cxs = [(0, 31), (31, 20), (20, 0)];
+        let circ = CXCircuit32::from_cxs(cxs);
+        assert_eq!(circ.row(31), (1 << 31) | 1);
+        assert_eq!(circ.row(20), (1 << 31) | (1 << 20) | 1);
+        assert_eq!(circ.row(0), (1 << 31) | (1 << 20));
+        assert_eq!(circ.transpose().transpose(), circ);
+        // CX gates are self-inverse
+        let inverse = CXCircuit32::from_cxs(cxs.into_iter().rev());
+        assert_eq!(circ.mult(&inverse), CXCircuit32::new());
+        assert_eq!(CXCircuit64::from_cxs([(63, 0)]).row(0), (1 << 63) | 1);
+    }
+
+    #[test]
+    fn a_star_circuit8() {
+        let moves: Vec<CX> = (0..7)
+            .flat_map(|i| [(i, i + 1), (i + 1, i)])
+            .map(CX::from)
+            .collect();
+        let target = CXCircuit8::from_cxs([(0, 1), (2, 3), (1, 2), (4, 3)]);
+        let solution = a_star(CXCircuit8::new(), &target, moves, None, None).unwrap();
+        assert_eq!(solution.len(), 4);
+        let result = solution
+            .iter()
+            .fold(CXCircuit8::new(), |circ, cx| circ.cx(cx.ctrl, cx.tgt));
+        assert_eq!(result, target);
+    }
+
+    #[test]
+    fn inverse_circuit64() {
+        let cxs = [(0, 63), (63, 40), (40, 0), (12, 63), (33, 12)];
+        let circ = CXCircuit64::from_cxs(cxs);
+        // CX gates are self-inverse
+        let inverse = CXCircuit64::from_cxs(cxs.into_iter().rev());
+        assert_eq!(circ.inverse(), inverse);
+        assert!(circ.mult(&circ.inverse()).is_identity());
+    }
+
+    #[test]
+    fn mitm_circuit64() {
+        let cxs: Vec<CX> = (0..63)
+            .flat_map(|i| [(i, i + 1), (i + 1, i)])
+            .map(CX::from)
+            .collect();
+        let moves: Vec<CXCircuit64> = cxs
+            .iter()
+            .map(|cx| CXCircuit64::from_cxs([(cx.ctrl as usize, cx.tgt as usize)]).transpose())
+            .collect();
+        let source = CXCircuit64::from_cxs([(40, 41)]);
+        let target = CXCircuit64::from_cxs([(40, 41), (62, 63), (0, 1), (63, 62)]);
+        let solution = mitm_bfs(source, target, &moves, 2, false, vec![], true).unwrap();
+        assert_eq!(solution.len(), 3);
+        let result = solution.iter().fold(source, |mut circ, &mv| {
+            circ.add_cx(cxs[mv].ctrl as usize, cxs[mv].tgt as usize);
+            circ
+        });
+        assert_eq!(result, target);
+    }
+
+    #[test]
+    fn mitm_circuit8() {
+        assert_eq!(
+            std::mem::size_of::<Option<CXCircuit8>>(),
+            std::mem::size_of::<CXCircuit8>()
+        );
+        let cxs: Vec<CX> = (0..7)
+            .flat_map(|i| [(i, i + 1), (i + 1, i)])
+            .map(CX::from)
+            .collect();
+        let moves: Vec<CXCircuit8> = cxs
+            .iter()
+            .map(|cx| CXCircuit8::from_cxs([(cx.ctrl as usize, cx.tgt as usize)]).transpose())
+            .collect();
+        let target = CXCircuit8::from_cxs([(0, 1), (2, 3), (1, 2), (4, 3), (6, 7)]);
+        let solution = mitm_bfs(CXCircuit8::new(), target, &moves, 3, false, vec![], true).unwrap();
+        assert_eq!(solution.len(), 5);
+        let result = solution.iter().fold(CXCircuit8::new(), |mut circ, &mv| {
+            circ.add_cx(cxs[mv].ctrl as usize, cxs[mv].tgt as usize);
+            circ
+        });
+        assert_eq!(result, target);
+    }
+}
@@ -0,0 +1,69 @@
+//! SIMD implementation of [`CXCircuit16::mult_transpose`].
+//!
+//! All 16 rows of the right matrix fit in a 256-bit AVX2 register, so each
+//! row of the result is computed with a handful of vector instructions.
+//!
+//! [`CXCircuit16::mult_transpose`]: super::CXCircuit::mult_transpose
+
+/// The matrix product of `a` with the transpose of `b`, given as rows.
+///
+/// Returns `None` if the CPU does not support AVX2.
+pub(super) fn mult_transpose(a: &[u16; 16], b: &[u16; 16]) -> Option<[u16; 16]> {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        // SAFETY: AVX2 is available
+        return Some(unsafe { mult_transpose_avx2(a, b) });
+    }
+    let _ = (a, b);
+    None
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn mult_transpose_avx2(a: &[u16; 16], b: &[u16; 16]) -> [u16; 16] {
+    use std::arch::x86_64::*;
+
+    let b_rows = _mm256_loadu_si256(b.as_ptr().cast());
+    let mut result = [0; 16];
+    for (i, &a_row) in a.iter().enumerate() {
+        // Entry j of row i is the parity of `a[i] & b[j]`, i.e. of lane j
+        let mut x = _mm256_and_si256(_mm256_set1_epi16(a_row as i16), b_rows);
+        x = _mm256_xor_si256(x, _mm256_srli_epi16::<8>(x));
+        x = _mm256_xor_si256(x, _mm256_srli_epi16::<4>(x));
+        x = _mm256_xor_si256(x, _mm256_srli_epi16::<2>(x));
+        x = _mm256_xor_si256(x, _mm256_srli_epi16::<1>(x));
+        // Move the parity of each lane to the top bit of its high byte
+        let mask = _mm256_movemask_epi8(_mm256_slli_epi16::<15>(x)) as u32;
+        result[i] = odd_bits(mask);
+    }
+    result
+}
+
+/// Bits 1, 3, ..., 31 of `mask`, packed into the 16 bits of a `u16`.
+#[cfg(target_arch = "x86_64")]
+fn odd_bits(mask: u32) -> u16 {
+    let mut x = (mask >> 1) & 0x5555_5555;
+    x = (x | (x >> 1)) & 0x3333_3333;
+    x = (x | (x >> 2)) & 0x0f0f_0f0f;
+    x = (x | (x >> 4)) & 0x00ff_00ff;
+    x = (x | (x >> 8)) & 0x0000_ffff;
+    x as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::cx_circuit::{CXCircuit, CXCircuit16};
+
+    #[test]
+    fn matches_scalar() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..100 {
+            let a = CXCircuit16::random_invertible(&mut rng);
+            let b = CXCircuit16::random_invertible(&mut rng);
+            assert_eq!(a.mult_transpose_simd(&b), a.mult_transpose_scalar(&b));
+            assert_eq!(a.mult(&b), a.mult_transpose_scalar(&b.transpose()));
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Lists of CX gates, and their simplification.
+
+use itertools::iproduct;
+
+use crate::{cx_circuit::CXCircuit, upper_bound::eliminate, CXCircuit16, CX};
+
+/// The default number of gates that [`CXList::simplify`] may commute a CX
+/// past.
+pub const DEFAULT_WINDOW: usize = 8;
+
+/// A list of CX gates, in the order in which they are applied.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CXList(pub Vec<CX>);
+
+impl CXList {
+    /// The circuit obtained by applying the gates in order.
+    pub fn to_cx_circuit(&self) -> CXCircuit16 {
+        CXCircuit16::from_cxs(self.0.iter().map(|cx| (cx.ctrl as usize, cx.tgt as usize)))
+    }
+
+    /// A list of gates implementing `circuit`, found by Gaussian elimination.
+    ///
+    /// Many lists implement the same circuit: this one is in general not the
+    /// shortest.
+    pub fn from_cx_circuit(circuit: &CXCircuit16) -> Self {
+        let all_to_all = iproduct!(0..16, 0..16)
+            .filter(|(a, b)| a != b)
+            .map(CX::from)
+            .collect();
+        let elimination = eliminate(circuit, &all_to_all).expect("circuits are invertible");
+        // CX gates are self-inverse, so the circuit is obtained by undoing the
+        // reduction to the identity
+        Self(elimination.into_iter().rev().collect())
+    }
+
+    /// Remove gates using [`CXList::simplify_within`] with the default window
+    /// of [`DEFAULT_WINDOW`] gates.
+    pub fn simplify(&self) -> Self {
+        self.simplify_within(DEFAULT_WINDOW)
+    }
+
+    /// Remove gates without changing the circuit.
+    ///
+    /// Until no rule applies, a CX is commuted past up to `window` gates to
+    ///  - cancel it with an identical CX, or otherwise
+    ///  - replace it and an identical CX on both sides of a CX it does not
+    ///    commute with by two CXs, using
+    ///    `CX(a,b);CX(b,c);CX(a,b) = CX(b,c);CX(a,c)` and
+    ///    `CX(b,c);CX(a,b);CX(b,c) = CX(a,b);CX(a,c)`.
+    ///
+    /// The result is not necessarily the shortest list for the circuit.
+    pub fn simplify_within(&self, window: usize) -> Self {
+        let mut gates = self.0.clone();
+        while let Some(simplified) = simplify_once(&gates, window) {
+            gates = simplified;
+        }
+        Self(gates)
+    }
+}
+
+impl From<Vec<CX>> for CXList {
+    fn from(gates: Vec<CX>) -> Self {
+        Self(gates)
+    }
+}
+
+/// Apply the first simplification found in `gates`, if any.
+///
+/// Cancellations are preferred, as they remove two gates.
+fn simplify_once(gates: &[CX], window: usize) -> Option<Vec<CX>> {
+    // The first gate after position `i` that `cx` cannot be commuted past,
+    // within the window
+    let next_blocking = |i: usize, cx: CX| {
+        (i + 1..gates.len())
+            .take(window + 1)
+            .find(|&j| gates[j] == cx || !gates[j].commutes_with(cx))
+    };
+
+    for (i, &x) in gates.iter().enumerate() {
+        if let Some(j) = next_blocking(i, x).filter(|&j| gates[j] == x) {
+            return Some(replace(gates, [i, j], j, &[]));
+        }
+    }
+    for (i, &x) in gates.iter().enumerate() {
+        let Some(j) = next_blocking(i, x) else {
+            continue;
+        };
+        let Some(replacement) = merge_triple(x, gates[j]) else {
+            continue;
+        };
+        if let Some(k) = next_blocking(j, x).filter(|&k| gates[k] == x) {
+            return Some(replace(gates, [i, k], j, &replacement));
+        }
+    }
+    None
+}
+
+/// Two CXs equal to `x;y;x`, if there are any.
+fn merge_triple(x: CX, y: CX) -> Option<[CX; 2]> {
+    if x.tgt == y.ctrl && y.tgt != x.ctrl {
+        // CX(a,b);CX(b,c);CX(a,b) = CX(b,c);CX(a,c)
+        Some([y, CX::from((x.ctrl as usize, y.tgt as usize))])
+    } else if y.tgt == x.ctrl && x.tgt != y.ctrl {
+        // CX(b,c);CX(a,b);CX(b,c) = CX(a,b);CX(a,c)
+        Some([y, CX::from((y.ctrl as usize, x.tgt as usize))])
+    } else {
+        None
+    }
+}
+
+/// `gates` without the gates at `removed`, and with the gate at `at` replaced
+/// by `replacement`.
+fn replace(gates: &[CX], removed: [usize; 2], at: usize, replacement: &[CX]) -> Vec<CX> {
+    let mut result = Vec::with_capacity(gates.len());
+    for (i, &cx) in gates.iter().enumerate() {
+        if i == at {
+            result.extend_from_slice(replacement);
+        } else if !removed.contains(&i) {
+            result.push(cx);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, RngExt, SeedableRng};
+
+    use super::*;
+
+    fn cx_list(cxs: impl IntoIterator<Item = (usize, usize)>) -> CXList {
+        CXList(cxs.into_iter().map(CX::from).collect())
+    }
+
+    #[test]
+    fn cancel_through_commuting_gates() {
+        assert_eq!(cx_list([(0, 1), (0, 1)]).simplify(), CXList::default());
+        // CX(2, 3) is disjoint and CX(0, 4) shares the control
+        let list = cx_list([(0, 1), (2, 3), (0, 4), (0, 1), (1, 2)]);
+        assert_eq!(list.simplify(), cx_list([(2, 3), (0, 4), (1, 2)]));
+        assert_eq!(list.simplify_within(1), list);
+        // CX(1, 2) does not commute with CX(0, 1)
+        let list = cx_list([(0, 1), (1, 2), (1, 0), (0, 1)]);
+        assert_eq!(list.simplify(), list);
+    }
+
+    #[test]
+    fn merge_triples() {
+        for cxs in [[(0, 1), (1, 2), (0, 1)], [(1, 2), (0, 1), (1, 2)]] {
+            let list = cx_list(cxs);
+            let simplified = list.simplify();
+            assert_eq!(simplified.0.len(), 2);
+            assert_eq!(simplified.to_cx_circuit(), list.to_cx_circuit());
+        }
+        // A SWAP needs three CXs
+        let swap = cx_list([(0, 1), (1, 0), (0, 1)]);
+        assert_eq!(swap.simplify(), swap);
+        // The triple is merged, after which the CX(1, 2)s cancel
+        let list = cx_list([(0, 1), (1, 2), (0, 1), (1, 2)]);
+        assert_eq!(list.simplify(), cx_list([(0, 2)]));
+    }
+
+    #[test]
+    fn from_cx_circuit() {
+        let circuit = CXCircuit16::from_cxs([(0, 3), (4, 2), (3, 4), (15, 0)]);
+        assert_eq!(CXList::from_cx_circuit(&circuit).to_cx_circuit(), circuit);
+        assert_eq!(
+            CXList::from_cx_circuit(&CXCircuit16::new()),
+            CXList::default()
+        );
+    }
+
+    #[test]
+    fn simplify_padded_circuit() {
+        // Each CX adds a new row to a different target, so at least 5 CXs
+        // are required
+        let minimal = cx_list([(0, 1), (1, 2), (2, 3), (3, 4), (4, 5)]);
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..20 {
+            // Pad the circuit with pairs of identical CXs
+            let mut padded = CXList::default();
+            for &cx in &minimal.0 {
+                for _ in 0..rng.random_range(0..3) {
+                    let ctrl = rng.random_range(0..6);
+                    let pad = CX::from((ctrl, (ctrl + rng.random_range(1..6)) % 6));
+                    padded.0.extend([pad, pad]);
+                }
+                padded.0.push(cx);
+            }
+            assert_eq!(padded.to_cx_circuit(), minimal.to_cx_circuit());
+
+            let simplified = padded.simplify();
+            assert_eq!(simplified, minimal);
+        }
+    }
+}
@@ -3,58 +3,203 @@ use std::{
     io::{self, BufRead, Write},
 };
 
+use thiserror::Error;
+
 use crate::{
-    cx_circuit::{CXCircuit, CXCircuit16},
+    cx_circuit::{CXCircuit, CXCircuit16, ParseCircuitError},
     stab_state::StabiliserState,
     Moves, CX,
 };
 
-fn parse_file(file: &File) -> io::Result<Vec<(usize, usize)>> {
+/// The number of qubits supported by the parsers.
+const N_QUBITS: usize = 16;
+
+/// An error when parsing an input file.
+#[derive(Debug, Error)]
+pub enum ParseError {
+    /// The line with the given number (counting from 1) is malformed
+    #[error("invalid line {0}: \"{1}\"")]
+    InvalidLine(usize, String),
+    /// A qubit index is not smaller than the number of qubits
+    #[error("qubit index {0} is out of range, only qubits 0 to {max} are supported", max = .1 - 1)]
+    QubitIndexOutOfRange(usize, usize),
+    /// A binary matrix is not a valid circuit
+    #[error("invalid matrix: {0}")]
+    InvalidMatrix(#[from] ParseCircuitError),
+    /// A solution file is not valid JSON, or misses a field
+    #[error("invalid JSON: {0}")]
+    #[cfg(feature = "json")]
+    InvalidJson(serde_json::Error),
+    /// The input could not be read
+    #[error("unable to read input: {0}")]
+    IoError(#[from] io::Error),
+}
+
+/// Check that all qubits of `(a, b)` are in range.
+fn check_qubits((a, b): (usize, usize)) -> Result<(), ParseError> {
+    match [a, b].into_iter().find(|&qb| qb >= N_QUBITS) {
+        Some(qb) => Err(ParseError::QubitIndexOutOfRange(qb, N_QUBITS)),
+        None => Ok(()),
+    }
+}
+
+/// Parse `ctrl tgt` pairs of distinct qubits, one per line.
+fn parse_pairs(reader: impl BufRead) -> Result<Vec<(usize, usize)>, ParseError> {
     let mut res = vec![];
-    let reader = io::BufReader::new(file);
-
-    for line in reader.lines() {
-        let parts: Vec<usize> = line?
-            .split_whitespace()
-            .map(|s| s.parse().expect("Parse error"))
-            .collect();
-        if parts.len() == 2 {
-            res.push((parts[0], parts[1]));
-        } else {
-            panic!("Each line must contain exactly two numbers");
-        }
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let parts: Option<Vec<usize>> = line.split_whitespace().map(|s| s.parse().ok()).collect();
+        let (a, b) = match parts.as_deref() {
+            Some(&[a, b]) if a != b => (a, b),
+            _ => return Err(ParseError::InvalidLine(i + 1, line)),
+        };
+        check_qubits((a, b))?;
+        res.push((a, b));
     }
     Ok(res)
 }
 
-/// Parse a circuit from a file.
-pub fn parse_cx_circuit(file: &File) -> io::Result<CXCircuit16> {
+/// Parse a circuit from a list of `ctrl tgt` pairs, one CX per line.
+pub fn parse_cx_circuit(reader: impl BufRead) -> Result<CXCircuit16, ParseError> {
     let mut circuit = CXCircuit16::new();
-    let all_cxs = parse_file(file)?;
+    let all_cxs = parse_pairs(reader)?;
     for (a, b) in &all_cxs {
         circuit.add_cx(*a, *b);
     }
     Ok(circuit)
 }
 
-pub fn parse_stabiliser(file: &File) -> io::Result<StabiliserState<16>> {
-    let reader = io::BufReader::new(file);
+/// Parse a stabiliser state from its X stabilisers, as a string of `X`s and
+/// `I`s per line.
+///
+/// Line `i` is the row of qubit `i`, see [`StabiliserState::from_strs`].
+pub fn parse_stabiliser(reader: impl BufRead) -> Result<StabiliserState<16>, ParseError> {
     let lines = reader.lines().collect::<io::Result<Vec<_>>>()?;
+    if lines.len() > N_QUBITS {
+        // Only one row per qubit
+        let line = lines[N_QUBITS].clone();
+        return Err(ParseError::InvalidLine(N_QUBITS + 1, line));
+    }
+    for (i, line) in lines.iter().enumerate() {
+        if line.chars().any(|c| c != 'X' && c != 'I') {
+            return Err(ParseError::InvalidLine(i + 1, line.clone()));
+        }
+        if line.len() > N_QUBITS {
+            return Err(ParseError::QubitIndexOutOfRange(line.len() - 1, N_QUBITS));
+        }
+    }
     Ok(StabiliserState::from_strs(lines.iter().map(|s| s.as_str())))
-    // let all_cxs = parse_file(file)?;
-    // for (a, b) in &all_cxs {
-    //     stabiliser.add_cx(*a, *b);
-    // }
-    // Ok(stabiliser)
 }
 
-/// Parse a list of moves from a file.
+/// Parse a list of moves from `ctrl tgt` pairs, one per line.
+///
+/// Careful: moves are always as stored as the transpose!
+pub fn parse_moves(reader: impl BufRead) -> Result<PairsAndMoves, ParseError> {
+    Ok(moves_from_pairs(parse_pairs(reader)?))
+}
+
+/// Parse a list of weighted moves from `ctrl tgt cost` lines.
+///
+/// As in [`parse_moves`], each line allows the CX gates in both directions,
+/// both with the given cost. The cost may be omitted, in which case it is 1,
+/// so that any file accepted by [`parse_moves`] is also accepted here.
+pub fn parse_weighted_moves(reader: impl BufRead) -> Result<Vec<(CX, f64)>, ParseError> {
+    let mut res = vec![];
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let parsed = match *parts.as_slice() {
+            [a, b] => Some((a, b, "1")),
+            [a, b, cost] => Some((a, b, cost)),
+            _ => None,
+        }
+        .and_then(|(a, b, cost)| {
+            let (a, b) = (a.parse::<usize>().ok()?, b.parse::<usize>().ok()?);
+            let cost = cost
+                .parse::<f64>()
+                .ok()
+                .filter(|&c| c.is_finite() && c > 0.)?;
+            (a != b).then_some((a, b, cost))
+        });
+        let Some((a, b, cost)) = parsed else {
+            return Err(ParseError::InvalidLine(i + 1, line));
+        };
+        check_qubits((a, b))?;
+        res.push((CX::from((a, b)), cost));
+        res.push((CX::from((b, a)), cost));
+    }
+    Ok(res)
+}
+
+/// Parse a circuit from the CX gates in an OpenQASM 2.0 file.
+///
+/// Header lines, comments and all other gates are ignored.
+pub fn parse_qasm_circuit(reader: impl BufRead) -> Result<CXCircuit16, ParseError> {
+    Ok(CXCircuit16::from_cxs(parse_qasm_cxs(reader)?))
+}
+
+/// Parse a circuit from its binary matrix.
+///
+/// Each of the 16 lines is a row of the matrix, written as 16 `0`s and `1`s,
+/// see [`CXCircuit16`]'s `FromStr` implementation.
+pub fn parse_binary_matrix(reader: impl BufRead) -> Result<CXCircuit16, ParseError> {
+    Ok(io::read_to_string(reader)?.parse::<CXCircuit16>()?)
+}
+
+/// Extract the qubit interactions of the CX gates in an OpenQASM file.
+///
+/// Every interacting pair is listed once, in order of first appearance.
+pub fn parse_qasm_interactions(reader: impl BufRead) -> Result<Vec<(usize, usize)>, ParseError> {
+    let mut pairs = Vec::new();
+    for (a, b) in parse_qasm_cxs(reader)? {
+        if !pairs.contains(&(a, b)) && !pairs.contains(&(b, a)) {
+            pairs.push((a, b));
+        }
+    }
+    Ok(pairs)
+}
+
+/// The CX gates in an OpenQASM file, as `(ctrl, tgt)` pairs.
+fn parse_qasm_cxs(reader: impl BufRead) -> Result<Vec<(usize, usize)>, ParseError> {
+    let mut cxs = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let code = line.split("//").next().expect("split is never empty");
+        for statement in code.split(';').map(str::trim) {
+            if !(statement.starts_with("cx ") || statement.starts_with("CX ")) {
+                continue;
+            }
+            let qubits = statement
+                .split('[')
+                .skip(1)
+                .map(|s| {
+                    let index = s.split(']').next().expect("split is never empty");
+                    index.trim().parse().ok()
+                })
+                .collect::<Option<Vec<usize>>>();
+            let (a, b) = match qubits.as_deref() {
+                Some(&[a, b]) if a != b => (a, b),
+                _ => return Err(ParseError::InvalidLine(i + 1, line.trim().to_string())),
+            };
+            check_qubits((a, b))?;
+            cxs.push((a, b));
+        }
+    }
+    Ok(cxs)
+}
+
+/// The qubit pairs of the allowed CXs, along with their moves.
+pub type PairsAndMoves = (Vec<(usize, usize)>, Moves<CXCircuit16>);
+
+/// Construct the moves for CX gates in both directions on each qubit pair.
 ///
 /// Careful: moves are always as stored as the transpose!
-pub fn parse_moves(file: &File) -> io::Result<(Vec<(usize, usize)>, Moves<CXCircuit16>)> {
+pub fn moves_from_pairs(pairs: impl IntoIterator<Item = (usize, usize)>) -> PairsAndMoves {
     let mut moves = Vec::new();
     let mut moves_inds = Vec::new();
-    for (a, b) in parse_file(file)? {
+    for (a, b) in pairs {
         if a >= 16 || b >= 16 {
             panic!("We currently only support qubits indices up to 15");
         }
@@ -65,7 +210,7 @@ pub fn parse_moves(file: &File) -> io::Result<(Vec<(usize, usize)>, Moves<CXCirc
         moves.push(cx_circ.transpose());
         moves_inds.push((b, a));
     }
-    Ok((moves_inds, moves))
+    (moves_inds, moves)
 }
 
 pub fn save_solution(file: &mut File, solution: &[CX]) -> io::Result<()> {
@@ -74,3 +219,216 @@ pub fn save_solution(file: &mut File, solution: &[CX]) -> io::Result<()> {
     }
     Ok(())
 }
+
+/// Save a solution as an OpenQASM 2.0 circuit on `n_qubits` qubits.
+pub fn save_solution_qasm(
+    writer: &mut impl Write,
+    solution: &[CX],
+    n_qubits: usize,
+) -> io::Result<()> {
+    writeln!(writer, "OPENQASM 2.0;")?;
+    writeln!(writer, "include \"qelib1.inc\";")?;
+    writeln!(writer, "qreg q[{n_qubits}];")?;
+    for &CX { ctrl, tgt } in solution {
+        writeln!(writer, "cx q[{ctrl}], q[{tgt}];")?;
+    }
+    Ok(())
+}
+
+/// Information about how a solution was found, saved along with it.
+#[cfg(feature = "json")]
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct SolutionMetadata {
+    /// The search algorithm, as passed to `--algo`
+    pub algorithm: String,
+    /// The duration of the search, in milliseconds
+    pub elapsed_ms: u64,
+    /// The number of CX gates in the solution
+    pub gate_count: usize,
+    /// The source file, if the search did not start from the identity
+    pub source_file: Option<String>,
+}
+
+/// A solution in JSON format, see [`save_solution_json`].
+#[cfg(feature = "json")]
+#[derive(serde::Deserialize, serde::Serialize)]
+struct SolutionJson {
+    gates: Vec<(u8, u8)>,
+    metadata: SolutionMetadata,
+}
+
+/// Save a solution as JSON, along with `metadata`.
+///
+/// The schema is
+/// ```json
+/// {
+///   "gates": [[ctrl, tgt], ...],
+///   "metadata": {
+///     "algorithm": "astar",
+///     "elapsed_ms": 12,
+///     "gate_count": 2,
+///     "source_file": null
+///   }
+/// }
+/// ```
+/// where gates are listed in the order in which they are applied. Fields may
+/// be added in the future, but existing fields will not be renamed.
+#[cfg(feature = "json")]
+pub fn save_solution_json(
+    writer: &mut impl Write,
+    solution: &[CX],
+    metadata: &SolutionMetadata,
+) -> io::Result<()> {
+    let json = SolutionJson {
+        gates: solution.iter().map(|cx| (cx.ctrl, cx.tgt)).collect(),
+        metadata: metadata.clone(),
+    };
+    serde_json::to_writer_pretty(&mut *writer, &json).map_err(io::Error::other)?;
+    writeln!(writer)
+}
+
+/// Parse a solution saved by [`save_solution_json`].
+#[cfg(feature = "json")]
+pub fn parse_solution_json(
+    reader: impl io::Read,
+) -> Result<(Vec<CX>, SolutionMetadata), ParseError> {
+    let json: SolutionJson = serde_json::from_reader(reader).map_err(|err| {
+        if err.is_io() {
+            ParseError::IoError(err.into())
+        } else {
+            ParseError::InvalidJson(err)
+        }
+    })?;
+    let solution = json
+        .gates
+        .into_iter()
+        .map(|(ctrl, tgt)| CX { ctrl, tgt })
+        .collect();
+    Ok((solution, json.metadata))
+}
+
+/// Save several solutions to the same file, separated by blank lines.
+pub fn save_solutions(file: &mut File, solutions: &[Vec<CX>]) -> io::Result<()> {
+    for (i, solution) in solutions.iter().enumerate() {
+        if i > 0 {
+            writeln!(file)?;
+        }
+        save_solution(file, solution)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn qasm_round_trip() {
+        let cxs = [(0, 1), (3, 2), (15, 0), (1, 3)];
+        let solution = cxs.map(CX::from);
+        let mut qasm = Vec::new();
+        save_solution_qasm(&mut qasm, &solution, 16).unwrap();
+        let parsed = parse_qasm_circuit(qasm.as_slice()).unwrap();
+        assert_eq!(parsed, CXCircuit16::from_cxs(cxs));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_round_trip() {
+        let solution = [(0, 1), (3, 2), (15, 0)].map(CX::from);
+        let metadata = SolutionMetadata {
+            algorithm: "astar".to_string(),
+            elapsed_ms: 42,
+            gate_count: solution.len(),
+            source_file: Some("source.txt".to_string()),
+        };
+        let mut json = Vec::new();
+        save_solution_json(&mut json, &solution, &metadata).unwrap();
+        let (parsed, parsed_metadata) = parse_solution_json(json.as_slice()).unwrap();
+        assert_eq!(parsed, solution);
+        assert_eq!(parsed_metadata, metadata);
+        let err = parse_solution_json("{\"gates\": []}".as_bytes()).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidJson(_)));
+        let err = parse_solution_json("{\"gates\": [".as_bytes()).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidJson(_)));
+    }
+
+    #[test]
+    fn parse_qasm() {
+        let qasm = "OPENQASM 2.0;
+include \"qelib1.inc\";
+qreg q[16];
+// cx q[5], q[6];
+h q[0];
+cx q[0], q[1]; CX q[2],q[3];
+cx q[1], q[2]; // the last CX
+";
+        let circuit = parse_qasm_circuit(qasm.as_bytes()).unwrap();
+        assert_eq!(circuit, CXCircuit16::from_cxs([(0, 1), (2, 3), (1, 2)]));
+
+        let err = parse_qasm_circuit("cx q[3], q[16];".as_bytes()).unwrap_err();
+        assert!(matches!(err, ParseError::QubitIndexOutOfRange(16, 16)));
+        assert!(err.to_string().contains("16"));
+        let err = parse_qasm_circuit("h q[0];\ncx q[0];".as_bytes()).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidLine(2, _)));
+        assert!(parse_qasm_circuit("cx q[a], q[1];".as_bytes()).is_err());
+        let err = parse_qasm_circuit("cx q[0], q[1];\ncx q[1], q[1];".as_bytes()).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidLine(2, _)));
+    }
+
+    #[test]
+    fn parse_from_memory() {
+        let circuit = parse_cx_circuit(Cursor::new(b"0 1\n2 3\n1 2\n".as_slice())).unwrap();
+        assert_eq!(circuit, CXCircuit16::from_cxs([(0, 1), (2, 3), (1, 2)]));
+
+        let (move_inds, moves) = parse_moves(Cursor::new(b"0 1\n1 2\n".as_slice())).unwrap();
+        assert_eq!(move_inds, [(0, 1), (1, 0), (1, 2), (2, 1)]);
+        assert_eq!(moves.len(), 4);
+
+        let stabs = ["XI", "XX"];
+        let state = parse_stabiliser(Cursor::new(stabs.join("\n").as_bytes())).unwrap();
+        assert_eq!(state, StabiliserState::from_strs(stabs));
+
+        let matrix = CXCircuit16::from_cxs([(3, 4)]).to_string();
+        let circuit = parse_binary_matrix(Cursor::new(matrix.as_bytes())).unwrap();
+        assert_eq!(circuit, CXCircuit16::from_cxs([(3, 4)]));
+
+        let qasm = "cx q[0], q[1];\ncx q[1], q[0];\ncx q[2], q[1];\n";
+        let pairs = parse_qasm_interactions(Cursor::new(qasm.as_bytes())).unwrap();
+        assert_eq!(pairs, [(0, 1), (2, 1)]);
+    }
+
+    #[test]
+    fn parse_weighted() {
+        let moves = parse_weighted_moves("0 1 2.5\n1 2\n".as_bytes()).unwrap();
+        assert_eq!(
+            moves,
+            [((0, 1), 2.5), ((1, 0), 2.5), ((1, 2), 1.), ((2, 1), 1.)]
+                .map(|(cx, c)| (CX::from(cx), c))
+        );
+        let err = parse_weighted_moves("0 1 -1\n".as_bytes()).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidLine(1, _)));
+        let err = parse_weighted_moves("0 1\n1 2 cheap\n".as_bytes()).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidLine(2, _)));
+    }
+
+    #[test]
+    fn malformed_circuit() {
+        let err = parse_cx_circuit("0 1\n2 x\n".as_bytes()).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidLine(2, ref line) if line == "2 x"));
+        let err = parse_cx_circuit("0 1 2\n".as_bytes()).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidLine(1, _)));
+        let err = parse_cx_circuit("3 3\n".as_bytes()).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidLine(1, _)));
+        let err = parse_moves("0 1\n1 16\n".as_bytes()).unwrap_err();
+        assert!(matches!(err, ParseError::QubitIndexOutOfRange(16, 16)));
+        let err = parse_stabiliser("XI\nXZ\n".as_bytes()).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidLine(2, _)));
+        let err = parse_stabiliser("X\n".repeat(17).as_bytes()).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidLine(17, _)));
+        let err = parse_binary_matrix("0101".as_bytes()).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidMatrix(_)));
+    }
+}
@@ -0,0 +1,42 @@
+//! Find optimal CX circuits, fast.
+//!
+//! Synthesises circuits of CX gates that map a source CX circuit or
+//! stabiliser state to a target, using only the CX gates allowed by a
+//! device topology. The main entry points are [`a_star()`] and [`mitm_bfs`].
+
+use fxhash::FxHashMap;
+
+pub mod a_star;
+#[cfg(feature = "json")]
+pub mod benchmark;
+pub mod bfs;
+pub mod cx;
+pub mod cx_circuit;
+pub mod cx_list;
+pub mod file_io;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod stab_state;
+pub mod synthesis;
+pub mod topology;
+pub mod upper_bound;
+
+pub use a_star::{a_star, AStarBuilder, AStarValue};
+pub use bfs::{mitm_bfs, mitm_bfs_all};
+pub use cx::CX;
+pub use cx_circuit::{CXCircuit, CXCircuit16};
+pub use cx_list::CXList;
+pub use file_io::{
+    parse_binary_matrix, parse_cx_circuit, parse_moves, parse_qasm_circuit, parse_stabiliser,
+    parse_weighted_moves, save_solution, save_solution_qasm, save_solutions, ParseError,
+};
+#[cfg(feature = "json")]
+pub use file_io::{parse_solution_json, save_solution_json, SolutionMetadata};
+pub use stab_state::StabiliserState;
+pub use synthesis::pmh_synthesis;
+pub use topology::Topology;
+
+/// The circuits reached by a BFS, mapped to the index of the last move.
+pub type CircMoves<T> = FxHashMap<T, usize>;
+/// The allowed moves of a BFS, as circuits.
+pub type Moves<T> = Vec<T>;
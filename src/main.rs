@@ -1,29 +1,28 @@
-use a_star::AStarValue;
-use bfs::mitm_bfs;
-use cx_circuit::{CXCircuit, CXCircuit16};
-use file_io::{parse_cx_circuit, parse_moves};
-
-use clap::Parser;
-use fxhash::FxHashMap;
+use clap::{
+    builder::{PossibleValuesParser, TypedValueParser},
+    Parser, ValueEnum,
+};
 use itertools::Itertools;
-use stab_state::StabiliserState;
-use std::fs::File;
-
-use crate::{
-    a_star::a_star,
-    cx::CX,
-    file_io::{parse_stabiliser, save_solution},
+use rayon::prelude::*;
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
 };
 
-mod a_star;
-mod bfs;
-mod cx;
-mod cx_circuit;
-mod file_io;
-mod stab_state;
-
-type CircMoves<T> = FxHashMap<T, usize>;
-type Moves<T> = Vec<T>;
+use fast_cx_circs::{
+    a_star::{ida_star, parallel::parallel_a_star, AStarBuilder, FocusMode, SearchStats},
+    benchmark::{git_commit_hash, BenchmarkReport, PhaseTimings},
+    bfs::{mitm_bfs_checkpointed, topology_automorphisms},
+    cx_circuit::generic::CXCircuit8,
+    file_io::{moves_from_pairs, parse_qasm_interactions},
+    mitm_bfs, mitm_bfs_all, parse_binary_matrix, parse_cx_circuit, parse_qasm_circuit,
+    parse_stabiliser, parse_weighted_moves, save_solution_json, save_solution_qasm, save_solutions,
+    topology::builtin_topology,
+    AStarValue, CXCircuit, CXCircuit16, Moves, ParseError, SolutionMetadata, StabiliserState, CX,
+};
 
 /// Search algorithm to use
 #[derive(clap::ValueEnum, Clone, Default, Debug, PartialEq, Eq)]
@@ -44,6 +43,51 @@ enum SearchAlgorithm {
     ///
     /// Should be leaner, but no parallelism yet.
     AstarStabiliser,
+    /// Iterative deepening A* search
+    ///
+    /// Uses memory linear in the depth, but revisits nodes.
+    Ida,
+}
+
+/// Format of the moves
+#[derive(clap::ValueEnum, Clone, Copy, Default, Debug, PartialEq, Eq)]
+enum MovesFormat {
+    /// A file with one `ctrl tgt` pair per line, optionally followed by the
+    /// cost of the CXs on that pair (1 by default). Costs other than 1 are
+    /// only supported by single-threaded A*
+    #[default]
+    Custom,
+    /// The name of a built-in topology: line, ring, grid or all-to-all,
+    /// optionally followed by its size, e.g. `line:8` or `grid:3x5`
+    Topology,
+    /// An OpenQASM file, from which the CX interactions are extracted
+    Qasm,
+}
+
+/// Format of the source and target files
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum InputFormat {
+    /// A CX circuit, with one `ctrl tgt` pair per line
+    Circuit,
+    /// An X-stabiliser state, with one Pauli string of `X`s and `I`s per line
+    Stabiliser,
+    /// An OpenQASM file, from which the CX gates are extracted
+    Qasm,
+    /// The binary matrix of a CX circuit, with one row of `0`s and `1`s per
+    /// line
+    Binary,
+}
+
+/// Format of the output file
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum OutputFormat {
+    /// One `ctrl tgt` pair per line
+    #[default]
+    Pairs,
+    /// An OpenQASM 2.0 circuit
+    Qasm,
+    /// A JSON object with the gates and information about the search
+    Json,
 }
 
 #[derive(Parser, Debug)]
@@ -53,125 +97,645 @@ struct Args {
     #[arg(short, long, default_value_t = String::from("in"))]
     target: String,
 
-    /// Name of source circuit or state. For circuits, defaults to identity.
+    /// Name of source circuit or state. Defaults to the identity circuit.
     #[arg(short, long)]
     source: Option<String>,
 
-    /// Name of moves file
+    /// Format of the target file. Defaults to `qasm` for files with a `.qasm`
+    /// extension, to `stabiliser` for `--algo astar-stabiliser` and to
+    /// `circuit` otherwise.
+    #[arg(long, value_enum)]
+    target_format: Option<InputFormat>,
+
+    /// Format of the source file. Defaults as for `--target-format`.
+    #[arg(long, value_enum)]
+    source_format: Option<InputFormat>,
+
+    /// Name of moves file, or of the topology for `--moves-format topology`.
+    /// Topologies with a size, such as `linear:8` or `grid:3x5`, are
+    /// recognised without `--moves-format`.
     #[arg(short, long, default_value_t = String::from("all_to_all"))]
     moves: String,
 
+    /// Format of the moves
+    #[arg(long, value_enum, default_value_t)]
+    moves_format: MovesFormat,
+
     /// Name of output file
     #[arg(short, long, default_value_t = String::from("out"))]
     output: String,
 
+    /// Directory of target files to solve in parallel, instead of `--target`.
+    /// Exits with an error if any target is not solved.
+    #[arg(long, requires = "output_dir", conflicts_with_all = ["target", "dry_run", "benchmark"])]
+    batch_dir: Option<String>,
+
+    /// Directory in which to write the solutions of `--batch-dir`, one file
+    /// per target with the same name
+    #[arg(long)]
+    output_dir: Option<String>,
+
+    /// Format of the output file
+    #[arg(long, value_enum, default_value_t)]
+    format: OutputFormat,
+
     /// Maximum depth of BFS. The maximum gate count will be 3*depth.
     /// Warning: I do not recommend setting this value higher than 5, memory
     /// consumption goes through the roof.
     #[arg(short, long, default_value_t = 5)]
     depth: usize,
 
+    /// Number of qubits of the circuits searched on by A* and IDA*. With 8,
+    /// smaller circuits are used, which speeds up the search of problems
+    /// that act on the first 8 qubits only.
+    #[arg(
+        long,
+        default_value_t = 16,
+        value_parser = PossibleValuesParser::new(["8", "16"]).map(|n| n.parse::<usize>().unwrap()),
+    )]
+    qubit_count: usize,
+
+    /// Maximum number of nodes to expand in A* search.
+    #[arg(long)]
+    max_expansions: Option<usize>,
+
+    /// Weight of the heuristic in the A* search. Values above 1 find solutions
+    /// faster, but up to `epsilon` times longer than optimal.
+    #[arg(long, default_value_t = 1.)]
+    epsilon: f64,
+
+    /// Time limit of the A* search, in seconds. When it is reached, the best
+    /// solution found so far is written.
+    #[arg(long)]
+    timeout: Option<f64>,
+
+    /// Number of threads for the A* search. With more than one thread, merge
+    /// moves are not used. Stabiliser searches always use a single thread.
+    #[arg(long, default_value_t = 1)]
+    threads: usize,
+
+    /// Also use SWAP gates as single moves of the A* search, on pairs of
+    /// qubits connected in both directions (single-threaded A* only).
+    /// Solutions still list them as three CXs.
+    #[arg(long)]
+    allow_swaps: bool,
+
+    /// Cost of a SWAP move with `--allow-swaps`. The default of 3 keeps
+    /// counting CXs.
+    #[arg(long, default_value_t = 3., requires = "allow_swaps")]
+    swap_cost: f64,
+
+    /// Only add CXs on the qubit furthest from the target when expanding a
+    /// node of the A* search (single-threaded A* only). This reduces the
+    /// branching factor, but solutions may not be optimal.
+    #[arg(long)]
+    most_constrained: bool,
+
+    /// Run the MITM search on a single thread, e.g. to compare its throughput
+    /// with the default parallel search.
+    #[arg(long)]
+    sequential: bool,
+
+    /// Directory in which to save the layers of the MITM search as they are
+    /// found. An interrupted search with the same arguments resumes from
+    /// them.
+    #[arg(long, conflicts_with = "sequential")]
+    checkpoint_dir: Option<String>,
+
+    /// Find all solutions of minimal length with A*, or one solution per
+    /// meeting point of the search with MITM. Solutions are written to the
+    /// output file separated by blank lines.
+    #[arg(long)]
+    all_solutions: bool,
+
+    /// Write the A* search graph to this file in Graphviz DOT format once the
+    /// search completes (single-threaded A* only).
+    #[arg(long, value_name = "FILE.dot")]
+    dump_graph: Option<String>,
+
     #[arg(short, long, value_enum, default_value_t)]
     algo: SearchAlgorithm,
+
+    /// Print search statistics at the end of the search.
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Write a JSON report with timings and search statistics.
+    #[arg(long)]
+    benchmark: bool,
+
+    /// Name of the benchmark report file
+    #[arg(long, default_value_t = String::from("benchmark.json"))]
+    benchmark_output: String,
+
+    /// Parse and validate the inputs, print a summary of the problem and exit
+    /// without running the search.
+    #[arg(long)]
+    dry_run: bool,
 }
 
 fn main() {
     let start_time = std::time::Instant::now();
 
     let args = Args::parse();
-    let target_filename = args.target;
-    let source_filename = args.source;
-    let moves_filename = args.moves;
-    let output_filename = args.output;
     let max_depth = args.depth;
+    let mut timings = PhaseTimings::default();
 
-    let source;
-    let target;
-    if args.algo == SearchAlgorithm::AstarStabiliser {
-        let source_filename =
-            source_filename.expect("For stabiliser search, source must be specified");
-        println!("Using source stabiliser in file \"{source_filename}\"");
-        let file = File::open(source_filename).expect("Unable to open source file");
-        source = CircuitOrStabiliser::Stabiliser(
-            parse_stabiliser(&file).expect("Unable to parse source stabiliser"),
-        );
+    let source = if let Some(source_filename) = &args.source {
+        let format = input_format(&args, source_filename, args.source_format);
+        println!("Using source {format:?} in file \"{source_filename}\"");
+        CircuitOrStabiliser::read(source_filename, format)
+            .unwrap_or_else(|err| exit_with_error(source_filename, err))
+    } else {
+        println!("Using identity circuit as source");
+        CircuitOrStabiliser::Circuit(CXCircuit16::new())
+    };
+    let (move_inds, moves, move_costs) = parse_moves_arg(&args);
+    if args.algo == SearchAlgorithm::AstarStabiliser && args.threads > 1 {
+        println!("--threads is not supported by astar-stabiliser, using a single thread");
+    }
+    let is_single_threaded_a_star = args.algo == SearchAlgorithm::AstarStabiliser
+        || args.algo == SearchAlgorithm::Astar && args.threads <= 1;
+    if move_costs.iter().any(|&(_, cost)| cost != 1.) && !is_single_threaded_a_star {
+        eprintln!("Error: move costs are only supported by single-threaded A*");
+        std::process::exit(1);
+    }
+    if args.allow_swaps && !is_single_threaded_a_star {
+        println!("SWAP moves are only supported by single-threaded A*, ignoring them");
+    }
+    if args.most_constrained && !is_single_threaded_a_star {
+        println!("--most-constrained is only supported by single-threaded A*, ignoring it");
+    }
+    if args.epsilon != 1. && !is_single_threaded_a_star {
+        println!("--epsilon is only supported by single-threaded A*, ignoring it");
+    }
+    if args.qubit_count != 16 {
+        check_qubit_count(&args, &move_inds);
+    }
+
+    if let Some(batch_dir) = &args.batch_dir {
+        let all_solved = solve_batch(&args, batch_dir, &source, &move_inds, &moves, &move_costs);
+        println!("\nTotal execution time: {:.2?}", start_time.elapsed());
+        if !all_solved {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let target_filename = &args.target;
+    let format = input_format(&args, target_filename, args.target_format);
+    println!("Using target {format:?} in file \"{target_filename}\"");
+    let target = CircuitOrStabiliser::read(target_filename, format)
+        .unwrap_or_else(|err| exit_with_error(target_filename, err));
+    let (source, target) = search_values(&args, source, target);
+    timings.parsing = start_time.elapsed().as_secs_f64();
+
+    if args.dry_run {
+        print_problem_summary(&source, &target, &move_inds, max_depth);
+        return;
+    }
 
-        println!("Using target stabiliser in file \"{target_filename}\"");
-        let file = File::open(target_filename).expect("Unable to open target file");
-        target = CircuitOrStabiliser::Stabiliser(
-            parse_stabiliser(&file).expect("Unable to parse target stabiliser"),
+    let search_start = Instant::now();
+    let (solutions, search_stats) =
+        search(&args, &source, &target, &move_inds, &moves, &move_costs);
+    timings.search = search_start.elapsed().as_secs_f64();
+    if let Some(stats) = search_stats.as_ref().filter(|_| args.verbose) {
+        println!("Nodes expanded: {}", stats.expansions);
+        println!(
+            "Graph size: {} nodes, {} edges",
+            stats.node_count, stats.edge_count
         );
-    } else {
-        if let Some(source_filename) = source_filename {
-            println!("Using source circuit in file \"{source_filename}\"");
-            let file = File::open(source_filename).expect("Unable to open source file");
-            source = CircuitOrStabiliser::Circuit(
-                parse_cx_circuit(&file).expect("Unable to parse source circuit"),
-            );
+        println!("Merges: {}", stats.merge_count);
+        println!(
+            "Depth: max {}, average {:.2}",
+            stats.max_depth, stats.avg_depth
+        );
+        println!(
+            "Memory freed by garbage collection: {} bytes",
+            stats.freed_bytes
+        );
+    }
+
+    if !solutions.is_empty() {
+        for solution in &solutions {
+            println!("Found a solution: {solution:?}");
+        }
+
+        let verification_start = Instant::now();
+        let is_correct = solutions
+            .iter()
+            .all(|solution| check_solution_correctness(solution, source.clone(), &target));
+        timings.verification = verification_start.elapsed().as_secs_f64();
+        if is_correct {
+            println!("Correctness check passed");
+            println!("Writing to {}", args.output);
+            let writing_start = Instant::now();
+            let elapsed_ms = (timings.search * 1000.) as u64;
+            write_solutions(
+                &args,
+                &args.output,
+                &solutions,
+                target.n_qubits(),
+                elapsed_ms,
+            )
+            .expect("Unable to save solution");
+            timings.writing = writing_start.elapsed().as_secs_f64();
         } else {
-            println!("Using identity circuit as source");
-            source = CircuitOrStabiliser::Circuit(CXCircuit16::new());
+            println!("Solution is incorrect! Please report this as a bug. Aborting");
         }
-        println!("Using target circuit in file \"{target_filename}\"");
+    } else {
+        println!("No solution found");
+    }
 
-        let file = File::open(target_filename).expect("Unable to open target file");
-        target = CircuitOrStabiliser::Circuit(
-            parse_cx_circuit(&file).expect("Unable to parse target circuit"),
-        );
+    if args.benchmark {
+        let report = BenchmarkReport {
+            git_commit: git_commit_hash(),
+            algorithm: algorithm_name(&args),
+            n_moves: move_inds.len(),
+            initial_dist: source.dist(&target),
+            solution_cx_count: solutions.first().map(|solution| solution.len()),
+            timings,
+            search_stats,
+        };
+        println!("Writing benchmark report to {}", args.benchmark_output);
+        let mut file = File::create(&args.benchmark_output).expect("Unable to open benchmark file");
+        report
+            .write(&mut file)
+            .expect("Unable to save benchmark report");
+    }
+
+    let elapsed_time = start_time.elapsed();
+    println!("\nTotal execution time: {:.2?}", elapsed_time);
+}
+
+/// The name of the search algorithm, as passed to `--algo`.
+fn algorithm_name(args: &Args) -> String {
+    args.algo
+        .to_possible_value()
+        .expect("no skipped algorithms")
+        .get_name()
+        .to_string()
+}
+
+/// The format of an input file, if not given explicitly.
+///
+/// Files with a `.qasm` extension are always read as QASM.
+fn input_format(args: &Args, filename: &str, format: Option<InputFormat>) -> InputFormat {
+    format.unwrap_or(if filename.ends_with(".qasm") {
+        InputFormat::Qasm
+    } else if args.algo == SearchAlgorithm::AstarStabiliser {
+        InputFormat::Stabiliser
+    } else {
+        InputFormat::Circuit
+    })
+}
+
+/// Report that `filename` could not be parsed and exit.
+fn exit_with_error(filename: &str, err: ParseError) -> ! {
+    eprintln!("Error: unable to parse \"{filename}\": {err}");
+    std::process::exit(1)
+}
+
+/// The allowed CXs, their circuits and the costs of the moves, if any.
+type ParsedMoves = (Vec<(usize, usize)>, Moves<CXCircuit16>, Vec<(CX, f64)>);
+
+/// Parse the moves given by `--moves` and `--moves-format`, along with their
+/// costs if the format has any.
+fn parse_moves_arg(args: &Args) -> ParsedMoves {
+    let moves_filename = &args.moves;
+    // A topology with its size, e.g. `linear:16`, needs no format
+    let moves_format = if args.moves_format == MovesFormat::Custom && moves_filename.contains(':') {
+        MovesFormat::Topology
+    } else {
+        args.moves_format
+    };
+    match moves_format {
+        MovesFormat::Custom => {
+            println!("Using moves in file \"{moves_filename}\"");
+            let move_costs = File::open(moves_filename)
+                .map_err(ParseError::from)
+                .and_then(|file| parse_weighted_moves(BufReader::new(file)))
+                .unwrap_or_else(|err| exit_with_error(moves_filename, err));
+            // Both directions of each pair are listed, one after the other
+            let pairs = move_costs
+                .iter()
+                .step_by(2)
+                .map(|(cx, _)| (cx.ctrl as usize, cx.tgt as usize));
+            let (move_inds, moves) = moves_from_pairs(pairs);
+            (move_inds, moves, move_costs)
+        }
+        MovesFormat::Topology => {
+            println!("Using built-in topology \"{moves_filename}\"");
+            let (move_inds, moves) =
+                moves_from_pairs(builtin_topology(moves_filename).expect("Unknown topology"));
+            (move_inds, moves, Vec::new())
+        }
+        MovesFormat::Qasm => {
+            println!("Using CX interactions in QASM file \"{moves_filename}\"");
+            let pairs = File::open(moves_filename)
+                .map_err(ParseError::from)
+                .and_then(|file| parse_qasm_interactions(BufReader::new(file)))
+                .unwrap_or_else(|err| exit_with_error(moves_filename, err));
+            let (move_inds, moves) = moves_from_pairs(pairs);
+            (move_inds, moves, Vec::new())
+        }
     }
-    println!("Using moves in file \"{moves_filename}\"");
-    let (move_inds, moves) = {
-        let file = File::open(moves_filename).expect("Unable to open moves file");
-        parse_moves(&file).expect("Unable to parse moves files")
+}
+
+/// The source and target to search on, converting both to stabiliser states
+/// if needed.
+fn search_values(
+    args: &Args,
+    source: CircuitOrStabiliser,
+    target: CircuitOrStabiliser,
+) -> (CircuitOrStabiliser, CircuitOrStabiliser) {
+    // Mixed searches are carried out on stabiliser states
+    let (source, target) = if args.algo == SearchAlgorithm::AstarStabiliser
+        || source.is_stabiliser()
+        || target.is_stabiliser()
+    {
+        (source.into_stabiliser(), target.into_stabiliser())
+    } else {
+        (source, target)
     };
+    if source.is_stabiliser() && args.algo != SearchAlgorithm::AstarStabiliser {
+        eprintln!("Error: searches on stabiliser states require `--algo astar-stabiliser`");
+        std::process::exit(1);
+    }
+    (source, target)
+}
 
-    // TODO make the function signatures match better
-    let solution = match args.algo {
-        SearchAlgorithm::MITM => mitm_bfs(
-            source.unwrap_circuit_ref(),
-            target.unwrap_circuit_ref(),
-            &moves,
-            max_depth,
-            true,
-        )
-        .map(|moves| moves.iter().map(|mv| move_inds[*mv].into()).collect()),
+/// Run the search selected by `args`, returning the solutions found and,
+/// for A* searches, search statistics.
+fn search(
+    args: &Args,
+    source: &CircuitOrStabiliser,
+    target: &CircuitOrStabiliser,
+    move_inds: &[(usize, usize)],
+    moves: &Moves<CXCircuit16>,
+    move_costs: &[(CX, f64)],
+) -> (Vec<Vec<CX>>, Option<SearchStats>) {
+    let max_depth = args.depth;
+    let all_solutions = args.all_solutions;
+    match args.algo {
+        SearchAlgorithm::MITM if all_solutions => {
+            if args.checkpoint_dir.is_some() {
+                println!("--checkpoint-dir is not supported with --all-solutions, ignoring it");
+            }
+            let (source, target) = (source.unwrap_circuit_ref(), target.unwrap_circuit_ref());
+            let solutions = mitm_bfs_all(source, target, moves, max_depth)
+                .into_iter()
+                .map(|moves| moves.iter().map(|mv| move_inds[*mv].into()).collect())
+                .collect();
+            (solutions, None)
+        }
+        SearchAlgorithm::MITM => {
+            let symmetries =
+                topology_automorphisms(&move_inds.iter().copied().map_into().collect_vec());
+            println!("Found {} topology symmetries", symmetries.len());
+            let (source, target) = (source.unwrap_circuit_ref(), target.unwrap_circuit_ref());
+            let solution = if let Some(checkpoint_dir) = &args.checkpoint_dir {
+                fs::create_dir_all(checkpoint_dir).expect("Unable to create checkpoint directory");
+                mitm_bfs_checkpointed(
+                    source,
+                    target,
+                    moves,
+                    max_depth,
+                    true,
+                    symmetries,
+                    Path::new(checkpoint_dir),
+                )
+                .expect("Unable to save or resume BFS checkpoints")
+            } else {
+                mitm_bfs(
+                    source,
+                    target,
+                    moves,
+                    max_depth,
+                    true,
+                    symmetries,
+                    !args.sequential,
+                )
+            };
+            let solutions = solution
+                .map(|moves| moves.iter().map(|mv| move_inds[*mv].into()).collect())
+                .into_iter()
+                .collect();
+            (solutions, None)
+        }
+        SearchAlgorithm::Astar if args.threads > 1 => {
+            if all_solutions {
+                println!(
+                    "--all-solutions is not supported with --threads, finding a single solution"
+                );
+            }
+            let moves = move_inds.iter().copied().map_into().collect();
+            let (source, target) = (source.unwrap_circuit_ref(), target.unwrap_circuit_ref());
+            let solution = if args.qubit_count == 8 {
+                let Some((source, target)) = to_circuits8(&source, &target) else {
+                    return (Vec::new(), None);
+                };
+                parallel_a_star(source, &target, moves, Some(max_depth), args.threads)
+            } else {
+                parallel_a_star(source, &target, moves, Some(max_depth), args.threads)
+            };
+            (solution.into_iter().collect(), None)
+        }
         SearchAlgorithm::Astar => {
+            let (source, target) = (source.unwrap_circuit_ref(), target.unwrap_circuit_ref());
+            let (solutions, stats) = if args.qubit_count == 8 {
+                let Some((source, target)) = to_circuits8(&source, &target) else {
+                    return (Vec::new(), None);
+                };
+                run_a_star(args, source, &target, move_inds, move_costs)
+            } else {
+                run_a_star(args, source, &target, move_inds, move_costs)
+            };
+            (solutions, Some(stats))
+        }
+        SearchAlgorithm::Ida => {
+            if all_solutions {
+                println!("--all-solutions is not supported for IDA, finding a single solution");
+            }
             let moves = move_inds.iter().copied().map_into();
-            a_star(
-                source.unwrap_circuit_ref(),
-                &target.unwrap_circuit_ref(),
-                moves,
-                Some(max_depth),
-            )
+            let (source, target) = (source.unwrap_circuit_ref(), target.unwrap_circuit_ref());
+            let solution = if args.qubit_count == 8 {
+                let Some((source, target)) = to_circuits8(&source, &target) else {
+                    return (Vec::new(), None);
+                };
+                ida_star(source, &target, moves, Some(max_depth))
+            } else {
+                ida_star(source, &target, moves, Some(max_depth))
+            };
+            (solution.into_iter().collect(), None)
         }
         SearchAlgorithm::AstarStabiliser => {
-            let moves = move_inds.iter().copied().map_into();
-            a_star(
-                source.unwrap_stabiliser_ref(),
-                &target.unwrap_stabiliser_ref(),
-                moves,
-                Some(max_depth),
-            )
+            let source = source.unwrap_stabiliser_ref();
+            let target = target.unwrap_stabiliser_ref();
+            let (solutions, stats) = run_a_star(args, source, &target, move_inds, move_costs);
+            (solutions, Some(stats))
         }
+    }
+}
+
+/// Check that the search selected by `args` can run on `--qubit-count`
+/// qubits with the moves `move_inds`, exiting otherwise.
+fn check_qubit_count(args: &Args, move_inds: &[(usize, usize)]) {
+    let n_qubits = args.qubit_count;
+    if !matches!(args.algo, SearchAlgorithm::Astar | SearchAlgorithm::Ida) {
+        eprintln!("Error: --qubit-count {n_qubits} is only supported by --algo astar and ida");
+        std::process::exit(1);
+    }
+    if let Some(&(a, b)) = move_inds.iter().find(|&&(a, b)| a.max(b) >= n_qubits) {
+        eprintln!("Error: move CX({a}, {b}) acts outside of the {n_qubits} qubits");
+        std::process::exit(1);
+    }
+}
+
+/// The source and target as 8-qubit circuits, if neither acts on the qubits
+/// after the first 8.
+fn to_circuits8(source: &CXCircuit16, target: &CXCircuit16) -> Option<(CXCircuit8, CXCircuit8)> {
+    let to_circuit8 = |circuit: &CXCircuit16| {
+        let is_identity_after_8 = (8..16).all(|i| circuit.row_bits(i) == 1 << i);
+        let rows = (0..8).map(|i| circuit.row_bits(i)).collect_vec();
+        (is_identity_after_8 && rows.iter().all(|&row| row < 1 << 8))
+            .then(|| CXCircuit8::from_row_bits(&rows))
     };
+    let circuits = to_circuit8(source).zip(to_circuit8(target));
+    if circuits.is_none() {
+        println!("The circuits act on more than 8 qubits, use --qubit-count 16");
+    }
+    circuits
+}
 
-    if let Some(solution) = solution {
-        println!("Found a solution: {solution:?}");
+/// Run the single-threaded A* search configured by `args`.
+fn run_a_star<V: AStarValue>(
+    args: &Args,
+    source: V,
+    target: &V,
+    move_inds: &[(usize, usize)],
+    move_costs: &[(CX, f64)],
+) -> (Vec<Vec<CX>>, SearchStats) {
+    let moves = move_inds.iter().copied().map_into();
+    let mut builder = AStarBuilder::new(source, target, moves)
+        .move_costs(move_costs.iter().copied())
+        .max_depth(args.depth)
+        .max_expansions(args.max_expansions)
+        .all_solutions(args.all_solutions)
+        .epsilon(args.epsilon)
+        .timeout(args.timeout.map(Duration::from_secs_f64));
+    if args.allow_swaps {
+        builder = builder.allow_swaps(args.swap_cost);
+    }
+    if args.most_constrained {
+        builder = builder.focus_mode(FocusMode::MostConstrained);
+    }
+    if let Some(dump_graph) = &args.dump_graph {
+        builder = builder.dump_graph(dump_graph);
+    }
+    builder.run()
+}
 
-        if check_solution_correctness(&solution, source, &target) {
-            println!("Correctness check passed");
-            println!("Writing to {output_filename}");
-            let mut file = File::create(output_filename).expect("Unable to open solution file");
-            save_solution(&mut file, &solution).expect("Unable to save solution");
-        } else {
-            println!("Solution is incorrect! Please report this as a bug. Aborting");
+/// Write `solutions` to `filename`, in the format given by `--format`.
+fn write_solutions(
+    args: &Args,
+    filename: &str,
+    solutions: &[Vec<CX>],
+    n_qubits: usize,
+    elapsed_ms: u64,
+) -> std::io::Result<()> {
+    let mut file = File::create(filename)?;
+    if args.format != OutputFormat::Pairs && solutions.len() > 1 {
+        println!(
+            "Only the first solution is written in {:?} format",
+            args.format
+        );
+    }
+    match args.format {
+        OutputFormat::Pairs => save_solutions(&mut file, solutions),
+        OutputFormat::Qasm => save_solution_qasm(&mut file, &solutions[0], n_qubits),
+        OutputFormat::Json => {
+            let metadata = SolutionMetadata {
+                algorithm: algorithm_name(args),
+                elapsed_ms,
+                gate_count: solutions[0].len(),
+                source_file: args.source.clone(),
+            };
+            save_solution_json(&mut file, &solutions[0], &metadata)
         }
-    } else {
-        println!("No solution found");
     }
+}
 
-    let elapsed_time = start_time.elapsed();
-    println!("\nTotal execution time: {:.2?}", elapsed_time);
+/// Solve every file in `batch_dir` as a separate target, in parallel, and
+/// write the solutions to files of the same name in `--output-dir`.
+///
+/// Returns whether a correct solution was found for every target.
+fn solve_batch(
+    args: &Args,
+    batch_dir: &str,
+    source: &CircuitOrStabiliser,
+    move_inds: &[(usize, usize)],
+    moves: &Moves<CXCircuit16>,
+    move_costs: &[(CX, f64)],
+) -> bool {
+    let output_dir = Path::new(args.output_dir.as_deref().expect("required by clap"));
+    fs::create_dir_all(output_dir).expect("Unable to create output directory");
+    let mut targets: Vec<PathBuf> = fs::read_dir(batch_dir)
+        .expect("Unable to read batch directory")
+        .map(|entry| entry.expect("Unable to read batch directory").path())
+        .filter(|path| path.is_file())
+        .collect();
+    targets.sort();
+    println!("Solving {} circuits in \"{batch_dir}\"", targets.len());
+
+    let n_solved = AtomicUsize::new(0);
+    let unsolved: Vec<&PathBuf> = targets
+        .par_iter()
+        .filter(|path| {
+            let filename = path.to_string_lossy();
+            let format = input_format(args, &filename, args.target_format);
+            let target = match CircuitOrStabiliser::read(&filename, format) {
+                Ok(target) => target,
+                Err(err) => {
+                    eprintln!("Error: unable to parse \"{filename}\": {err}");
+                    return true;
+                }
+            };
+            let (source, target) = search_values(args, source.clone(), target);
+
+            let search_start = Instant::now();
+            let (solutions, _) = search(args, &source, &target, move_inds, moves, move_costs);
+            let elapsed_ms = search_start.elapsed().as_millis() as u64;
+            let is_solved = !solutions.is_empty()
+                && solutions
+                    .iter()
+                    .all(|solution| check_solution_correctness(solution, source.clone(), &target));
+            if is_solved {
+                let output = output_dir.join(path.file_name().expect("files have a name"));
+                write_solutions(
+                    args,
+                    &output.to_string_lossy(),
+                    &solutions,
+                    target.n_qubits(),
+                    elapsed_ms,
+                )
+                .expect("Unable to save solution");
+                let n_solved = n_solved.fetch_add(1, Ordering::Relaxed) + 1;
+                println!("{n_solved}/{} circuits solved", targets.len());
+            }
+            !is_solved
+        })
+        .collect();
+
+    println!(
+        "\nSolved {}/{} circuits",
+        targets.len() - unsolved.len(),
+        targets.len()
+    );
+    for path in &unsolved {
+        println!("No solution found for \"{}\"", path.display());
+    }
+    unsolved.is_empty()
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -181,6 +745,45 @@ enum CircuitOrStabiliser {
 }
 
 impl CircuitOrStabiliser {
+    /// Parse a circuit or stabiliser state in the given format.
+    fn parse(reader: impl BufRead, format: InputFormat) -> Result<Self, ParseError> {
+        Ok(match format {
+            InputFormat::Circuit => Self::Circuit(parse_cx_circuit(reader)?),
+            InputFormat::Stabiliser => Self::Stabiliser(parse_stabiliser(reader)?),
+            InputFormat::Qasm => Self::Circuit(parse_qasm_circuit(reader)?),
+            InputFormat::Binary => Self::Circuit(parse_binary_matrix(reader)?),
+        })
+    }
+
+    /// Parse the circuit or stabiliser state in the file `filename`.
+    fn read(filename: &str, format: InputFormat) -> Result<Self, ParseError> {
+        Self::parse(BufReader::new(File::open(filename)?), format)
+    }
+
+    fn is_stabiliser(&self) -> bool {
+        matches!(self, Self::Stabiliser(_))
+    }
+
+    /// Whether the circuit is invertible or the stabiliser state is valid.
+    fn is_valid(&self) -> bool {
+        match self {
+            Self::Circuit(circuit) => circuit.rank() == 16,
+            Self::Stabiliser(stabiliser) => stabiliser.is_valid(),
+        }
+    }
+
+    /// Convert a circuit to the stabiliser state it prepares from the
+    /// all-plus state, see [`StabiliserState::from_cx_circuit`].
+    ///
+    /// CX gates act on the two in the same way, so that a circuit mapping
+    /// the source to the target also maps the converted states.
+    fn into_stabiliser(self) -> Self {
+        match self {
+            Self::Circuit(circuit) => Self::Stabiliser(StabiliserState::from_cx_circuit(&circuit)),
+            Self::Stabiliser(_) => self,
+        }
+    }
+
     fn unwrap_circuit_ref(&self) -> CXCircuit16 {
         match self {
             Self::Circuit(circuit) => circuit.clone(),
@@ -205,6 +808,13 @@ impl AStarValue for CircuitOrStabiliser {
         }
     }
 
+    fn n_qubits(&self) -> usize {
+        match self {
+            Self::Circuit(circuit) => circuit.n_qubits(),
+            Self::Stabiliser(stabiliser) => stabiliser.n_qubits(),
+        }
+    }
+
     fn is_complete(&self, qb: u8, target: &Self) -> bool {
         match (self, target) {
             (Self::Circuit(a), Self::Circuit(b)) => a.is_complete(qb, b),
@@ -213,6 +823,14 @@ impl AStarValue for CircuitOrStabiliser {
         }
     }
 
+    fn qubit_dist(&self, qb: u8, target: &Self) -> usize {
+        match (self, target) {
+            (Self::Circuit(a), Self::Circuit(b)) => a.qubit_dist(qb, b),
+            (Self::Stabiliser(a), Self::Stabiliser(b)) => a.qubit_dist(qb, b),
+            _ => panic!("Expected same type"),
+        }
+    }
+
     fn cx(&self, ctrl: u8, tgt: u8) -> Self {
         match self {
             Self::Circuit(circuit) => Self::Circuit(circuit.cx(ctrl, tgt)),
@@ -220,6 +838,13 @@ impl AStarValue for CircuitOrStabiliser {
         }
     }
 
+    fn swap(&self, q1: u8, q2: u8) -> Self {
+        match self {
+            Self::Circuit(circuit) => Self::Circuit(circuit.swap(q1, q2)),
+            Self::Stabiliser(stabiliser) => Self::Stabiliser(stabiliser.swap(q1, q2)),
+        }
+    }
+
     fn merge(&self, other: &Self, used_qubits: &fxhash::FxHashSet<u8>) -> Self {
         match (self, other) {
             (Self::Circuit(a), Self::Circuit(b)) => Self::Circuit(a.merge(b, used_qubits)),
@@ -227,6 +852,79 @@ impl AStarValue for CircuitOrStabiliser {
             _ => panic!("Expected same type"),
         }
     }
+
+    fn merge_with_gates(
+        &self,
+        other: &Self,
+        used_qubits: &fxhash::FxHashSet<u8>,
+        gates: &[(&Self, &Self)],
+    ) -> Self {
+        match (self, other) {
+            (Self::Stabiliser(a), Self::Stabiliser(b)) => {
+                let gates = gates
+                    .iter()
+                    .map(|&gate| match gate {
+                        (Self::Stabiliser(before), Self::Stabiliser(after)) => (before, after),
+                        _ => panic!("Expected same type"),
+                    })
+                    .collect::<Vec<_>>();
+                Self::Stabiliser(a.merge_with_gates(b, used_qubits, &gates))
+            }
+            _ => self.merge(other, used_qubits),
+        }
+    }
+
+    fn upper_bound(&self, target: &Self, allowed_moves: &fxhash::FxHashSet<CX>) -> Option<Vec<CX>> {
+        match (self, target) {
+            (Self::Circuit(a), Self::Circuit(b)) => a.upper_bound(b, allowed_moves),
+            (Self::Stabiliser(a), Self::Stabiliser(b)) => a.upper_bound(b, allowed_moves),
+            _ => panic!("Expected same type"),
+        }
+    }
+
+    fn secondary_cost(&self, target: &Self) -> usize {
+        match (self, target) {
+            (Self::Circuit(a), Self::Circuit(b)) => a.secondary_cost(b),
+            (Self::Stabiliser(a), Self::Stabiliser(b)) => a.secondary_cost(b),
+            _ => panic!("Expected same type"),
+        }
+    }
+}
+
+/// Validate the inputs and print a summary of the search problem.
+fn print_problem_summary(
+    source: &CircuitOrStabiliser,
+    target: &CircuitOrStabiliser,
+    move_inds: &[(usize, usize)],
+    max_depth: usize,
+) {
+    for (name, value) in [("Source", source), ("Target", target)] {
+        let kind = if value.is_stabiliser() {
+            "stabiliser state"
+        } else {
+            "circuit"
+        };
+        println!("{name}: {kind} on {} qubits", value.n_qubits());
+        if !value.is_valid() {
+            eprintln!("Error: {} {kind} is not valid", name.to_lowercase());
+            std::process::exit(1);
+        }
+    }
+    if source.n_qubits() != target.n_qubits() {
+        eprintln!("Error: source and target act on different numbers of qubits");
+        std::process::exit(1);
+    }
+    let n_qubits = target.n_qubits();
+    if let Some(&(a, b)) = move_inds.iter().find(|&&(a, b)| a.max(b) >= n_qubits) {
+        eprintln!("Error: move CX({a}, {b}) acts outside of the {n_qubits} qubits");
+        std::process::exit(1);
+    }
+    println!("Moves: {}", move_inds.len());
+    println!("Initial distance: {}", source.dist(target));
+    // Every CX can be followed by any move, so this grows exponentially
+    let search_space = (move_inds.len() as f64).powi(max_depth as i32);
+    println!("Estimated search space at depth {max_depth}: {search_space:.2e} circuits");
+    println!("Inputs are valid");
 }
 
 fn check_solution_correctness<V: AStarValue>(solution: &[CX], mut source: V, target: &V) -> bool {
@@ -238,23 +936,22 @@ fn check_solution_correctness<V: AStarValue>(solution: &[CX], mut source: V, tar
 
 #[cfg(test)]
 mod tests {
-    use std::fs::File;
+    use std::io::Cursor;
 
-    use crate::{
-        bfs::{bfs, mitm_bfs},
-        cx_circuit::{CXCircuit, CXCircuit16},
-        file_io::parse_moves,
-        Moves,
-    };
+    use fast_cx_circs::{bfs::bfs, mitm_bfs, parse_moves, CXCircuit, CXCircuit16, Moves};
+
+    use super::to_circuits8;
 
     fn run_test_e2e(
         cx_list: &[(usize, usize)],
         bfs: impl Fn(CXCircuit16, &Moves<CXCircuit16>, usize) -> Option<Vec<usize>>,
     ) {
-        let (move_inds, moves) = {
-            let file = File::open("all_to_all").expect("Unable to open moves file");
-            parse_moves(&file).expect("Unable to parse moves files")
-        };
+        // All-to-all connectivity on 16 qubits
+        let all_to_all: String = (0..16)
+            .flat_map(|i| (i + 1..16).map(move |j| format!("{i} {j}\n")))
+            .collect();
+        let (move_inds, moves) =
+            parse_moves(Cursor::new(all_to_all)).expect("Unable to parse moves files");
         let mut circuit = CXCircuit16::new();
         for &(ctrl, tgt) in cx_list {
             circuit.add_cx(ctrl, tgt);
@@ -275,8 +972,19 @@ mod tests {
         for cx_list in &test_cases {
             run_test_e2e(cx_list, bfs);
             run_test_e2e(cx_list, |a, b, c| {
-                mitm_bfs(CXCircuit16::new(), a, b, c, false)
+                mitm_bfs(CXCircuit16::new(), a, b, c, false, vec![], true)
             });
         }
     }
+
+    #[test]
+    fn circuits8() {
+        let source = CXCircuit16::new();
+        let target = CXCircuit16::from_cxs([(0, 7), (7, 3)]);
+        let (source8, target8) = to_circuits8(&source, &target).unwrap();
+        assert_eq!(source8, CXCircuit::new());
+        assert_eq!(target8, CXCircuit::from_cxs([(0, 7), (7, 3)]));
+        assert!(to_circuits8(&source, &CXCircuit16::from_cxs([(0, 8)])).is_none());
+        assert!(to_circuits8(&source, &CXCircuit16::from_cxs([(9, 8)])).is_none());
+    }
 }
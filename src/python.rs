@@ -0,0 +1,138 @@
+//! Python bindings, enabled by the `python` feature.
+//!
+//! Builds a `fast_cx_circs` extension module exposing [`CXCircuit16`] and the
+//! A* and meet-in-the-middle searches. Moves and solutions are passed as
+//! lists of `(ctrl, tgt)` pairs.
+
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use crate::{a_star, mitm_bfs, CXCircuit, CXCircuit16, CX};
+
+/// A 16-qubit CX circuit.
+#[pyclass(name = "CXCircuit16", module = "fast_cx_circs")]
+pub struct PyCXCircuit16(CXCircuit16);
+
+#[pymethods]
+impl PyCXCircuit16 {
+    /// The identity circuit.
+    #[new]
+    fn new() -> Self {
+        Self(CXCircuit16::new())
+    }
+
+    /// Apply a CX gate to the circuit.
+    fn add_cx(&mut self, ctrl: usize, tgt: usize) -> PyResult<()> {
+        check_cx((ctrl, tgt))?;
+        self.0.add_cx(ctrl, tgt);
+        Ok(())
+    }
+
+    /// Compose two circuits together.
+    fn mult(&self, other: &Self) -> Self {
+        Self(self.0.mult(&other.0))
+    }
+
+    fn transpose(&self) -> Self {
+        Self(self.0.transpose())
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+
+    fn __repr__(&self) -> String {
+        let rows = (0..16).map(|i| self.0.row(i).to_string());
+        format!("CXCircuit16([{}])", rows.collect::<Vec<_>>().join(", "))
+    }
+}
+
+/// Raise a `ValueError` if `(ctrl, tgt)` is not a CX on two of the 16 qubits.
+fn check_cx((ctrl, tgt): (usize, usize)) -> PyResult<()> {
+    if ctrl >= 16 || tgt >= 16 {
+        return Err(PyValueError::new_err(format!(
+            "CX({ctrl}, {tgt}) acts outside of the 16 qubits"
+        )));
+    }
+    if ctrl == tgt {
+        return Err(PyValueError::new_err(format!(
+            "CX({ctrl}, {tgt}) has the same control and target"
+        )));
+    }
+    Ok(())
+}
+
+/// Find a shortest sequence of `moves` that maps `start` to `target`, using
+/// A* search.
+///
+/// The search is aborted beyond `max_depth` CXs, in which case the best
+/// solution found so far is returned, if any.
+#[pyfunction]
+#[pyo3(name = "a_star", signature = (start, target, moves, max_depth = None))]
+fn py_a_star(
+    py: Python<'_>,
+    start: &PyCXCircuit16,
+    target: &PyCXCircuit16,
+    moves: Vec<(usize, usize)>,
+    max_depth: Option<usize>,
+) -> PyResult<Option<Vec<(usize, usize)>>> {
+    moves.iter().copied().try_for_each(check_cx)?;
+    let (start, target) = (start.0, target.0);
+    let solution = py.detach(|| {
+        a_star(
+            start,
+            &target,
+            moves.into_iter().map(CX::from),
+            max_depth,
+            None,
+        )
+    });
+    Ok(solution.map(|cxs| {
+        cxs.iter()
+            .map(|cx| (cx.ctrl as usize, cx.tgt as usize))
+            .collect()
+    }))
+}
+
+/// Find a shortest sequence of `moves` that maps `source` to `target`, using
+/// a meet-in-the-middle BFS.
+///
+/// Returns `None` if no solution is found. See [`mitm_bfs`] for the meaning
+/// of `max_depth` and `extrapolate`.
+#[pyfunction]
+#[pyo3(name = "mitm_bfs", signature = (source, target, moves, max_depth, extrapolate = true))]
+fn py_mitm_bfs(
+    py: Python<'_>,
+    source: &PyCXCircuit16,
+    target: &PyCXCircuit16,
+    moves: Vec<(usize, usize)>,
+    max_depth: usize,
+    extrapolate: bool,
+) -> PyResult<Option<Vec<(usize, usize)>>> {
+    moves.iter().copied().try_for_each(check_cx)?;
+    let (source, target) = (source.0, target.0);
+    // Moves are stored as the transpose, see `file_io::moves_from_pairs`
+    let move_circs: Vec<_> = moves
+        .iter()
+        .map(|&cx| CXCircuit16::from_cxs([cx]).transpose())
+        .collect();
+    let solution = py.detach(|| {
+        mitm_bfs(
+            source,
+            target,
+            &move_circs,
+            max_depth,
+            extrapolate,
+            vec![],
+            true,
+        )
+    });
+    Ok(solution.map(|inds| inds.into_iter().map(|i| moves[i]).collect()))
+}
+
+#[pymodule]
+fn fast_cx_circs(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyCXCircuit16>()?;
+    m.add_function(wrap_pyfunction!(py_a_star, m)?)?;
+    m.add_function(wrap_pyfunction!(py_mitm_bfs, m)?)?;
+    Ok(())
+}
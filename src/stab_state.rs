@@ -1,60 +1,362 @@
-use std::fmt::Debug;
+use std::{
+    cmp::{max, Ordering},
+    fmt::Debug,
+};
 
 use fxhash::FxHashSet;
+use rayon::prelude::*;
 
-use crate::a_star::AStarValue;
+use crate::{a_star::AStarValue, cx_circuit::CXCircuit16};
 
-/// A stabiliser state on N <= 16 qubits, defined by `N` X stabilisers.
+/// A Clifford gate, acting on the given qubits.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum CliffordGate {
+    /// Hadamard gate
+    H(usize),
+    /// Phase gate
+    S(usize),
+    /// CX gate, with control and target qubits
+    CX(usize, usize),
+}
+
+/// A stabiliser state on N <= 16 qubits, defined by `N` stabilisers.
 ///
-/// Note that we would also need N Z stabilisers, but they do not matter for
-/// the problem of finding CX circuits.
+/// The Z parts of the stabilisers are only set for graph states, states
+/// constructed from Clifford circuits or states modified with
+/// [`Self::hadamard`] and [`Self::phase`].
+/// States read from X stabilisers have no Z parts, and CX gates keep it that
+/// way.
 #[derive(Clone, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct StabiliserState<const N: usize> {
-    /// The X stabilisers
+    /// The X parts of the stabilisers, one row per qubit
+    ///
+    /// Bit `j` of row `i` is set if generator `j` has an X on qubit `i`.
     x_stabs: [u16; N],
+    /// The Z parts of the stabilisers, in the same layout as `x_stabs`
+    z_stabs: [u16; N],
+    /// The phases of the stabilisers, as a bitmask over the generators
+    ///
+    /// Bit `i` is set if generator `i` has a phase of -1.
+    phases: u16,
 }
 
 impl<const N: usize> StabiliserState<N> {
+    /// The state with the X stabilisers given as strings of `X`s and `I`s.
+    ///
+    /// String `i` holds the row of qubit `i`: its character `j` is `X` if
+    /// generator `j` has an X on qubit `i`, as in
+    /// [`StabiliserState::to_binary_matrix`].
     pub fn from_strs<'a>(x_stabs_str: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut rows = [[false; N]; N];
+        for (row, stab_str) in rows.iter_mut().zip(x_stabs_str) {
+            for (j, x) in stab_str.chars().enumerate() {
+                row[j] = is_set(x);
+            }
+        }
+        Self::from_binary_matrix(&rows)
+    }
+
+    /// The state obtained by applying `gates` to the all-zero state.
+    pub fn from_clifford_circuit(gates: &[CliffordGate]) -> Self {
+        assert!(N <= 16);
+        // Generator `i` of the all-zero state is Z on qubit `i`
+        let mut state = Self {
+            x_stabs: [0; N],
+            z_stabs: std::array::from_fn(|i| 1 << i),
+            phases: 0,
+        };
+        for &gate in gates {
+            match gate {
+                CliffordGate::H(qb) => state.apply_h(qb),
+                CliffordGate::S(qb) => state.apply_s(qb),
+                CliffordGate::CX(ctrl, tgt) => state.apply_cx(ctrl, tgt),
+            }
+        }
+        state
+    }
+
+    /// The graph state of the graph on `N` qubits with edges `edges`.
+    ///
+    /// Generator `i` is `X` on qubit `i` and `Z` on its neighbours. Repeated
+    /// edges are ignored. Panics if an edge is a self-loop or acts on a
+    /// qubit beyond `N`.
+    pub fn graph_state(edges: &[(usize, usize)]) -> Self {
         assert!(N <= 16);
-        let mut x_stabs = [0; N];
+        let mut z_stabs = [0; N];
+        for &(a, b) in edges {
+            assert!(
+                a < N && b < N,
+                "edge ({a}, {b}) acts on more than {N} qubits"
+            );
+            assert_ne!(a, b, "graph states have no self-loops");
+            // Generator `a` has Z on qubit `b` and vice versa
+            z_stabs[b] |= 1 << a;
+            z_stabs[a] |= 1 << b;
+        }
+        Self {
+            x_stabs: std::array::from_fn(|i| 1 << i),
+            z_stabs,
+            phases: 0,
+        }
+    }
 
-        for (stab_u16, stab_str) in x_stabs.iter_mut().zip(x_stabs_str) {
-            let set_digits = stab_str
-                .chars()
-                .enumerate()
-                .filter_map(|(j, x)| is_set(x).then_some(j));
-            for j in set_digits {
-                *stab_u16 ^= 1 << j;
+    /// A random circuit of `depth` gates on `N` qubits.
+    ///
+    /// Gate types are drawn uniformly from `gates`; the qubits of the gates in
+    /// `gates` are ignored and chosen uniformly at random instead.
+    #[cfg(any(test, feature = "rand"))]
+    pub fn random_unitary_circuit(
+        rng: &mut impl rand::Rng,
+        depth: usize,
+        gates: &[CliffordGate],
+    ) -> Vec<CliffordGate> {
+        use rand::RngExt;
+
+        assert!(!gates.is_empty(), "no gates to choose from");
+        (0..depth)
+            .map(|_| match gates[rng.random_range(0..gates.len())] {
+                CliffordGate::H(_) => CliffordGate::H(rng.random_range(0..N)),
+                CliffordGate::S(_) => CliffordGate::S(rng.random_range(0..N)),
+                CliffordGate::CX(_, _) => {
+                    assert!(N >= 2, "CX gates require at least two qubits");
+                    let ctrl = rng.random_range(0..N);
+                    // Pick a target distinct from the control
+                    let tgt = (ctrl + rng.random_range(1..N)) % N;
+                    CliffordGate::CX(ctrl, tgt)
+                }
+            })
+            .collect()
+    }
+
+    /// Whether the generators define a valid stabiliser state.
+    ///
+    /// The generators must pairwise commute and be independent.
+    pub fn is_valid(&self) -> bool {
+        // Generator `g` anticommutes with the generators in `anticommuting[g]`
+        let mut anticommuting = [0u16; N];
+        for (&x, &z) in self.x_stabs.iter().zip(&self.z_stabs) {
+            for (g, row) in anticommuting.iter_mut().enumerate() {
+                if x & (1 << g) != 0 {
+                    *row ^= z;
+                }
+                if z & (1 << g) != 0 {
+                    *row ^= x;
+                }
+            }
+        }
+        if anticommuting.iter().any(|&row| row != 0) {
+            return false;
+        }
+
+        // The generators as bit vectors, with X on bits 0..N and Z on bits
+        // N..2N, must be linearly independent
+        let mut basis: Vec<u32> = Vec::with_capacity(N);
+        for g in 0..N {
+            let mut generator = (0..N)
+                .map(|q| {
+                    let x = (self.x_stabs[q] >> g) & 1;
+                    let z = (self.z_stabs[q] >> g) & 1;
+                    (x as u32) << q | (z as u32) << (N + q)
+                })
+                .fold(0, |acc, bits| acc | bits);
+            // Reduce by the basis, sorted by decreasing leading bit
+            for &b in &basis {
+                generator = generator.min(generator ^ b);
             }
+            if generator == 0 {
+                return false;
+            }
+            basis.push(generator);
+            basis.sort_unstable_by(|a, b| b.cmp(a));
+        }
+        true
+    }
+
+    fn apply_h(&mut self, qb: usize) {
+        self.phases ^= self.x_stabs[qb] & self.z_stabs[qb];
+        std::mem::swap(&mut self.x_stabs[qb], &mut self.z_stabs[qb]);
+    }
+
+    fn apply_s(&mut self, qb: usize) {
+        self.phases ^= self.x_stabs[qb] & self.z_stabs[qb];
+        self.z_stabs[qb] ^= self.x_stabs[qb];
+    }
+
+    fn apply_cx(&mut self, ctrl: usize, tgt: usize) {
+        let (x_ctrl, z_ctrl) = (self.x_stabs[ctrl], self.z_stabs[ctrl]);
+        let (x_tgt, z_tgt) = (self.x_stabs[tgt], self.z_stabs[tgt]);
+        self.phases ^= x_ctrl & z_tgt & !(x_tgt ^ z_ctrl);
+        self.x_stabs[tgt] ^= x_ctrl;
+        self.z_stabs[ctrl] ^= z_tgt;
+    }
+
+    /// The state after a Hadamard gate on `qubit`, swapping its X and Z rows.
+    pub fn hadamard(&self, qubit: u8) -> Self {
+        let mut new = self.clone();
+        new.apply_h(qubit as usize);
+        new
+    }
+
+    /// The state after an S gate on `qubit`, XOR-ing its X row into its Z
+    /// row.
+    pub fn phase(&self, qubit: u8) -> Self {
+        let mut new = self.clone();
+        new.apply_s(qubit as usize);
+        new
+    }
+
+    /// Flip the phase of every stabiliser with an X on `qubit`.
+    ///
+    /// This is the action of a Z gate on `qubit`. Note that CX gates never
+    /// change the phases of X stabilisers: a phase flip requires an X on the
+    /// control and a Z on the target.
+    pub fn apply_phase_flip(&mut self, qubit: u8) {
+        self.phases ^= self.x_stabs[qubit as usize];
+    }
+
+    /// The pairwise distances between `states`.
+    ///
+    /// Entry `(i, j)` is `states[i].dist(&states[j])`. Only the upper triangle
+    /// is computed, in parallel, as the matrix is symmetric.
+    pub fn distance_matrix(states: &[Self]) -> Vec<Vec<usize>> {
+        let upper: Vec<Vec<usize>> = states
+            .par_iter()
+            .enumerate()
+            .map(|(i, a)| states[i + 1..].iter().map(|b| a.dist(b)).collect())
+            .collect();
+        (0..states.len())
+            .map(|i| {
+                (0..states.len())
+                    .map(|j| match i.cmp(&j) {
+                        Ordering::Less => upper[i][j - i - 1],
+                        Ordering::Equal => 0,
+                        Ordering::Greater => upper[j][i - j - 1],
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The X stabilisers as a binary matrix.
+    ///
+    /// Entry `(i, j)` is `true` if generator `j` has an X on qubit `i`.
+    pub fn to_binary_matrix(&self) -> [[bool; N]; N] {
+        self.x_stabs
+            .map(|stab| std::array::from_fn(|j| stab & (1 << j) != 0))
+    }
+
+    /// Construct a stabiliser state from the binary matrix of its X
+    /// stabilisers.
+    ///
+    /// This is the inverse of [`StabiliserState::to_binary_matrix`].
+    pub fn from_binary_matrix(rows: &[[bool; N]; N]) -> Self {
+        Self::from_u16_rows(std::array::from_fn(|i| {
+            rows[i]
+                .iter()
+                .enumerate()
+                .filter(|&(_, &is_set)| is_set)
+                .fold(0, |stab, (j, _)| stab | (1 << j))
+        }))
+    }
+
+    /// The X stabilisers, with bit `j` of row `i` set if generator `j` has
+    /// an X on qubit `i`.
+    pub fn to_u16_rows(&self) -> [u16; N] {
+        self.x_stabs
+    }
+
+    /// Construct a stabiliser state from its X stabilisers, in the layout of
+    /// [`StabiliserState::to_u16_rows`].
+    pub fn from_u16_rows(rows: [u16; N]) -> Self {
+        assert!(N <= 16);
+        Self {
+            x_stabs: rows,
+            z_stabs: [0; N],
+            phases: 0,
         }
-        Self { x_stabs }
+    }
+}
+
+impl StabiliserState<16> {
+    /// The state obtained by applying `circuit` to the all-plus state.
+    ///
+    /// Its X stabilisers are the rows of the circuit matrix.
+    pub fn from_cx_circuit(circuit: &CXCircuit16) -> Self {
+        let all_plus = Self::from_u16_rows(std::array::from_fn(|i| 1 << i));
+        circuit.apply_to_stabiliser_state(&all_plus)
+    }
+
+    /// The circuit with the X stabilisers as rows of its matrix.
+    ///
+    /// This is the inverse of [`StabiliserState::from_cx_circuit`], up to the
+    /// Z parts and phases. Returns `None` if the X stabilisers are not
+    /// independent.
+    pub fn to_cx_circuit(&self) -> Option<CXCircuit16> {
+        if self.x_stabs.contains(&0) {
+            return None;
+        }
+        let circuit = CXCircuit16::from_sparse_repr(self.x_stabs.into_iter().enumerate());
+        (circuit.rank() == 16).then_some(circuit)
     }
 }
 
 impl<const N: usize> AStarValue for StabiliserState<N> {
     fn dist(&self, other: &Self) -> usize {
-        self.x_stabs
-            .iter()
-            .zip(other.x_stabs.iter())
-            .map(|(a, b)| (a != b) as usize)
-            .sum()
+        // A CX changes one X row and one Z row, so the larger of the two
+        // counts is a lower bound
+        let count_diffs =
+            |a: &[u16; N], b: &[u16; N]| a.iter().zip(b).filter(|(a, b)| a != b).count();
+        max(
+            count_diffs(&self.x_stabs, &other.x_stabs),
+            count_diffs(&self.z_stabs, &other.z_stabs),
+        )
+    }
+
+    fn n_qubits(&self) -> usize {
+        N
     }
 
     fn is_complete(&self, qb: u8, target: &Self) -> bool {
-        self.x_stabs[qb as usize] == target.x_stabs[qb as usize]
+        let qb = qb as usize;
+        self.x_stabs[qb] == target.x_stabs[qb] && self.z_stabs[qb] == target.z_stabs[qb]
+    }
+
+    fn qubit_dist(&self, qb: u8, target: &Self) -> usize {
+        let qb = qb as usize;
+        let x_diff = self.x_stabs[qb] ^ target.x_stabs[qb];
+        let z_diff = self.z_stabs[qb] ^ target.z_stabs[qb];
+        (x_diff.count_ones() + z_diff.count_ones()) as usize
     }
 
     fn cx(&self, ctrl: u8, tgt: u8) -> Self {
         let mut new = self.clone();
-        new.x_stabs[tgt as usize] ^= self.x_stabs[ctrl as usize];
+        new.apply_cx(ctrl as usize, tgt as usize);
         new
     }
 
+    /// Takes the phases of `self`, see [`Self::merge_with_gates`] to also
+    /// carry the phase flips of `other`.
     fn merge(&self, other: &Self, used_qubits: &FxHashSet<u8>) -> Self {
         let mut new = self.clone();
         for &qb in used_qubits {
             new.x_stabs[qb as usize] = other.x_stabs[qb as usize];
+            new.z_stabs[qb as usize] = other.z_stabs[qb as usize];
+        }
+        new
+    }
+
+    /// The phases are those of `self`, flipped by each gate leading to
+    /// `other`. As the gates of both branches act on different qubits, the
+    /// flips of a gate do not depend on the other branch.
+    fn merge_with_gates(
+        &self,
+        other: &Self,
+        used_qubits: &FxHashSet<u8>,
+        gates: &[(&Self, &Self)],
+    ) -> Self {
+        let mut new = self.merge(other, used_qubits);
+        for (before, after) in gates {
+            new.phases ^= before.phases ^ after.phases;
         }
         new
     }
@@ -77,9 +379,315 @@ impl<const N: usize> Debug for StabiliserState<N> {
     }
 }
 
+/// Serialised as a struct of the `x_stabs` and `z_stabs` arrays, of `N`
+/// bitmasks each, and the `phases` bitmask. Unlike [`CXCircuit16`], this is not
+/// a plain array of rows: the Z stabilisers and phases must round-trip too.
+#[cfg(feature = "serde")]
+impl<const N: usize> serde::Serialize for StabiliserState<N> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("StabiliserState", 3)?;
+        state.serialize_field("x_stabs", &self.x_stabs[..])?;
+        state.serialize_field("z_stabs", &self.z_stabs[..])?;
+        state.serialize_field("phases", &self.phases)?;
+        state.end()
+    }
+}
+
+/// The stabilisers and phases must act on at most `N` qubits and generators.
+#[cfg(feature = "serde")]
+impl<'de, const N: usize> serde::Deserialize<'de> for StabiliserState<N> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        #[derive(serde::Deserialize)]
+        struct Fields {
+            x_stabs: Vec<u16>,
+            z_stabs: Vec<u16>,
+            phases: u16,
+        }
+
+        let in_range = |bits: u16| N >= 16 || bits >> N == 0;
+        let to_array = |stabs: Vec<u16>, pauli: char| -> Result<[u16; N], D::Error> {
+            let stabs: [u16; N] = stabs.try_into().map_err(|stabs: Vec<u16>| {
+                D::Error::invalid_length(stabs.len(), &format!("{N} {pauli} stabilisers").as_str())
+            })?;
+            if !stabs.iter().all(|&stab| in_range(stab)) {
+                return Err(D::Error::custom(format!(
+                    "{pauli} stabilisers must act on at most {N} qubits"
+                )));
+            }
+            Ok(stabs)
+        };
+
+        let fields = Fields::deserialize(deserializer)?;
+        if !in_range(fields.phases) {
+            return Err(D::Error::custom(format!(
+                "phases must be set on at most {N} generators"
+            )));
+        }
+        Ok(Self {
+            x_stabs: to_array(fields.x_stabs, 'X')?,
+            z_stabs: to_array(fields.z_stabs, 'Z')?,
+            phases: fields.phases,
+        })
+    }
+}
+
 fn u16_as_str<const N: usize>(bits: u16, pauli: char) -> String {
     (0..N)
         .map(|i| bits & (1 << i) != 0)
         .map(|p| if p { pauli } else { 'I' })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_matrix_round_trip() {
+        let state = StabiliserState::<3>::from_strs(["XXI", "IXI", "IXX"]);
+        let matrix = state.to_binary_matrix();
+        assert_eq!(
+            matrix,
+            [
+                [true, true, false],
+                [false, true, false],
+                [false, true, true]
+            ]
+        );
+        assert_eq!(StabiliserState::from_binary_matrix(&matrix), state);
+    }
+
+    #[test]
+    fn u16_rows_round_trip() {
+        let state = StabiliserState::<3>::from_strs(["XXI", "IXI", "IXX"]);
+        let rows = state.to_u16_rows();
+        assert_eq!(rows, [0b011, 0b010, 0b110]);
+        assert_eq!(StabiliserState::from_u16_rows(rows), state);
+    }
+
+    #[test]
+    fn strs_round_trip() {
+        let strs = ["XIXI", "IXII", "IIXX", "XXXX"];
+        let state = StabiliserState::<4>::from_strs(strs);
+        let round_trip = state.to_binary_matrix().map(|row| {
+            row.iter()
+                .map(|&is_set| if is_set { 'X' } else { 'I' })
+                .collect::<String>()
+        });
+        assert_eq!(round_trip, strs);
+
+        // Rows are indexed by qubit: generator 0 of the Bell state is XX
+        let bell = StabiliserState::<2>::from_clifford_circuit(&[
+            CliffordGate::H(0),
+            CliffordGate::CX(0, 1),
+        ]);
+        assert_eq!(bell.to_binary_matrix(), [[true, false], [true, false]]);
+        assert_eq!(
+            StabiliserState::<2>::from_strs(["XI", "XI"]).x_stabs,
+            bell.x_stabs
+        );
+    }
+
+    #[test]
+    fn phase_flip() {
+        let mut state = StabiliserState::<3>::from_strs(["XXI", "IXI", "IXX"]);
+        state.apply_phase_flip(0);
+        assert_eq!(state.phases, 0b011);
+        // Phases are preserved by CX gates
+        state = state.cx(0, 1);
+        state.apply_phase_flip(1);
+        assert_eq!(state.phases, 0b010);
+        state.apply_phase_flip(1);
+        assert_eq!(state.phases, 0b011);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let gates = [
+            CliffordGate::H(0),
+            CliffordGate::S(0),
+            CliffordGate::CX(0, 0),
+        ];
+        for _ in 0..20 {
+            let circuit = StabiliserState::<5>::random_unitary_circuit(&mut rng, 20, &gates);
+            let mut state = StabiliserState::<5>::from_clifford_circuit(&circuit);
+            state.apply_phase_flip(2);
+            let json = serde_json::to_string(&state).unwrap();
+            assert_eq!(
+                serde_json::from_str::<StabiliserState<5>>(&json).unwrap(),
+                state
+            );
+        }
+        let graph = StabiliserState::<4>::graph_state(&[(0, 1), (1, 2), (2, 3)]);
+        let json = serde_json::to_string(&graph).unwrap();
+        assert_eq!(
+            serde_json::from_str::<StabiliserState<4>>(&json).unwrap(),
+            graph
+        );
+
+        let parse = serde_json::from_str::<StabiliserState<3>>;
+        assert!(parse(r#"{"x_stabs":[1,2,4],"z_stabs":[0,0,0],"phases":5}"#).is_ok());
+        assert!(parse(r#"{"x_stabs":[1,2],"z_stabs":[0,0,0],"phases":0}"#).is_err());
+        assert!(parse(r#"{"x_stabs":[1,2,4],"z_stabs":[0,8,0],"phases":0}"#).is_err());
+        assert!(parse(r#"{"x_stabs":[1,2,4],"z_stabs":[0,0,0],"phases":8}"#).is_err());
+        assert!(parse("[1,2,4]").is_err());
+    }
+
+    /// The gates of a CZ, which graph states apply on each edge.
+    fn cz(ctrl: usize, tgt: usize) -> [CliffordGate; 3] {
+        use CliffordGate::*;
+        [H(tgt), CX(ctrl, tgt), H(tgt)]
+    }
+
+    #[test]
+    fn graph_states() {
+        // Equivalent to a Bell state, up to a Hadamard on one qubit
+        let bell = StabiliserState::<2>::graph_state(&[(0, 1)]);
+        assert!(bell.is_valid());
+        assert_eq!((bell.x_stabs, bell.z_stabs), ([0b01, 0b10], [0b10, 0b01]));
+        let mut gates = vec![CliffordGate::H(0), CliffordGate::H(1)];
+        gates.extend(cz(0, 1));
+        assert_eq!(StabiliserState::from_clifford_circuit(&gates), bell);
+        assert_eq!(StabiliserState::graph_state(&[(1, 0), (0, 1)]), bell);
+
+        let edges = [(0, 1), (1, 2), (2, 3)];
+        let cluster = StabiliserState::<4>::graph_state(&edges);
+        assert!(cluster.is_valid());
+        // Generator 1 is ZXZI
+        assert_eq!(cluster.x_stabs.map(|x| x >> 1 & 1), [0, 1, 0, 0]);
+        assert_eq!(cluster.z_stabs.map(|z| z >> 1 & 1), [1, 0, 1, 0]);
+        let mut gates = (0..4).map(CliffordGate::H).collect::<Vec<_>>();
+        gates.extend(edges.iter().flat_map(|&(a, b)| cz(a, b)));
+        assert_eq!(StabiliserState::from_clifford_circuit(&gates), cluster);
+    }
+
+    #[test]
+    fn hadamard_and_phase() {
+        let state = StabiliserState::<2>::from_strs(["XI", "IX"]);
+        let h = state.hadamard(0);
+        assert_eq!((h.x_stabs[0], h.z_stabs[0]), (0, state.x_stabs[0]));
+        assert_eq!(h.hadamard(0), state);
+        let s = state.phase(1);
+        assert_eq!(s.z_stabs[1], s.x_stabs[1]);
+
+        // Differences in X and Z rows are bounded separately
+        assert_eq!(state.dist(&h), 1);
+        assert_eq!(state.dist(&s.phase(0)), 2);
+        assert!(!h.is_complete(0, &state));
+        assert_eq!(h.qubit_dist(0, &state), 2);
+        assert!(h.is_complete(1, &state));
+    }
+
+    #[test]
+    fn hadamard_conjugated_cx_is_cz() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        /// The state after a CZ gate, using the conjugation rules
+        /// `X_c -> X_c Z_t` and `X_t -> Z_c X_t`
+        fn cz(state: &StabiliserState<5>, ctrl: usize, tgt: usize) -> StabiliserState<5> {
+            let mut new = state.clone();
+            let (x_ctrl, x_tgt) = (state.x_stabs[ctrl], state.x_stabs[tgt]);
+            new.phases ^= x_ctrl & x_tgt & (state.z_stabs[ctrl] ^ state.z_stabs[tgt]);
+            new.z_stabs[ctrl] ^= x_tgt;
+            new.z_stabs[tgt] ^= x_ctrl;
+            new
+        }
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let gates = [
+            CliffordGate::H(0),
+            CliffordGate::S(0),
+            CliffordGate::CX(0, 1),
+        ];
+        for _ in 0..50 {
+            let circuit = StabiliserState::<5>::random_unitary_circuit(&mut rng, 20, &gates);
+            let state = StabiliserState::<5>::from_clifford_circuit(&circuit);
+            for (ctrl, tgt) in [(0, 1), (3, 2), (4, 0)] {
+                let h_cx_h = state.hadamard(tgt).cx(ctrl, tgt).hadamard(tgt);
+                assert_eq!(h_cx_h, cz(&state, ctrl as usize, tgt as usize));
+            }
+        }
+    }
+
+    #[test]
+    fn bell_state_from_clifford_circuit() {
+        let state = StabiliserState::<2>::from_clifford_circuit(&[
+            CliffordGate::H(0),
+            CliffordGate::CX(0, 1),
+        ]);
+        // Generators XX and ZZ
+        assert_eq!(state.x_stabs, [0b01, 0b01]);
+        assert_eq!(state.z_stabs, [0b10, 0b10]);
+        assert_eq!(state.phases, 0);
+    }
+
+    #[test]
+    fn phase_from_clifford_circuit() {
+        // S^2 = Z maps |+> to |->, with stabiliser -X
+        let state = StabiliserState::<1>::from_clifford_circuit(&[
+            CliffordGate::H(0),
+            CliffordGate::S(0),
+            CliffordGate::S(0),
+        ]);
+        let mut expected = StabiliserState::<1>::from_clifford_circuit(&[CliffordGate::H(0)]);
+        expected.apply_phase_flip(0);
+        assert_eq!(state, expected);
+        assert_eq!(state.phases, 1);
+    }
+
+    #[test]
+    fn distance_matrix() {
+        let states = [
+            StabiliserState::<3>::from_strs(["XII", "IXI", "IIX"]),
+            StabiliserState::<3>::from_strs(["XXI", "IXI", "IIX"]),
+            StabiliserState::<3>::from_strs(["XXI", "IXX", "IIX"]),
+        ];
+        assert_eq!(
+            StabiliserState::distance_matrix(&states),
+            vec![vec![0, 1, 2], vec![1, 0, 1], vec![2, 1, 0]]
+        );
+        assert!(StabiliserState::<3>::distance_matrix(&[]).is_empty());
+    }
+
+    #[test]
+    fn random_clifford_circuits_are_valid() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let gates = [
+            CliffordGate::H(0),
+            CliffordGate::S(0),
+            CliffordGate::CX(0, 1),
+        ];
+        for depth in [0, 1, 5, 20, 100] {
+            for _ in 0..50 {
+                let circuit = StabiliserState::<5>::random_unitary_circuit(&mut rng, depth, &gates);
+                assert_eq!(circuit.len(), depth);
+                let state = StabiliserState::<5>::from_clifford_circuit(&circuit);
+                assert!(state.is_valid(), "invalid state for {circuit:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_stabiliser_states() {
+        // Dependent generators
+        let state = StabiliserState::<2>::from_strs(["XX", "XX"]);
+        assert!(!state.is_valid());
+        // Generators XI and ZI anticommute
+        let state = StabiliserState::<2> {
+            x_stabs: [0b01, 0b00],
+            z_stabs: [0b10, 0b00],
+            phases: 0,
+        };
+        assert!(!state.is_valid());
+    }
+}
@@ -0,0 +1,175 @@
+//! Polynomial-time synthesis of CX circuits.
+
+use crate::{
+    cx_circuit::{CXCircuit, CXCircuit16},
+    Topology, CX,
+};
+
+/// The number of qubits of the synthesised circuits.
+const N_QUBITS: usize = 16;
+
+/// The number of columns per section in [`pmh_synthesis`], about half the
+/// logarithm of the number of qubits.
+const SECTION_SIZE: usize = 2;
+
+/// Synthesise `circuit` with the Patel-Markov-Hayes algorithm.
+///
+/// The circuit matrix is reduced to the identity by a Gaussian elimination
+/// that first cancels repeated row patterns within sections of
+/// two columns, which on `n` qubits uses `O(n² / log n)` CXs.
+/// See Patel, Markov and Hayes, "Optimal synthesis of linear reversible
+/// circuits", 2008.
+///
+/// If `topology` is given, every CX between qubits that are not adjacent is
+/// replaced by `4(d - 1)` CXs along a shortest path of length `d`. Paths only
+/// use the first 16 qubits of the topology. Returns `None` if the
+/// elimination requires a CX between qubits that are not connected there.
+pub fn pmh_synthesis(circuit: CXCircuit16, topology: Option<&Topology>) -> Option<Vec<CX>> {
+    let mut rows: [u16; N_QUBITS] = std::array::from_fn(|i| circuit.row(i));
+    // The row operations reducing the matrix to upper triangular form, and
+    // then its transpose to the identity
+    let lower = lower_triangular_synthesis(&mut rows);
+    let transposed = CXCircuit16::from_row_bits(&rows.map(u64::from)).transpose();
+    rows = std::array::from_fn(|i| transposed.row(i));
+    let upper = lower_triangular_synthesis(&mut rows);
+
+    // Row operations are CXs applied after the circuit and CXs are
+    // self-inverse. The operations on the transpose are CXs applied before
+    // the circuit, with control and target exchanged.
+    let cxs = upper
+        .into_iter()
+        .map(|(ctrl, tgt)| (tgt, ctrl))
+        .chain(lower.into_iter().rev());
+    match topology {
+        Some(topology) => {
+            // Restrict routing to the qubits of the circuit
+            let edges = topology.edges().into_iter();
+            let edges: Vec<_> = edges.filter(|&(a, b)| a.max(b) < N_QUBITS).collect();
+            let topology = Topology::from_edge_list(N_QUBITS, &edges);
+            let paths: Option<Vec<_>> = cxs
+                .map(|(ctrl, tgt)| route_cx(ctrl, tgt, &topology))
+                .collect();
+            paths.map(|paths| paths.concat())
+        }
+        None => Some(cxs.map(CX::from).collect()),
+    }
+}
+
+/// Reduce `rows` to an upper triangular matrix.
+///
+/// Returns the row operations used, as `(ctrl, tgt)` pairs adding row `ctrl`
+/// to row `tgt`.
+fn lower_triangular_synthesis(rows: &mut [u16; N_QUBITS]) -> Vec<(usize, usize)> {
+    let mut ops = Vec::new();
+    let mut add_row = |rows: &mut [u16; N_QUBITS], ctrl: usize, tgt: usize| {
+        rows[tgt] ^= rows[ctrl];
+        ops.push((ctrl, tgt));
+    };
+
+    for start in (0..N_QUBITS).step_by(SECTION_SIZE) {
+        let cols = start..(start + SECTION_SIZE).min(N_QUBITS);
+        let section_mask = cols.clone().fold(0u16, |mask, col| mask | (1 << col));
+
+        // Cancel the rows whose pattern within the section has been seen
+        let mut first_with_pattern = [None; 1 << SECTION_SIZE];
+        for row in start..N_QUBITS {
+            let pattern = ((rows[row] & section_mask) >> start) as usize;
+            if pattern == 0 {
+                continue;
+            }
+            match first_with_pattern[pattern] {
+                Some(first) => add_row(rows, first, row),
+                None => first_with_pattern[pattern] = Some(row),
+            }
+        }
+
+        for col in cols {
+            let mut has_pivot = rows[col] & (1 << col) != 0;
+            for row in col + 1..N_QUBITS {
+                if rows[row] & (1 << col) == 0 {
+                    continue;
+                }
+                if !has_pivot {
+                    add_row(rows, row, col);
+                    has_pivot = true;
+                }
+                add_row(rows, col, row);
+            }
+        }
+    }
+    ops
+}
+
+/// CXs implementing `CX(ctrl, tgt)` along a shortest path in `topology`, if
+/// the qubits are connected.
+fn route_cx(ctrl: usize, tgt: usize, topology: &Topology) -> Option<Vec<CX>> {
+    let path = topology.shortest_path(ctrl, tgt)?;
+    let k = path.len() - 1;
+    let step = |i: usize| CX::from((path[i], path[i + 1]));
+    if k == 1 {
+        return Some(vec![step(0)]);
+    }
+    // Add `ctrl` to every qubit of the path, and then undo it on all but
+    // `tgt`
+    let mut cxs = Vec::with_capacity(4 * (k - 1));
+    cxs.extend((1..k).rev().map(step));
+    cxs.extend((0..k).map(step));
+    cxs.extend((1..k - 1).rev().map(step));
+    cxs.extend((0..k - 1).map(step));
+    Some(cxs)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, RngExt, SeedableRng};
+
+    use super::*;
+    use crate::{cx_circuit::CXCircuit, CXList};
+
+    fn random_circuit(rng: &mut StdRng, n_qubits: usize, n_cxs: usize) -> CXCircuit16 {
+        CXCircuit16::from_cxs((0..n_cxs).map(|_| {
+            let ctrl = rng.random_range(0..n_qubits);
+            (ctrl, (ctrl + rng.random_range(1..n_qubits)) % n_qubits)
+        }))
+    }
+
+    #[test]
+    fn test_pmh_synthesis() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..20 {
+            let circuit = random_circuit(&mut rng, 16, 200);
+            let cxs = pmh_synthesis(circuit, None).unwrap();
+            assert_eq!(CXList::from(cxs).to_cx_circuit(), circuit);
+        }
+        assert_eq!(pmh_synthesis(CXCircuit16::new(), None), Some(vec![]));
+    }
+
+    #[test]
+    fn test_pmh_synthesis_on_topology() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for topology in [
+            Topology::linear(16),
+            Topology::grid(4, 4),
+            Topology::ring(7),
+            Topology::heavy_hex(27).unwrap(),
+        ] {
+            let moves = topology.to_cx_moves();
+            for _ in 0..10 {
+                let n_qubits = topology.n_qubits().min(16);
+                let circuit = random_circuit(&mut rng, n_qubits, 50);
+                let cxs = pmh_synthesis(circuit, Some(&topology)).unwrap();
+                assert!(cxs.iter().all(|cx| moves.contains(cx)));
+                assert_eq!(CXList::from(cxs).to_cx_circuit(), circuit);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pmh_synthesis_disconnected() {
+        let topology = Topology::from_edge_list(4, &[(0, 1), (2, 3)]);
+        let circuit = CXCircuit16::from_cxs([(0, 1), (2, 3)]);
+        assert!(pmh_synthesis(circuit, Some(&topology)).is_some());
+        let circuit = CXCircuit16::from_cxs([(1, 2)]);
+        assert_eq!(pmh_synthesis(circuit, Some(&topology)), None);
+    }
+}
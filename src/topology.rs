@@ -0,0 +1,478 @@
+//! Qubit connectivity of quantum hardware.
+
+use std::collections::VecDeque;
+
+use fxhash::FxHashSet;
+
+use crate::{
+    bfs::topology_automorphisms,
+    cx_circuit::CXCircuit16,
+    upper_bound::{eliminate, EliminationError},
+    CX,
+};
+
+/// The number of qubits of the built-in topologies.
+const N_QUBITS: usize = 16;
+
+/// The coupling map of IBM's 27-qubit Falcon processors.
+const FALCON_EDGES: [(usize, usize); 28] = [
+    (0, 1),
+    (1, 2),
+    (1, 4),
+    (2, 3),
+    (3, 5),
+    (4, 7),
+    (5, 8),
+    (6, 7),
+    (7, 10),
+    (8, 9),
+    (8, 11),
+    (10, 12),
+    (11, 14),
+    (12, 13),
+    (12, 15),
+    (13, 14),
+    (14, 16),
+    (15, 18),
+    (16, 19),
+    (17, 18),
+    (18, 21),
+    (19, 20),
+    (19, 22),
+    (21, 23),
+    (22, 25),
+    (23, 24),
+    (24, 25),
+    (25, 26),
+];
+
+/// The qubit connectivity of a device, as an adjacency list.
+///
+/// Interactions are symmetric: CX gates are allowed in both directions on
+/// every edge.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Topology {
+    /// The sorted neighbours of each qubit
+    adjacency: Vec<Vec<usize>>,
+}
+
+impl Topology {
+    /// A topology on `n` qubits with the given edges.
+    ///
+    /// Repeated edges are ignored. Panics if an edge is a self-loop or acts
+    /// on a qubit outside of `0..n`.
+    pub fn from_edge_list(n: usize, edges: &[(usize, usize)]) -> Self {
+        let mut adjacency = vec![Vec::new(); n];
+        for &(a, b) in edges {
+            assert!(a < n && b < n, "edge ({a}, {b}) is out of range");
+            assert_ne!(a, b, "self-loops are not allowed");
+            adjacency[a].push(b);
+            adjacency[b].push(a);
+        }
+        for neighbours in &mut adjacency {
+            neighbours.sort_unstable();
+            neighbours.dedup();
+        }
+        Self { adjacency }
+    }
+
+    /// Every pair of qubits interacts.
+    pub fn all_to_all(n: usize) -> Self {
+        let edges: Vec<_> = (0..n)
+            .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+            .collect();
+        Self::from_edge_list(n, &edges)
+    }
+
+    /// Qubits on a line, each interacting with the next.
+    pub fn linear(n: usize) -> Self {
+        let edges: Vec<_> = (1..n).map(|i| (i - 1, i)).collect();
+        Self::from_edge_list(n, &edges)
+    }
+
+    /// A line of qubits whose two ends also interact.
+    pub fn ring(n: usize) -> Self {
+        let mut edges: Vec<_> = (1..n).map(|i| (i - 1, i)).collect();
+        if n > 2 {
+            edges.push((n - 1, 0));
+        }
+        Self::from_edge_list(n, &edges)
+    }
+
+    /// A `rows` x `cols` grid, with qubits numbered row by row.
+    pub fn grid(rows: usize, cols: usize) -> Self {
+        let n = rows * cols;
+        let horizontal = (0..n).filter(|i| i % cols != cols - 1).map(|i| (i, i + 1));
+        let vertical = (0..n.saturating_sub(cols)).map(|i| (i, i + cols));
+        let edges: Vec<_> = horizontal.chain(vertical).collect();
+        Self::from_edge_list(n, &edges)
+    }
+
+    /// The heavy-hex coupling map of IBM Quantum processors with `n_qubits`
+    /// qubits, numbered as by IBM.
+    ///
+    /// Supports the 27-qubit Falcon, 65-qubit Hummingbird and 127-qubit
+    /// Eagle processors, and returns `None` for other sizes.
+    pub fn heavy_hex(n_qubits: usize) -> Option<Self> {
+        match n_qubits {
+            27 => Some(Self::from_edge_list(27, &FALCON_EDGES)),
+            65 => Some(Self::heavy_hex_rows(&[
+                (0, 9),
+                (0, 10),
+                (0, 10),
+                (0, 10),
+                (1, 10),
+            ])),
+            127 => Some(Self::heavy_hex_rows(&[
+                (0, 13),
+                (0, 14),
+                (0, 14),
+                (0, 14),
+                (0, 14),
+                (0, 14),
+                (1, 14),
+            ])),
+            _ => None,
+        }
+    }
+
+    /// The coupling map of the IBM Quantum backend or processor family
+    /// `name`, e.g. `ibm_sherbrooke` or `ibm_eagle`.
+    ///
+    /// Returns `None` for unknown backends, and for backends whose coupling
+    /// map is not a supported [`Topology::heavy_hex`].
+    pub fn from_ibm_backend_name(name: &str) -> Option<Self> {
+        let n_qubits = match name {
+            "ibm_falcon" | "ibm_algiers" | "ibm_auckland" | "ibm_cairo" | "ibm_hanoi"
+            | "ibm_kolkata" | "ibm_mumbai" | "ibm_peekskill" | "ibmq_montreal" => 27,
+            "ibm_hummingbird" | "ibm_ithaca" | "ibmq_brooklyn" | "ibmq_manhattan" => 65,
+            "ibm_eagle" | "ibm_brisbane" | "ibm_cusco" | "ibm_kyiv" | "ibm_kyoto" | "ibm_nazca"
+            | "ibm_osaka" | "ibm_quebec" | "ibm_sherbrooke" | "ibm_washington" => 127,
+            _ => return None,
+        };
+        Self::heavy_hex(n_qubits)
+    }
+
+    /// A heavy-hex lattice with rows of qubits spanning the columns `rows`,
+    /// joined by a bridge qubit every 4 columns.
+    ///
+    /// Bridges start at column 0 below even rows, and at column 2 below odd
+    /// rows. Qubits are numbered row by row, each row followed by the
+    /// bridges below it.
+    fn heavy_hex_rows(rows: &[(usize, usize)]) -> Self {
+        let mut edges = Vec::new();
+        let mut n = 0;
+        // The bridges from the previous row, with their column
+        let mut bridges = Vec::new();
+        for (i, &(first_col, last_col)) in rows.iter().enumerate() {
+            let row_start = n;
+            let qubit_at = |col: usize| row_start + col - first_col;
+            n += last_col - first_col + 1;
+            edges.extend((row_start + 1..n).map(|qb| (qb - 1, qb)));
+            for (bridge, col) in bridges.drain(..) {
+                edges.push((bridge, qubit_at(col)));
+            }
+            if i + 1 < rows.len() {
+                for col in (2 * (i % 2)..=last_col).step_by(4) {
+                    edges.push((qubit_at(col), n));
+                    bridges.push((n, col));
+                    n += 1;
+                }
+            }
+        }
+        Self::from_edge_list(n, &edges)
+    }
+
+    /// Parse a topology from its name and optional size, e.g. `linear:8`,
+    /// `ring`, `grid:3x5` or `all-to-all:12`.
+    ///
+    /// `line` is a synonym for `linear` and `all_to_all` for `all-to-all`.
+    /// Without a size, topologies have 16 qubits and grids are 4x4. Returns
+    /// `None` for unknown names or invalid sizes.
+    pub fn from_spec(spec: &str) -> Option<Self> {
+        let (name, size) = match spec.split_once(':') {
+            Some((name, size)) => (name, Some(size)),
+            None => (spec, None),
+        };
+        let n = || size.map_or(Some(N_QUBITS), |size| size.parse().ok());
+        let topology = match name {
+            "line" | "linear" => Self::linear(n()?),
+            "ring" => Self::ring(n()?),
+            "all-to-all" | "all_to_all" => Self::all_to_all(n()?),
+            "grid" => {
+                let (rows, cols) = match size {
+                    Some(size) => {
+                        let (rows, cols) = size.split_once('x')?;
+                        (rows.parse().ok()?, cols.parse().ok()?)
+                    }
+                    None => (4, 4),
+                };
+                Self::grid(rows, cols)
+            }
+            _ => return None,
+        };
+        Some(topology)
+    }
+
+    /// The number of qubits.
+    pub fn n_qubits(&self) -> usize {
+        self.adjacency.len()
+    }
+
+    /// The interacting qubit pairs, each listed once with the smaller qubit
+    /// first.
+    pub fn edges(&self) -> Vec<(usize, usize)> {
+        self.adjacency
+            .iter()
+            .enumerate()
+            .flat_map(|(a, neighbours)| {
+                neighbours
+                    .iter()
+                    .filter(move |&&b| a < b)
+                    .map(move |&b| (a, b))
+            })
+            .collect()
+    }
+
+    /// The CX moves allowed by the topology, in both directions on every
+    /// edge.
+    pub fn to_cx_moves(&self) -> Vec<CX> {
+        self.edges()
+            .into_iter()
+            .flat_map(|(a, b)| [(a, b), (b, a)])
+            .map(CX::from)
+            .collect()
+    }
+
+    /// The relabellings of the qubits that map edges to edges.
+    ///
+    /// Permutation `perm` maps qubit `i` to `perm[i]`. The group is computed
+    /// by [`topology_automorphisms`] and is therefore truncated to the
+    /// identity if it is too large to be useful to the BFS.
+    pub fn automorphism_group(&self) -> Vec<Vec<usize>> {
+        let n = self.n_qubits();
+        topology_automorphisms(&self.to_cx_moves())
+            .into_iter()
+            .map(|mut perm| {
+                // Qubits after the last edge are isolated and left in place
+                perm.extend(perm.len()..n);
+                perm
+            })
+            .collect()
+    }
+
+    /// Whether every qubit can reach every other qubit.
+    pub fn is_connected(&self) -> bool {
+        self.distances_from(0).iter().all(Option::is_some)
+    }
+
+    /// The largest distance between two qubits.
+    ///
+    /// Panics if the topology is not connected.
+    pub fn diameter(&self) -> usize {
+        (0..self.n_qubits())
+            .flat_map(|qb| self.distances_from(qb))
+            .map(|dist| dist.expect("topology is not connected"))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The qubits on a shortest path from `source` to `target`, both
+    /// included, if there is one.
+    pub fn shortest_path(&self, source: usize, target: usize) -> Option<Vec<usize>> {
+        let n = self.n_qubits();
+        if source >= n || target >= n {
+            return None;
+        }
+        // Search backwards from `target`, so that following the parents
+        // from `source` gives the path in order
+        let mut parents = vec![None; n];
+        parents[target] = Some(target);
+        let mut queue = VecDeque::from([target]);
+        while let Some(qb) = queue.pop_front() {
+            for &neighbour in &self.adjacency[qb] {
+                if parents[neighbour].is_none() {
+                    parents[neighbour] = Some(qb);
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+        let mut path = vec![source];
+        while *path.last().unwrap() != target {
+            path.push(parents[*path.last().unwrap()]?);
+        }
+        Some(path)
+    }
+
+    /// The distance from `source` to every qubit, if it can be reached.
+    fn distances_from(&self, source: usize) -> Vec<Option<usize>> {
+        let mut distances = vec![None; self.n_qubits()];
+        let Some(dist) = distances.get_mut(source) else {
+            return distances;
+        };
+        *dist = Some(0);
+        let mut queue = VecDeque::from([source]);
+        while let Some(qb) = queue.pop_front() {
+            let next_dist = distances[qb].map(|dist| dist + 1);
+            for &neighbour in &self.adjacency[qb] {
+                if distances[neighbour].is_none() {
+                    distances[neighbour] = next_dist;
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+        distances
+    }
+}
+
+/// The qubit pairs that can interact in a built-in topology.
+///
+/// See [`Topology::from_spec`] for the supported names. Every pair is listed
+/// once; CX gates are allowed in both directions. Returns `None` for unknown
+/// names.
+pub fn builtin_topology(name: &str) -> Option<Vec<(usize, usize)>> {
+    Topology::from_spec(name).map(|topology| topology.edges())
+}
+
+/// Whether `circuit` can be implemented with the CXs in `topology`.
+///
+/// See [`check_implementable`].
+pub fn is_implementable(circuit: &CXCircuit16, topology: &[CX]) -> bool {
+    check_implementable(circuit, topology).is_ok()
+}
+
+/// Check that `circuit` can be implemented with the CXs in `topology`.
+///
+/// Runs a Gauss-Jordan elimination that only uses row operations of allowed
+/// CXs, and returns the first interaction it needs that is not in
+/// `topology`. The elimination is greedy, so circuits that can only be
+/// implemented by routing through other qubits are rejected.
+pub fn check_implementable(circuit: &CXCircuit16, topology: &[CX]) -> Result<(), EliminationError> {
+    let allowed_moves = FxHashSet::from_iter(topology.iter().copied());
+    eliminate(circuit, &allowed_moves).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cx_circuit::CXCircuit;
+
+    #[test]
+    fn test_builtin_topology() {
+        assert_eq!(builtin_topology("line").unwrap().len(), 15);
+        assert_eq!(builtin_topology("ring").unwrap().len(), 16);
+        assert_eq!(builtin_topology("grid").unwrap().len(), 24);
+        assert_eq!(builtin_topology("all-to-all").unwrap().len(), 120);
+        assert_eq!(builtin_topology("torus"), None);
+        assert_eq!(builtin_topology("line:8").unwrap().len(), 7);
+        assert_eq!(builtin_topology("grid:2x3").unwrap().len(), 7);
+        assert_eq!(builtin_topology("grid:2"), None);
+    }
+
+    #[test]
+    fn test_topology() {
+        let all_to_all = Topology::all_to_all(16);
+        let moves = all_to_all.to_cx_moves();
+        assert_eq!(moves.len(), 240);
+        let expected: FxHashSet<_> = (0..16)
+            .flat_map(|a| (0..16).map(move |b| (a, b)))
+            .filter(|(a, b)| a != b)
+            .map(CX::from)
+            .collect();
+        assert_eq!(FxHashSet::from_iter(moves), expected);
+        assert_eq!(all_to_all.diameter(), 1);
+
+        assert_eq!(Topology::linear(16).diameter(), 15);
+        assert_eq!(Topology::ring(16).diameter(), 8);
+        assert_eq!(Topology::grid(4, 4).diameter(), 6);
+        assert_eq!(Topology::from_spec("linear:16"), Some(Topology::linear(16)));
+
+        let disconnected = Topology::from_edge_list(4, &[(0, 1), (2, 3)]);
+        assert!(!disconnected.is_connected());
+        assert!(Topology::ring(5).is_connected());
+    }
+
+    #[test]
+    fn test_automorphism_group() {
+        let ring = Topology::ring(5);
+        let group = ring.automorphism_group();
+        // The rotations and reflections of the ring
+        assert_eq!(group.len(), 10);
+        let edges = FxHashSet::from_iter(ring.edges());
+        for perm in &group {
+            for &(a, b) in &edges {
+                let (a, b) = (perm[a], perm[b]);
+                assert!(edges.contains(&(a.min(b), a.max(b))));
+            }
+        }
+
+        let with_isolated = Topology::from_edge_list(4, &[(0, 1)]);
+        assert_eq!(
+            with_isolated.automorphism_group(),
+            vec![vec![0, 1, 2, 3], vec![1, 0, 2, 3]]
+        );
+    }
+
+    #[test]
+    fn test_shortest_path() {
+        let ring = Topology::ring(6);
+        assert_eq!(ring.shortest_path(1, 4).unwrap().len(), 4);
+        assert_eq!(ring.shortest_path(0, 5), Some(vec![0, 5]));
+        assert_eq!(ring.shortest_path(2, 2), Some(vec![2]));
+        let disconnected = Topology::from_edge_list(3, &[(0, 1)]);
+        assert_eq!(disconnected.shortest_path(0, 2), None);
+        assert_eq!(disconnected.shortest_path(0, 3), None);
+    }
+
+    #[test]
+    fn test_heavy_hex() {
+        for (n_qubits, n_edges) in [(27, 28), (65, 72), (127, 144)] {
+            let topology = Topology::heavy_hex(n_qubits).unwrap();
+            assert_eq!(topology.n_qubits(), n_qubits);
+            assert_eq!(topology.edges().len(), n_edges);
+            assert!(topology.is_connected());
+            assert!(topology.adjacency.iter().all(|nbs| nbs.len() <= 3));
+        }
+        assert_eq!(Topology::heavy_hex(16), None);
+
+        let eagle = Topology::heavy_hex(127).unwrap();
+        let edges = eagle.edges();
+        // The bridges between the first two rows, and the last qubit
+        for edge in [
+            (0, 14),
+            (14, 18),
+            (12, 17),
+            (17, 30),
+            (108, 112),
+            (112, 126),
+        ] {
+            assert!(edges.contains(&edge), "{edge:?}");
+        }
+        assert_eq!(
+            Topology::from_ibm_backend_name("ibm_sherbrooke"),
+            Some(eagle)
+        );
+        assert_eq!(
+            Topology::from_ibm_backend_name("ibm_kolkata"),
+            Topology::heavy_hex(27)
+        );
+        assert_eq!(Topology::from_ibm_backend_name("ibm_torino"), None);
+    }
+
+    #[test]
+    fn test_is_implementable() {
+        let line: Vec<CX> = builtin_topology("line")
+            .unwrap()
+            .into_iter()
+            .flat_map(|(a, b)| [(a, b), (b, a)])
+            .map(CX::from)
+            .collect();
+        let circuit = CXCircuit16::from_cxs([(2, 3), (1, 2), (0, 1)]);
+        assert!(is_implementable(&circuit, &line));
+        let circuit = CXCircuit16::from_cxs([(0, 2)]);
+        assert_eq!(
+            check_implementable(&circuit, &line),
+            Err(EliminationError::Disallowed(CX { ctrl: 0, tgt: 2 }))
+        );
+    }
+}
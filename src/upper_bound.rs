@@ -0,0 +1,117 @@
+//! Polynomial-time upper bounds on the number of CX gates required.
+
+use std::fmt;
+
+use fxhash::FxHashSet;
+
+use crate::{cx_circuit::CXCircuit16, CX};
+
+/// Synthesise a circuit mapping `source` to `target` by Gaussian elimination.
+///
+/// Both circuits are reduced to the identity using only `allowed_moves`, and
+/// the two reductions are then composed. The result is in general far from
+/// optimal, but gives an upper bound on the optimal CX count.
+///
+/// Returns `None` if the elimination requires a CX that is not allowed.
+pub fn synthesize_by_elimination(
+    source: &CXCircuit16,
+    target: &CXCircuit16,
+    allowed_moves: &FxHashSet<CX>,
+) -> Option<Vec<CX>> {
+    let mut solution = eliminate(source, allowed_moves).ok()?;
+    // CX gates are self-inverse, so undoing the reduction of `target` is
+    // applying the same gates in reverse order
+    let target_elimination = eliminate(target, allowed_moves).ok()?;
+    solution.extend(target_elimination.into_iter().rev());
+    Some(solution)
+}
+
+/// The reason a Gauss-Jordan elimination failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EliminationError {
+    /// The matrix is not invertible
+    Singular,
+    /// The elimination requires a CX that is not allowed
+    Disallowed(CX),
+}
+
+impl fmt::Display for EliminationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Singular => write!(f, "circuit matrix is not invertible"),
+            Self::Disallowed(cx) => write!(f, "elimination requires {cx:?}, which is not allowed"),
+        }
+    }
+}
+
+impl std::error::Error for EliminationError {}
+
+/// Reduce `circuit` to the identity by Gauss-Jordan elimination.
+///
+/// Returns the CX gates that map `circuit` to the identity.
+pub(crate) fn eliminate(
+    circuit: &CXCircuit16,
+    allowed_moves: &FxHashSet<CX>,
+) -> Result<Vec<CX>, EliminationError> {
+    let mut rows: [u16; 16] = std::array::from_fn(|i| circuit.row(i));
+    let mut cxs = Vec::new();
+    let mut add_row = |rows: &mut [u16; 16], ctrl: usize, tgt: usize| {
+        let cx = CX::from((ctrl, tgt));
+        if !allowed_moves.contains(&cx) {
+            return Err(EliminationError::Disallowed(cx));
+        }
+        rows[tgt] ^= rows[ctrl];
+        cxs.push(cx);
+        Ok(())
+    };
+
+    for col in 0..16 {
+        if rows[col] & (1 << col) == 0 {
+            // Find a pivot among the rows that have not been reduced yet
+            let pivot = (col + 1..16)
+                .find(|&row| rows[row] & (1 << col) != 0)
+                .ok_or(EliminationError::Singular)?;
+            add_row(&mut rows, pivot, col)?;
+        }
+        for row in 0..16 {
+            if row != col && rows[row] & (1 << col) != 0 {
+                add_row(&mut rows, col, row)?;
+            }
+        }
+    }
+    Ok(cxs)
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::iproduct;
+
+    use super::*;
+    use crate::{a_star::AStarValue, cx_circuit::CXCircuit};
+
+    #[test]
+    fn test_synthesize_by_elimination() {
+        let all_to_all = iproduct!(0..16, 0..16)
+            .filter(|(a, b)| a != b)
+            .map(CX::from)
+            .collect();
+        let source = CXCircuit16::from_cxs([(0, 3), (4, 2), (3, 4)]);
+        let target = CXCircuit16::from_cxs([(1, 2), (2, 0), (0, 1), (5, 3)]);
+
+        let solution = synthesize_by_elimination(&source, &target, &all_to_all).unwrap();
+        let result = solution
+            .iter()
+            .fold(source, |circ, cx| circ.cx(cx.ctrl, cx.tgt));
+        assert_eq!(result, target);
+    }
+
+    #[test]
+    fn test_elimination_stalls() {
+        let moves = FxHashSet::from_iter([CX { ctrl: 0, tgt: 1 }]);
+        let target = CXCircuit16::from_cxs([(1, 0)]);
+        assert_eq!(
+            synthesize_by_elimination(&CXCircuit16::new(), &target, &moves),
+            None
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use std::{fs, path::PathBuf, process::Command};
+
+fn run_batch(output_dir: &str, extra_args: &[&str]) -> bool {
+    Command::new(env!("CARGO_BIN_EXE_fast-cx-circs"))
+        .args([
+            "--batch-dir",
+            "tests/data/batch",
+            "--output-dir",
+            output_dir,
+        ])
+        .args(["--moves", "linear:16"])
+        .args(extra_args)
+        .output()
+        .expect("Unable to run binary")
+        .status
+        .success()
+}
+
+#[test]
+fn batch_solve() {
+    let output_dir: PathBuf = [env!("CARGO_TARGET_TMPDIR"), "batch_solve"]
+        .iter()
+        .collect();
+    let _ = fs::remove_dir_all(&output_dir);
+    assert!(run_batch(output_dir.to_str().unwrap(), &[]));
+    for (name, cx_count) in [("single_cx", 1), ("two_cxs", 2), ("chain", 3)] {
+        let solution = fs::read_to_string(output_dir.join(name)).unwrap();
+        assert_eq!(solution.lines().count(), cx_count, "{name}");
+    }
+}
+
+#[test]
+fn batch_unsolved() {
+    let output_dir: PathBuf = [env!("CARGO_TARGET_TMPDIR"), "batch_unsolved"]
+        .iter()
+        .collect();
+    let _ = fs::remove_dir_all(&output_dir);
+    // The chain needs 3 CXs
+    assert!(!run_batch(
+        output_dir.to_str().unwrap(),
+        &["--algo", "ida", "--depth", "2"]
+    ));
+    assert!(output_dir.join("two_cxs").exists());
+    assert!(!output_dir.join("chain").exists());
+}
@@ -0,0 +1,36 @@
+"""Smoke test for the Python bindings.
+
+Run with `maturin develop && pytest tests/python`.
+"""
+
+from fast_cx_circs import CXCircuit16, a_star, mitm_bfs
+
+LINE = [(i, i + 1) for i in range(15)] + [(i + 1, i) for i in range(15)]
+
+
+def circuit(cxs):
+    circ = CXCircuit16()
+    for ctrl, tgt in cxs:
+        circ.add_cx(ctrl, tgt)
+    return circ
+
+
+def test_a_star():
+    target = circuit([(0, 1), (1, 2)])
+    solution = a_star(CXCircuit16(), target, LINE)
+    assert len(solution) == 2
+    assert circuit(solution) == target
+
+
+def test_mitm_bfs():
+    target = circuit([(0, 1), (1, 2)])
+    solution = mitm_bfs(CXCircuit16(), target, LINE, 2)
+    assert len(solution) == 2
+    assert circuit(solution) == target
+
+
+def test_circuit():
+    circ = circuit([(0, 1), (2, 1)])
+    assert circ.mult(CXCircuit16()) == circ
+    assert circ.transpose().transpose() == circ
+    assert circ != CXCircuit16()
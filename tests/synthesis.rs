@@ -0,0 +1,29 @@
+use fast_cx_circs::{a_star, mitm_bfs, AStarValue, CXCircuit, CXCircuit16, Topology};
+
+#[test]
+fn synthesise_on_linear_topology() {
+    let moves = Topology::linear(16).to_cx_moves();
+    let target = CXCircuit16::from_cxs([(0, 1), (2, 3), (1, 2)]);
+
+    let solution = a_star(CXCircuit16::new(), &target, moves.clone(), None, None).unwrap();
+    assert_eq!(solution.len(), 3);
+    let circuit = solution
+        .iter()
+        .fold(CXCircuit16::new(), |circ, cx| circ.cx(cx.ctrl, cx.tgt));
+    assert_eq!(circuit, target);
+
+    let move_circs: Vec<_> = moves
+        .iter()
+        .map(|cx| CXCircuit16::from_cxs([(cx.ctrl as usize, cx.tgt as usize)]))
+        .collect();
+    let move_inds = mitm_bfs(
+        CXCircuit16::new(),
+        target,
+        &move_circs,
+        3,
+        false,
+        vec![],
+        true,
+    );
+    assert_eq!(move_inds.unwrap().len(), 3);
+}